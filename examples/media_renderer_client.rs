@@ -37,8 +37,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ..Default::default()
         }),
         autoplay: true,
-        object_class: Some(ObjectClass::Video),
-        // ..Default::default()
+        object_class: Some(ObjectClass::from("object.item.videoItem")),
+        ..Default::default()
     };
 
     let media_url =
@@ -0,0 +1,711 @@
+//! Typed helpers for the `AVTransport` service of a [`Device`].
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    didl,
+    error::UpnpError,
+    parser::{
+        format_time, parse_current_transport_actions, parse_device_capabilities, parse_media_info,
+        parse_position_info, parse_transport_settings,
+    },
+    types::{
+        Device, DeviceCapabilities, MediaInfo, Metadata, ObjectClass, PositionInfo, SeekTarget,
+        Service, TransportSettings,
+    },
+};
+
+impl Device {
+    /// The `AVTransport` service of the device, or an error naming the device when it has
+    /// none.
+    pub fn av_transport(&self) -> Result<&Service, UpnpError> {
+        self.require_service("AVTransport")
+    }
+
+    /// Loads `uri` along with its DIDL-Lite `metadata`, which many renderers need to play
+    /// it or to display its title. The object class is derived from the MIME type of
+    /// `metadata.protocol_info`, and `metadata.url` is replaced by `uri`.
+    ///
+    /// Renderers that need DLNA parameters get
+    /// [`DEFAULT_DLNA_FEATURES`](crate::quirks::DEFAULT_DLNA_FEATURES) unless the
+    /// `protocol_info` has some.
+    pub async fn set_av_transport_uri(
+        &self,
+        uri: &str,
+        metadata: &Metadata,
+    ) -> Result<(), UpnpError> {
+        self.invoke_av_transport(
+            "SetAVTransportURI",
+            &[
+                ("CurrentURI", uri),
+                ("CurrentURIMetaData", &self.uri_metadata(uri, metadata)),
+            ],
+        )
+        .await
+    }
+
+    /// Preloads `uri` to play once the current one ends, which lets renderers move on
+    /// without a gap. Its `metadata` is sent like [`Self::set_av_transport_uri`] does.
+    ///
+    /// Fails with [`UpnpError::ActionNotSupported`] without contacting renderers that don't
+    /// advertise `SetNextAVTransportURI` or are known to mishandle it.
+    pub async fn set_next_av_transport_uri(
+        &self,
+        uri: &str,
+        metadata: &Metadata,
+    ) -> Result<(), UpnpError> {
+        self.require_next_uri()?;
+        self.invoke_av_transport(
+            "SetNextAVTransportURI",
+            &[
+                ("NextURI", uri),
+                ("NextURIMetaData", &self.uri_metadata(uri, metadata)),
+            ],
+        )
+        .await
+    }
+
+    /// Loads `uri` and preloads `next_uri` to follow it, the two first tracks of a queue.
+    /// Once the renderer moved on to `next_uri`, the following track is queued with
+    /// [`Self::set_next_av_transport_uri`].
+    ///
+    /// Fails like [`Self::set_next_av_transport_uri`] before loading anything when the
+    /// renderer cannot preload.
+    pub async fn load_queue(
+        &self,
+        uri: &str,
+        metadata: &Metadata,
+        next_uri: &str,
+        next_metadata: &Metadata,
+    ) -> Result<(), UpnpError> {
+        self.require_next_uri()?;
+        self.set_av_transport_uri(uri, metadata).await?;
+        self.set_next_av_transport_uri(next_uri, next_metadata)
+            .await
+    }
+
+    /// Fails unless `SetNextAVTransportURI` is advertised and not known to be broken.
+    fn require_next_uri(&self) -> Result<(), UpnpError> {
+        let service = self.av_transport()?;
+        if self.quirks().no_next_uri || service.find_action("SetNextAVTransportURI").is_none() {
+            return Err(UpnpError::ActionNotSupported {
+                service_id: service.service_id.clone(),
+                action: "SetNextAVTransportURI".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The DIDL-Lite sent along with `uri`: `metadata`, with `uri` as its URL and the
+    /// object class derived from the MIME type of its `protocol_info`.
+    fn uri_metadata(&self, uri: &str, metadata: &Metadata) -> String {
+        let metadata = Metadata {
+            url: uri.to_string(),
+            protocol_info: self.quirks().protocol_info(&metadata.protocol_info),
+            ..metadata.clone()
+        };
+        let mime = metadata.protocol_info.split(':').nth(2).unwrap_or_default();
+        let object_class = if mime.starts_with("audio/") {
+            ObjectClass::from("object.item.audioItem.musicTrack")
+        } else if mime.starts_with("image/") {
+            ObjectClass::from("object.item.imageItem.photo")
+        } else {
+            ObjectClass::from("object.item.videoItem")
+        };
+        didl::metadata_didl(&metadata, &object_class)
+    }
+
+    /// Starts playback at `speed`, `"1"` being the normal speed.
+    pub async fn play(&self, speed: &str) -> Result<(), UpnpError> {
+        self.invoke_av_transport("Play", &[("Speed", speed)]).await
+    }
+
+    pub async fn pause(&self) -> Result<(), UpnpError> {
+        self.invoke_av_transport("Pause", &[]).await
+    }
+
+    pub async fn stop(&self) -> Result<(), UpnpError> {
+        self.invoke_av_transport("Stop", &[]).await
+    }
+
+    /// Skips to the next track of the current playlist.
+    pub async fn next(&self) -> Result<(), UpnpError> {
+        self.invoke_av_transport("Next", &[]).await
+    }
+
+    /// Goes back to the previous track of the current playlist.
+    pub async fn previous(&self) -> Result<(), UpnpError> {
+        self.invoke_av_transport("Previous", &[]).await
+    }
+
+    pub async fn seek(&self, target: SeekTarget) -> Result<(), UpnpError> {
+        let (unit, target) = match target {
+            SeekTarget::RelTime(position) => ("REL_TIME", format_time(position.as_secs())),
+            SeekTarget::TrackNr(track) => ("TRACK_NR", track.to_string()),
+        };
+        self.invoke_av_transport("Seek", &[("Unit", unit), ("Target", &target)])
+            .await
+    }
+
+    /// The current track, its duration, URI and metadata, and the position within it, in
+    /// a single round-trip.
+    pub async fn get_position_info(&self) -> Result<PositionInfo, UpnpError> {
+        let response = self
+            .av_transport()?
+            .invoke_with_quirks(self.quirks(), "GetPositionInfo", &[("InstanceID", "0")])
+            .await?;
+        parse_position_info(&response)
+    }
+
+    /// The loaded media: its number of tracks, duration, URI and metadata.
+    pub async fn get_media_info(&self) -> Result<MediaInfo, UpnpError> {
+        let response = self
+            .av_transport()?
+            .invoke_with_quirks(self.quirks(), "GetMediaInfo", &[("InstanceID", "0")])
+            .await?;
+        parse_media_info(&response)
+    }
+
+    /// The storage media the renderer can play from and record to, e.g. to offer
+    /// controls it supports only.
+    pub async fn get_device_capabilities(&self) -> Result<DeviceCapabilities, UpnpError> {
+        let response = self
+            .av_transport()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetDeviceCapabilities",
+                &[("InstanceID", "0")],
+            )
+            .await?;
+        parse_device_capabilities(&response)
+    }
+
+    /// The actions valid in the current state of the transport, such as `Play`, `Pause`
+    /// or `Seek`, e.g. to disable the controls the renderer would reject with a `701`
+    /// fault.
+    pub async fn get_current_transport_actions(&self) -> Result<Vec<String>, UpnpError> {
+        let response = self
+            .av_transport()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetCurrentTransportActions",
+                &[("InstanceID", "0")],
+            )
+            .await?;
+        parse_current_transport_actions(&response)
+    }
+
+    /// The current play mode and recording quality of the renderer.
+    pub async fn get_transport_settings(&self) -> Result<TransportSettings, UpnpError> {
+        let response = self
+            .av_transport()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetTransportSettings",
+                &[("InstanceID", "0")],
+            )
+            .await?;
+        parse_transport_settings(&response)
+    }
+
+    /// The play modes the renderer accepts, from the `allowedValueList` of its
+    /// `CurrentPlayMode` state variable. Empty when the description doesn't declare them.
+    pub fn allowed_play_modes(&self) -> Result<Vec<String>, UpnpError> {
+        let service = self.av_transport()?;
+        Ok(service
+            .argument_state_variable("SetPlayMode", "NewPlayMode")
+            .filter(|variable| !variable.allowed_values.is_empty())
+            .or_else(|| {
+                service
+                    .state_variables
+                    .iter()
+                    .find(|variable| variable.name == "CurrentPlayMode")
+            })
+            .map(|variable| variable.allowed_values.clone())
+            .unwrap_or_default())
+    }
+
+    /// Sets the play mode, such as `NORMAL`, `REPEAT_ALL` or `SHUFFLE`. Modes outside of
+    /// [`Self::allowed_play_modes`] are rejected without contacting the renderer.
+    pub async fn set_play_mode(&self, mode: &str) -> Result<(), UpnpError> {
+        let allowed = self.allowed_play_modes()?;
+        if !allowed.is_empty() && !allowed.iter().any(|allowed| allowed == mode) {
+            return Err(anyhow!(
+                "Play mode {mode} is not supported, expected one of {}",
+                allowed.join(", ")
+            )
+            .into());
+        }
+        self.invoke_av_transport("SetPlayMode", &[("NewPlayMode", mode)])
+            .await
+    }
+
+    /// Invokes `action` on instance 0 of the `AVTransport` service.
+    async fn invoke_av_transport(
+        &self,
+        action: &str,
+        args: &[(&str, &str)],
+    ) -> Result<(), UpnpError> {
+        let mut args = args.to_vec();
+        args.insert(0, ("InstanceID", "0"));
+        self.av_transport()?
+            .invoke_with_quirks(self.quirks(), action, &args)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        error::UpnpError,
+        parser::parse_location,
+        test_utils::{escape_didl, mock_device, soap_response, MockResponse, MockService},
+        types::{DeviceCapabilities, Metadata, SeekTarget, TransportSettings},
+    };
+
+    fn av_transport(actions: &[&'static str]) -> MockService {
+        let arguments = |action| match action {
+            "Play" => vec![("InstanceID", "in"), ("Speed", "in")],
+            "Seek" => vec![("InstanceID", "in"), ("Unit", "in"), ("Target", "in")],
+            "SetPlayMode" => vec![("InstanceID", "in"), ("NewPlayMode", "in")],
+            "SetAVTransportURI" => vec![
+                ("InstanceID", "in"),
+                ("CurrentURI", "in"),
+                ("CurrentURIMetaData", "in"),
+            ],
+            "SetNextAVTransportURI" => vec![
+                ("InstanceID", "in"),
+                ("NextURI", "in"),
+                ("NextURIMetaData", "in"),
+            ],
+            _ => vec![("InstanceID", "in")],
+        };
+        MockService {
+            name: "AVTransport",
+            actions: actions
+                .iter()
+                .map(|action| (*action, arguments(*action)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transport_controls() {
+        let server = mock_device(
+            vec![av_transport(&["Play", "Pause", "Stop", "Seek"])],
+            |_| MockResponse::ok(""),
+        )
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        device.play("1").await.unwrap();
+        device.pause().await.unwrap();
+        device.stop().await.unwrap();
+        device
+            .seek(SeekTarget::RelTime(Duration::from_secs(3725)))
+            .await
+            .unwrap();
+        device.seek(SeekTarget::TrackNr(3)).await.unwrap();
+
+        let requests = server.actions();
+        let actions = requests
+            .iter()
+            .map(|req| req.action().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, vec!["Play", "Pause", "Stop", "Seek", "Seek"]);
+        assert!(requests
+            .iter()
+            .all(|req| req.param("InstanceID").as_deref() == Some("0")));
+        assert_eq!(requests[0].param("Speed").as_deref(), Some("1"));
+        assert_eq!(requests[3].param("Unit").as_deref(), Some("REL_TIME"));
+        assert_eq!(requests[3].param("Target").as_deref(), Some("01:02:05"));
+        assert_eq!(requests[4].param("Unit").as_deref(), Some("TRACK_NR"));
+        assert_eq!(requests[4].param("Target").as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn test_track_navigation() {
+        let server = mock_device(vec![av_transport(&["Next", "Previous"])], |_| {
+            MockResponse::ok("")
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        device.next().await.unwrap();
+        device.previous().await.unwrap();
+
+        let requests = server.actions();
+        for (request, action) in requests.iter().zip(["Next", "Previous"]) {
+            assert_eq!(
+                request.headers.get("soapaction").map(String::as_str),
+                Some(format!("\"urn:schemas-upnp-org:service:AVTransport:1#{action}\"").as_str())
+            );
+            assert!(request.body.contains(&format!(
+                "<u:{action} xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\">"
+            )));
+            assert_eq!(request.param("InstanceID").as_deref(), Some("0"));
+        }
+
+        let server = mock_device(vec![av_transport(&["Play"])], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        for error in [
+            device.next().await.unwrap_err(),
+            device.previous().await.unwrap_err(),
+        ] {
+            assert!(matches!(error, UpnpError::ActionNotSupported { .. }));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_av_transport_uri_escapes_metadata() {
+        let server = mock_device(vec![av_transport(&["SetAVTransportURI"])], |_| {
+            MockResponse::ok("")
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let uri = "http://192.168.1.2:8200/track.mp3?id=1&format=mp3";
+        let metadata = Metadata {
+            title: "Rock & Roll".to_string(),
+            protocol_info: "http-get:*:audio/mpeg:*".to_string(),
+            ..Default::default()
+        };
+        device.set_av_transport_uri(uri, &metadata).await.unwrap();
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(
+            request.param("CurrentURI").as_deref(),
+            Some("http://192.168.1.2:8200/track.mp3?id=1&amp;format=mp3")
+        );
+        let didl = request.param("CurrentURIMetaData").unwrap();
+        assert!(didl.contains("&lt;dc:title&gt;Rock &amp;amp; Roll&lt;/dc:title&gt;"));
+        assert!(didl.contains("object.item.audioItem.musicTrack"));
+    }
+
+    #[tokio::test]
+    async fn test_load_queue() {
+        let server = mock_device(
+            vec![av_transport(&[
+                "SetAVTransportURI",
+                "SetNextAVTransportURI",
+            ])],
+            |_| MockResponse::ok(""),
+        )
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let track = |title: &str| Metadata {
+            title: title.to_string(),
+            protocol_info: "http-get:*:audio/flac:*".to_string(),
+            ..Default::default()
+        };
+        device
+            .load_queue(
+                "http://192.168.1.2:8200/1.flac",
+                &track("First"),
+                "http://192.168.1.2:8200/2.flac",
+                &track("Second"),
+            )
+            .await
+            .unwrap();
+
+        let requests = server.actions();
+        let actions = requests
+            .iter()
+            .map(|req| req.action().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["SetAVTransportURI", "SetNextAVTransportURI"]);
+
+        let current = &requests[0];
+        assert_eq!(
+            current.param("CurrentURI").as_deref(),
+            Some("http://192.168.1.2:8200/1.flac")
+        );
+        let didl = current.param("CurrentURIMetaData").unwrap();
+        assert!(didl.contains("&lt;dc:title&gt;First&lt;/dc:title&gt;"));
+        assert!(didl.contains("http://192.168.1.2:8200/1.flac"));
+        assert!(didl.contains("object.item.audioItem.musicTrack"));
+
+        let next = &requests[1];
+        assert_eq!(next.param("InstanceID").as_deref(), Some("0"));
+        assert_eq!(
+            next.param("NextURI").as_deref(),
+            Some("http://192.168.1.2:8200/2.flac")
+        );
+        let didl = next.param("NextURIMetaData").unwrap();
+        assert!(didl.contains("&lt;dc:title&gt;Second&lt;/dc:title&gt;"));
+        assert!(didl.contains("http://192.168.1.2:8200/2.flac"));
+        assert!(didl.contains("object.item.audioItem.musicTrack"));
+    }
+
+    #[tokio::test]
+    async fn test_load_queue_without_next_uri() {
+        let server = mock_device(vec![av_transport(&["SetAVTransportURI"])], |_| {
+            MockResponse::ok("")
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        let metadata = Metadata::default();
+
+        let error = device
+            .load_queue("http://a/1.mp3", &metadata, "http://a/2.mp3", &metadata)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            UpnpError::ActionNotSupported { action, .. } if action == "SetNextAVTransportURI"
+        ));
+        assert!(server.actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_action() {
+        let server = mock_device(vec![av_transport(&["Play", "Stop"])], |_| {
+            MockResponse::ok("")
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let error = device.pause().await.unwrap_err();
+        assert!(matches!(
+            error,
+            UpnpError::ActionNotSupported { action, .. } if action == "Pause"
+        ));
+        assert!(server.actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_position_info() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="1" parentID="0" restricted="1"><dc:title>Song</dc:title><upnp:artist>Artist</upnp:artist><res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.2/song.mp3</res></item></DIDL-Lite>"#;
+        let server = mock_device(vec![av_transport(&["GetPositionInfo"])], |_| {
+            soap_response(
+                "AVTransport",
+                "GetPositionInfo",
+                &format!(
+                    "<Track>2</Track>\
+                     <TrackDuration>0:03:25.000</TrackDuration>\
+                     <TrackMetaData>{}</TrackMetaData>\
+                     <TrackURI>http://192.168.1.2/song.mp3</TrackURI>\
+                     <RelTime>0:01:05</RelTime>\
+                     <AbsTime>0:04:10</AbsTime>\
+                     <RelCount>2147483647</RelCount>\
+                     <AbsCount>2147483647</AbsCount>",
+                    escape_didl(DIDL)
+                ),
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let info = device.get_position_info().await.unwrap();
+
+        assert_eq!(server.actions().len(), 1);
+        assert_eq!(info.track, 2);
+        assert_eq!(info.track_duration, Some(Duration::from_secs(205)));
+        assert_eq!(info.track_uri, "http://192.168.1.2/song.mp3");
+        assert_eq!(info.rel_time, Some(Duration::from_secs(65)));
+        assert_eq!(info.abs_time, Some(Duration::from_secs(250)));
+        assert_eq!(info.effective_position(), info.rel_time);
+        let metadata = info.track_metadata.unwrap();
+        assert_eq!(metadata.title, "Song");
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.url, "http://192.168.1.2/song.mp3");
+    }
+
+    #[tokio::test]
+    async fn test_position_info_falls_back_to_abs_time() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/"><item id="1" parentID="0" restricted="1"><dc:title>Radio</dc:title><res protocolInfo="http-get:*:audio/mpeg:*" duration="1:00:00">http://192.168.1.2/live.mp3</res></item></DIDL-Lite>"#;
+        let server = mock_device(vec![av_transport(&["GetPositionInfo"])], |_| {
+            soap_response(
+                "AVTransport",
+                "GetPositionInfo",
+                &format!(
+                    "<Track>1</Track>\
+                     <TrackDuration>NOT_IMPLEMENTED</TrackDuration>\
+                     <TrackMetaData>{}</TrackMetaData>\
+                     <TrackURI>http://192.168.1.2/live.mp3</TrackURI>\
+                     <RelTime>NOT_IMPLEMENTED</RelTime>\
+                     <AbsTime>0:12:34</AbsTime>",
+                    escape_didl(DIDL)
+                ),
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let info = device.get_position_info().await.unwrap();
+
+        assert_eq!(info.rel_time, None);
+        assert_eq!(info.track_duration, None);
+        assert_eq!(info.effective_position(), Some(Duration::from_secs(754)));
+        assert_eq!(info.effective_duration(), Some(Duration::from_secs(3600)));
+    }
+
+    #[tokio::test]
+    async fn test_get_media_info() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/"><item id="1" parentID="0" restricted="1"><dc:title>Playlist</dc:title><res protocolInfo="http-get:*:audio/x-mpegurl:*">http://192.168.1.2/list.m3u</res></item></DIDL-Lite>"#;
+        let server = mock_device(vec![av_transport(&["GetMediaInfo"])], |_| {
+            soap_response(
+                "AVTransport",
+                "GetMediaInfo",
+                &format!(
+                    "<NrTracks>12</NrTracks>\
+                     <MediaDuration>0:48:10</MediaDuration>\
+                     <CurrentURI>http://192.168.1.2/list.m3u</CurrentURI>\
+                     <CurrentURIMetaData>{}</CurrentURIMetaData>\
+                     <NextURI></NextURI>\
+                     <NextURIMetaData>NOT_IMPLEMENTED</NextURIMetaData>\
+                     <PlayMedium>NETWORK</PlayMedium>\
+                     <RecordMedium>NOT_IMPLEMENTED</RecordMedium>\
+                     <WriteStatus>NOT_IMPLEMENTED</WriteStatus>",
+                    escape_didl(DIDL)
+                ),
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let info = device.get_media_info().await.unwrap();
+
+        assert_eq!(server.actions()[0].action(), Some("GetMediaInfo"));
+        assert_eq!(info.nr_tracks, 12);
+        assert_eq!(info.media_duration, Some(Duration::from_secs(2890)));
+        assert_eq!(info.current_uri, "http://192.168.1.2/list.m3u");
+        assert_eq!(info.current_uri_metadata.unwrap().title, "Playlist");
+    }
+
+    #[tokio::test]
+    async fn test_get_device_capabilities_and_transport_settings() {
+        let server = mock_device(
+            vec![av_transport(&[
+                "GetDeviceCapabilities",
+                "GetTransportSettings",
+            ])],
+            |req| match req.action() {
+                Some("GetDeviceCapabilities") => soap_response(
+                    "AVTransport",
+                    "GetDeviceCapabilities",
+                    "<PlayMedia>NETWORK,HDD, USB</PlayMedia>\
+                     <RecMedia>NOT_IMPLEMENTED</RecMedia>\
+                     <RecQualityModes>NOT_IMPLEMENTED</RecQualityModes>",
+                ),
+                _ => soap_response(
+                    "AVTransport",
+                    "GetTransportSettings",
+                    "<PlayMode>REPEAT_ALL</PlayMode>\
+                     <RecQualityMode>NOT_IMPLEMENTED</RecQualityMode>",
+                ),
+            },
+        )
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            device.get_device_capabilities().await.unwrap(),
+            DeviceCapabilities {
+                play_media: vec!["NETWORK".into(), "HDD".into(), "USB".into()],
+                rec_media: vec![],
+                rec_quality_modes: vec![],
+            }
+        );
+        assert_eq!(
+            device.get_transport_settings().await.unwrap(),
+            TransportSettings {
+                play_mode: "REPEAT_ALL".to_string(),
+                rec_quality_mode: None,
+            }
+        );
+        let actions = server
+            .actions()
+            .iter()
+            .map(|req| req.action().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["GetDeviceCapabilities", "GetTransportSettings"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_current_transport_actions() {
+        let server = mock_device(vec![av_transport(&["GetCurrentTransportActions"])], |_| {
+            soap_response(
+                "AVTransport",
+                "GetCurrentTransportActions",
+                "<Actions>Play,Stop, Seek,Next,Previous,</Actions>",
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let actions = device.get_current_transport_actions().await.unwrap();
+        assert_eq!(actions, ["Play", "Stop", "Seek", "Next", "Previous"]);
+        assert!(!actions.iter().any(|action| action == "Pause"));
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("GetCurrentTransportActions"));
+        assert_eq!(request.param("InstanceID").as_deref(), Some("0"));
+    }
+
+    #[tokio::test]
+    async fn test_set_play_mode() {
+        let service = MockService {
+            state_table: r#"<stateVariable sendEvents="no">
+                    <name>CurrentPlayMode</name>
+                    <dataType>string</dataType>
+                    <defaultValue>NORMAL</defaultValue>
+                    <allowedValueList>
+                        <allowedValue>NORMAL</allowedValue>
+                        <allowedValue>REPEAT_ALL</allowedValue>
+                        <allowedValue>SHUFFLE</allowedValue>
+                    </allowedValueList>
+                </stateVariable>"#,
+            ..av_transport(&["SetPlayMode"])
+        };
+        let server = mock_device(vec![service], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            device.allowed_play_modes().unwrap(),
+            vec!["NORMAL", "REPEAT_ALL", "SHUFFLE"]
+        );
+        device.set_play_mode("REPEAT_ALL").await.unwrap();
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("SetPlayMode"));
+        assert_eq!(request.param("InstanceID").as_deref(), Some("0"));
+        assert_eq!(request.param("NewPlayMode").as_deref(), Some("REPEAT_ALL"));
+
+        let error = device.set_play_mode("RANDOM").await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("expected one of NORMAL, REPEAT_ALL, SHUFFLE"));
+        assert_eq!(server.actions().len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+//! Blocking wrappers of the async API, for callers without a runtime of their own such as
+//! CLI tools, scripts or FFI.
+//!
+//! Each call runs its async counterpart to completion on an internal single-threaded
+//! runtime. Like the async functions they wrap, they must not be called from within an
+//! async context, where blocking the thread panics.
+
+use std::{future::Future, ops::Deref, sync::LazyLock};
+
+use crate::{
+    error::UpnpError,
+    parser,
+    types::{self, BrowseFlag, BrowseResult, MediaInfo, Metadata, PositionInfo, SeekTarget},
+};
+
+static RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the runtime of the blocking API")
+});
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+/// Fetches and parses the description at `location`, like
+/// [`parser::parse_location`].
+pub fn parse_location(location: &str) -> Result<Device, UpnpError> {
+    block_on(parser::parse_location(location)).map(Device)
+}
+
+/// A [`types::Device`] whose `ContentDirectory`, `AVTransport` and `RenderingControl`
+/// helpers block until the device answers. It dereferences to the device, e.g. for its
+/// `friendly_name` or `services`.
+#[derive(Debug, Clone)]
+pub struct Device(types::Device);
+
+impl Device {
+    /// The wrapped device, e.g. to use its async helpers.
+    #[must_use]
+    pub fn into_inner(self) -> types::Device {
+        self.0
+    }
+
+    /// See [`types::Device::browse`].
+    pub fn browse(
+        &self,
+        object_id: &str,
+        flag: BrowseFlag,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        block_on(
+            self.0
+                .browse(object_id, flag, starting_index, requested_count),
+        )
+    }
+
+    /// See [`types::Device::search`].
+    pub fn search(
+        &self,
+        container_id: &str,
+        search_criteria: &str,
+        filter: &str,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        block_on(self.0.search(
+            container_id,
+            search_criteria,
+            filter,
+            starting_index,
+            requested_count,
+        ))
+    }
+
+    /// See [`types::Device::set_av_transport_uri`].
+    pub fn set_av_transport_uri(&self, uri: &str, metadata: &Metadata) -> Result<(), UpnpError> {
+        block_on(self.0.set_av_transport_uri(uri, metadata))
+    }
+
+    /// See [`types::Device::set_next_av_transport_uri`].
+    pub fn set_next_av_transport_uri(
+        &self,
+        uri: &str,
+        metadata: &Metadata,
+    ) -> Result<(), UpnpError> {
+        block_on(self.0.set_next_av_transport_uri(uri, metadata))
+    }
+
+    /// See [`types::Device::load_queue`].
+    pub fn load_queue(
+        &self,
+        uri: &str,
+        metadata: &Metadata,
+        next_uri: &str,
+        next_metadata: &Metadata,
+    ) -> Result<(), UpnpError> {
+        block_on(self.0.load_queue(uri, metadata, next_uri, next_metadata))
+    }
+
+    /// See [`types::Device::play`].
+    pub fn play(&self, speed: &str) -> Result<(), UpnpError> {
+        block_on(self.0.play(speed))
+    }
+
+    /// See [`types::Device::pause`].
+    pub fn pause(&self) -> Result<(), UpnpError> {
+        block_on(self.0.pause())
+    }
+
+    /// See [`types::Device::stop`].
+    pub fn stop(&self) -> Result<(), UpnpError> {
+        block_on(self.0.stop())
+    }
+
+    /// See [`types::Device::next`].
+    pub fn next(&self) -> Result<(), UpnpError> {
+        block_on(self.0.next())
+    }
+
+    /// See [`types::Device::previous`].
+    pub fn previous(&self) -> Result<(), UpnpError> {
+        block_on(self.0.previous())
+    }
+
+    /// See [`types::Device::seek`].
+    pub fn seek(&self, target: SeekTarget) -> Result<(), UpnpError> {
+        block_on(self.0.seek(target))
+    }
+
+    /// See [`types::Device::get_position_info`].
+    pub fn get_position_info(&self) -> Result<PositionInfo, UpnpError> {
+        block_on(self.0.get_position_info())
+    }
+
+    /// See [`types::Device::get_media_info`].
+    pub fn get_media_info(&self) -> Result<MediaInfo, UpnpError> {
+        block_on(self.0.get_media_info())
+    }
+
+    /// See [`types::Device::get_current_transport_actions`].
+    pub fn get_current_transport_actions(&self) -> Result<Vec<String>, UpnpError> {
+        block_on(self.0.get_current_transport_actions())
+    }
+
+    /// See [`types::Device::set_play_mode`].
+    pub fn set_play_mode(&self, mode: &str) -> Result<(), UpnpError> {
+        block_on(self.0.set_play_mode(mode))
+    }
+
+    /// See [`types::Device::get_volume`].
+    pub fn get_volume(&self) -> Result<u8, UpnpError> {
+        block_on(self.0.get_volume())
+    }
+
+    /// See [`types::Device::get_volume_and_mute`].
+    pub fn get_volume_and_mute(&self, channel: Option<&str>) -> Result<(u8, bool), UpnpError> {
+        block_on(self.0.get_volume_and_mute(channel))
+    }
+
+    /// See [`types::Device::set_volume`].
+    pub fn set_volume(&self, volume: u16) -> Result<u16, UpnpError> {
+        block_on(self.0.set_volume(volume))
+    }
+
+    /// See [`types::Device::get_mute`].
+    pub fn get_mute(&self, channel: Option<&str>) -> Result<bool, UpnpError> {
+        block_on(self.0.get_mute(channel))
+    }
+
+    /// See [`types::Device::set_mute`].
+    pub fn set_mute(&self, channel: Option<&str>, desired: bool) -> Result<(), UpnpError> {
+        block_on(self.0.set_mute(channel, desired))
+    }
+}
+
+impl Deref for Device {
+    type Target = types::Device;
+
+    fn deref(&self) -> &types::Device {
+        &self.0
+    }
+}
+
+impl From<types::Device> for Device {
+    fn from(device: types::Device) -> Self {
+        Self(device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::runtime::Runtime;
+
+    use crate::{
+        blocking::parse_location,
+        test_utils::{escape_didl, mock_device, soap_response, MockResponse, MockService},
+        types::{BrowseFlag, SeekTarget},
+    };
+
+    fn services() -> Vec<MockService> {
+        vec![
+            MockService {
+                name: "ContentDirectory",
+                actions: vec![(
+                    "Browse",
+                    vec![
+                        ("ObjectID", "in"),
+                        ("BrowseFlag", "in"),
+                        ("Filter", "in"),
+                        ("StartingIndex", "in"),
+                        ("RequestedCount", "in"),
+                        ("SortCriteria", "in"),
+                    ],
+                )],
+                ..Default::default()
+            },
+            MockService {
+                name: "AVTransport",
+                actions: vec![
+                    ("Play", vec![("InstanceID", "in"), ("Speed", "in")]),
+                    (
+                        "Seek",
+                        vec![("InstanceID", "in"), ("Unit", "in"), ("Target", "in")],
+                    ),
+                ],
+                ..Default::default()
+            },
+            MockService {
+                name: "RenderingControl",
+                actions: vec![("GetVolume", vec![("InstanceID", "in"), ("Channel", "in")])],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_blocking_wrappers() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="65" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://127.0.0.1:8200/MediaItems/65.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+        // The mock device runs on a runtime of its own, the test thread being synchronous.
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(mock_device(services(), |req| match req.action() {
+            Some("Browse") => soap_response(
+                "ContentDirectory",
+                "Browse",
+                &format!(
+                    "<Result>{}</Result><NumberReturned>1</NumberReturned><TotalMatches>1</TotalMatches>",
+                    escape_didl(DIDL)
+                ),
+            ),
+            Some("GetVolume") => soap_response(
+                "RenderingControl",
+                "GetVolume",
+                "<CurrentVolume>42</CurrentVolume>",
+            ),
+            _ => MockResponse::ok(""),
+        }));
+
+        let device = parse_location(&server.url("/description.xml")).unwrap();
+        assert_eq!(device.services.len(), 3);
+
+        let result = device.browse("0", BrowseFlag::default(), 0, 0).unwrap();
+        assert_eq!(result.items[0].title, "Song");
+
+        device.play("1").unwrap();
+        device
+            .seek(SeekTarget::RelTime(Duration::from_secs(90)))
+            .unwrap();
+        assert_eq!(device.get_volume().unwrap(), 42);
+
+        let actions = server
+            .actions()
+            .iter()
+            .map(|req| req.action().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["Browse", "Play", "Seek", "GetVolume"]);
+        assert_eq!(
+            server.actions()[2].param("Target").as_deref(),
+            Some("00:01:30")
+        );
+    }
+
+    #[test]
+    fn test_blocking_parse_location_error() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(mock_device(vec![], |_| MockResponse::ok("")));
+
+        assert!(parse_location(&server.url("/missing.xml")).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{discovery::SsdpResponse, error::UpnpError, parser::parse_location, types::Device};
+
+/// How long an entry lives when the SSDP answer carries no `max-age` directive, which is
+/// the minimum the `UPnP` Device Architecture recommends.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(30 * 60);
+
+/// Parsed devices keyed by USN (or location), each kept until the `max-age` its SSDP
+/// advertisement announced has elapsed, so rediscovering a device does not download its
+/// description and every SCPD again.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceCache {
+    entries: HashMap<String, (Device, Instant)>,
+}
+
+impl DeviceCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `device` under `key` until `max_age` has elapsed.
+    pub fn insert(&mut self, key: &str, device: Device, max_age: Duration) {
+        let deadline = Instant::now() + max_age;
+        self.entries.insert(key.to_string(), (device, deadline));
+    }
+
+    /// Returns the device stored under `key`, unless it has expired, in which case the entry
+    /// is dropped.
+    pub fn get(&mut self, key: &str) -> Option<Device> {
+        match self.entries.get(key) {
+            Some((device, deadline)) if Instant::now() < *deadline => Some(device.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Removes the entry stored under `key`, e.g. when the device sent `ssdp:byebye`.
+    pub fn invalidate(&mut self, key: &str) -> Option<Device> {
+        self.entries.remove(key).map(|(device, _)| device)
+    }
+
+    /// Returns the device behind an SSDP answer, parsing its description only if no fresh
+    /// copy is cached under its USN (or its location when the USN is empty).
+    pub async fn get_or_parse(&mut self, response: &SsdpResponse) -> Result<Device, UpnpError> {
+        let key = if response.usn.is_empty() {
+            response.location.as_str()
+        } else {
+            response.usn.as_str()
+        };
+        if let Some(device) = self.get(key) {
+            return Ok(device);
+        }
+
+        let device = parse_location(&response.location).await?;
+        let max_age = response.max_age.map_or(DEFAULT_MAX_AGE, |max_age| {
+            Duration::from_secs(max_age.into())
+        });
+        self.insert(key, device.clone(), max_age);
+        Ok(device)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        cache::DeviceCache,
+        discovery::SsdpResponse,
+        test_utils::{mock_device, MockResponse},
+        types::Device,
+    };
+
+    fn device(name: &str) -> Device {
+        Device {
+            friendly_name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fresh_entry_is_returned() {
+        let mut cache = DeviceCache::new();
+        cache.insert("uuid:1", device("Kitchen"), Duration::from_secs(60));
+        assert_eq!(cache.get("uuid:1").unwrap().friendly_name, "Kitchen");
+        assert!(cache.get("uuid:2").is_none());
+    }
+
+    #[test]
+    fn test_expired_entry_is_dropped() {
+        let mut cache = DeviceCache::new();
+        cache.insert("uuid:1", device("Kitchen"), Duration::ZERO);
+        assert!(cache.get("uuid:1").is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let mut cache = DeviceCache::new();
+        cache.insert("uuid:1", device("Kitchen"), Duration::from_secs(60));
+        assert_eq!(cache.invalidate("uuid:1").unwrap().friendly_name, "Kitchen");
+        assert!(cache.get("uuid:1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_or_parse_fetches_once() {
+        let server = mock_device(vec![], |_| MockResponse::ok("")).await;
+        let response = SsdpResponse {
+            location: server.url("/description.xml"),
+            usn: "uuid:00000000-0000-0000-0000-000000000001".to_string(),
+            max_age: Some(1800),
+            ..Default::default()
+        };
+
+        let mut cache = DeviceCache::new();
+        let first = cache.get_or_parse(&response).await.unwrap();
+        let second = cache.get_or_parse(&response).await.unwrap();
+        assert_eq!(first.friendly_name, second.friendly_name);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
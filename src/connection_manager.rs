@@ -0,0 +1,192 @@
+//! Typed helpers for the `ConnectionManager` service of a [`Device`].
+
+use anyhow::Result;
+
+use crate::{
+    error::UpnpError,
+    parser::{parse_connection_info, parse_current_connection_ids, parse_protocol_info},
+    types::{ConnectionInfo, Device, ProtocolInfo, Service},
+};
+
+impl Device {
+    /// The `ConnectionManager` service of the device, or an error naming the device when it
+    /// has none.
+    pub fn connection_manager(&self) -> Result<&Service, UpnpError> {
+        self.require_service("ConnectionManager")
+    }
+
+    /// The protocols the device can send (`source`) and receive (`sink`), which tell
+    /// whether a renderer can play a content format before it is loaded.
+    pub async fn get_protocol_info(&self) -> Result<ProtocolInfo, UpnpError> {
+        let response = self
+            .connection_manager()?
+            .invoke_with_quirks(self.quirks(), "GetProtocolInfo", &[])
+            .await?;
+        parse_protocol_info(&response)
+    }
+
+    /// The ids of the connections the device currently has, e.g. one per rendering.
+    pub async fn get_current_connection_ids(&self) -> Result<Vec<i32>, UpnpError> {
+        let response = self
+            .connection_manager()?
+            .invoke_with_quirks(self.quirks(), "GetCurrentConnectionIDs", &[])
+            .await?;
+        parse_current_connection_ids(&response)
+    }
+
+    /// The connection `connection_id`, including the `AVTransport` and `RenderingControl`
+    /// instances that control it.
+    pub async fn get_current_connection_info(
+        &self,
+        connection_id: i32,
+    ) -> Result<ConnectionInfo, UpnpError> {
+        let response = self
+            .connection_manager()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetCurrentConnectionInfo",
+                &[("ConnectionID", &connection_id.to_string())],
+            )
+            .await?;
+        parse_connection_info(&response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_location,
+        test_utils::{mock_device, soap_response, MockResponse, MockService},
+    };
+
+    #[tokio::test]
+    async fn test_get_protocol_info() {
+        let server = mock_device(
+            vec![MockService {
+                name: "ConnectionManager",
+                actions: vec![(
+                    "GetProtocolInfo",
+                    vec![("Source", "out"), ("Sink", "out")],
+                )],
+                ..Default::default()
+            }],
+            |_| {
+                soap_response(
+                    "ConnectionManager",
+                    "GetProtocolInfo",
+                    "<Source>http-get:*:audio/mpeg:*</Source>\
+                     <Sink>http-get:*:audio/mpeg:*,http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_BL_CIF15_AAC_520,bogus</Sink>",
+                )
+            },
+        )
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let protocol_info = device.get_protocol_info().await.unwrap();
+
+        assert_eq!(server.actions()[0].action(), Some("GetProtocolInfo"));
+        assert_eq!(protocol_info.source.len(), 1);
+        assert_eq!(protocol_info.source[0].content_format, "audio/mpeg");
+        assert_eq!(protocol_info.sink.len(), 2);
+        assert_eq!(protocol_info.sink[1].content_format, "video/mp4");
+        assert_eq!(
+            protocol_info.sink[1].additional_info,
+            "DLNA.ORG_PN=AVC_MP4_BL_CIF15_AAC_520"
+        );
+    }
+
+    fn connection_manager() -> MockService {
+        MockService {
+            name: "ConnectionManager",
+            actions: vec![
+                ("GetCurrentConnectionIDs", vec![("ConnectionIDs", "out")]),
+                (
+                    "GetCurrentConnectionInfo",
+                    vec![
+                        ("ConnectionID", "in"),
+                        ("RcsID", "out"),
+                        ("AVTransportID", "out"),
+                        ("ProtocolInfo", "out"),
+                        ("PeerConnectionManager", "out"),
+                        ("PeerConnectionID", "out"),
+                        ("Direction", "out"),
+                        ("Status", "out"),
+                    ],
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_current_connection_ids() {
+        let server = mock_device(vec![connection_manager()], |_| {
+            soap_response(
+                "ConnectionManager",
+                "GetCurrentConnectionIDs",
+                "<ConnectionIDs>0, 3,bogus</ConnectionIDs>",
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let ids = device.get_current_connection_ids().await.unwrap();
+
+        assert_eq!(
+            server.actions()[0].action(),
+            Some("GetCurrentConnectionIDs")
+        );
+        assert_eq!(ids, [0, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_current_connection_info() {
+        let server = mock_device(vec![connection_manager()], |_| {
+            soap_response(
+                "ConnectionManager",
+                "GetCurrentConnectionInfo",
+                "<RcsID>2</RcsID><AVTransportID>5</AVTransportID>\
+                 <ProtocolInfo>http-get:*:audio/flac:*</ProtocolInfo>\
+                 <PeerConnectionManager></PeerConnectionManager><PeerConnectionID>-1</PeerConnectionID>\
+                 <Direction>Input</Direction><Status>OK</Status>",
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let info = device.get_current_connection_info(3).await.unwrap();
+
+        let request = &server.actions()[0];
+        assert_eq!(request.action(), Some("GetCurrentConnectionInfo"));
+        assert_eq!(request.param("ConnectionID").as_deref(), Some("3"));
+        assert_eq!(info.rcs_id, 2);
+        assert_eq!(info.av_transport_id, 5);
+        assert_eq!(
+            info.protocol_info
+                .map(|entry| entry.content_format)
+                .as_deref(),
+            Some("audio/flac")
+        );
+        assert_eq!(info.direction, "Input");
+        assert_eq!(info.status, "OK");
+    }
+
+    #[tokio::test]
+    async fn test_missing_connection_manager() {
+        let server = mock_device(vec![], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let error = device.get_protocol_info().await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("has no ConnectionManager service"));
+    }
+}
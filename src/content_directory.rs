@@ -0,0 +1,297 @@
+//! Typed helpers for the `ContentDirectory` service of a [`Device`].
+
+use anyhow::Result;
+
+use crate::{
+    error::UpnpError,
+    parser::{parse_browse_result, parse_search_capabilities, parse_sort_capabilities},
+    types::{BrowseFlag, BrowseResult, Device, Service},
+};
+
+impl Device {
+    /// The `ContentDirectory` service of the device, or an error naming the device when it
+    /// has none.
+    pub fn content_directory(&self) -> Result<&Service, UpnpError> {
+        self.require_service("ContentDirectory")
+    }
+
+    /// Browses `object_id`, returning up to `requested_count` objects from
+    /// `starting_index` on, `0` requesting all of them.
+    pub async fn browse(
+        &self,
+        object_id: &str,
+        flag: BrowseFlag,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        let response = self
+            .content_directory()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "Browse",
+                &[
+                    ("ObjectID", object_id),
+                    ("BrowseFlag", &flag.to_string()),
+                    ("Filter", "*"),
+                    ("StartingIndex", &starting_index.to_string()),
+                    ("RequestedCount", &requested_count.to_string()),
+                    ("SortCriteria", ""),
+                ],
+            )
+            .await?;
+        parse_browse_result(&response, &self.host().unwrap_or_default())
+    }
+
+    /// Searches `container_id` and its descendants for the objects matching
+    /// `search_criteria`, such as `upnp:class derivedfrom "object.item.audioItem"`.
+    ///
+    /// Fails with [`UpnpError::ActionNotSupported`](crate::error::UpnpError) when the
+    /// server doesn't advertise `Search`.
+    pub async fn search(
+        &self,
+        container_id: &str,
+        search_criteria: &str,
+        filter: &str,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        let response = self
+            .content_directory()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "Search",
+                &[
+                    ("ContainerID", container_id),
+                    ("SearchCriteria", search_criteria),
+                    ("Filter", filter),
+                    ("StartingIndex", &starting_index.to_string()),
+                    ("RequestedCount", &requested_count.to_string()),
+                    ("SortCriteria", ""),
+                ],
+            )
+            .await?;
+        parse_browse_result(&response, &self.host().unwrap_or_default())
+    }
+
+    /// The properties the criteria of [`Self::search`] may use, `*` alone when the server
+    /// supports all of them and none when it cannot search.
+    pub async fn search_capabilities(&self) -> Result<Vec<String>, UpnpError> {
+        let response = self
+            .content_directory()?
+            .invoke_with_quirks(self.quirks(), "GetSearchCapabilities", &[])
+            .await?;
+        parse_search_capabilities(&response)
+    }
+
+    /// The properties results can be sorted by, `*` alone when the server supports all of
+    /// them and none when it cannot sort.
+    pub async fn sort_capabilities(&self) -> Result<Vec<String>, UpnpError> {
+        let response = self
+            .content_directory()?
+            .invoke_with_quirks(self.quirks(), "GetSortCapabilities", &[])
+            .await?;
+        parse_sort_capabilities(&response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::UpnpError,
+        parser::parse_location,
+        test_utils::{escape_didl, mock_device, soap_response, MockResponse, MockService},
+        types::{BrowseFlag, ObjectClass},
+    };
+
+    fn content_directory(actions: &[&'static str]) -> MockService {
+        let arguments = |action| {
+            let mut arguments = match action {
+                "GetSearchCapabilities" => return vec![("SearchCaps", "out")],
+                "GetSortCapabilities" => return vec![("SortCaps", "out")],
+                "Search" => vec![("ContainerID", "in"), ("SearchCriteria", "in")],
+                _ => vec![("ObjectID", "in"), ("BrowseFlag", "in")],
+            };
+            arguments.extend([
+                ("Filter", "in"),
+                ("StartingIndex", "in"),
+                ("RequestedCount", "in"),
+                ("SortCriteria", "in"),
+                ("Result", "out"),
+                ("NumberReturned", "out"),
+                ("TotalMatches", "out"),
+                ("UpdateID", "out"),
+            ]);
+            arguments
+        };
+        MockService {
+            name: "ContentDirectory",
+            actions: actions
+                .iter()
+                .map(|action| (*action, arguments(*action)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn result_response(action: &str, didl: &str, count: u32) -> MockResponse {
+        soap_response(
+            "ContentDirectory",
+            action,
+            &format!(
+                "<Result>{}</Result><NumberReturned>{count}</NumberReturned><TotalMatches>{count}</TotalMatches><UpdateID>7</UpdateID>",
+                escape_didl(didl)
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_browse() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="64" parentID="0" restricted="1" childCount="2">
+                <dc:title>Music</dc:title>
+                <upnp:class>object.container.storageFolder</upnp:class>
+            </container>
+            <item id="65" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <upnp:artist>Artist</upnp:artist>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*" duration="0:03:25.000">http://127.0.0.1:8200/MediaItems/65.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+        let server = mock_device(vec![content_directory(&["Browse"])], |_| {
+            result_response("Browse", DIDL, 2)
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let result = device
+            .browse("0", BrowseFlag::default(), 0, 10)
+            .await
+            .unwrap();
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("Browse"));
+        assert_eq!(request.param("ObjectID").as_deref(), Some("0"));
+        assert_eq!(
+            request.param("BrowseFlag").as_deref(),
+            Some("BrowseDirectChildren")
+        );
+        assert_eq!(request.param("RequestedCount").as_deref(), Some("10"));
+
+        assert_eq!(result.number_returned, 2);
+        assert_eq!(result.total_matches, 2);
+        assert_eq!(result.update_id, Some(7));
+        assert_eq!(result.containers.len(), 1);
+        assert_eq!(result.containers[0].title, "Music");
+        assert_eq!(result.items.len(), 1);
+        let item = &result.items[0];
+        assert_eq!(item.id, "65");
+        assert_eq!(item.title, "Song");
+        assert_eq!(item.artist.as_deref(), Some("Artist"));
+        assert_eq!(item.url, "http://127.0.0.1:8200/MediaItems/65.mp3");
+        assert!(matches!(
+            item.object_class,
+            Some(ObjectClass::MusicTrack(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="65" parentID="64" restricted="1">
+                <dc:title>Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://127.0.0.1:8200/MediaItems/65.mp3</res>
+            </item>
+            <item id="66" parentID="64" restricted="1">
+                <dc:title>Movie</dc:title>
+                <upnp:class>object.item.videoItem.movie</upnp:class>
+                <res protocolInfo="http-get:*:video/mp4:*">http://127.0.0.1:8200/MediaItems/66.mp4</res>
+            </item>
+        </DIDL-Lite>"#;
+        let server = mock_device(vec![content_directory(&["Browse", "Search"])], |_| {
+            result_response("Search", DIDL, 2)
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let criteria = r#"dc:title contains "o" and upnp:class derivedfrom "object.item""#;
+        let result = device.search("64", criteria, "*", 0, 0).await.unwrap();
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("Search"));
+        assert_eq!(request.param("ContainerID").as_deref(), Some("64"));
+        assert_eq!(
+            request.param("SearchCriteria").as_deref(),
+            Some(r#"dc:title contains "o" and upnp:class derivedfrom "object.item""#)
+        );
+
+        assert!(result.containers.is_empty());
+        let classes = result
+            .items
+            .iter()
+            .map(|item| (item.title.as_str(), item.object_class.clone()))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            classes.as_slice(),
+            [
+                ("Song", Some(ObjectClass::MusicTrack(_))),
+                ("Movie", Some(ObjectClass::VideoItem(_)))
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_unsupported() {
+        let server = mock_device(vec![content_directory(&["Browse"])], |_| {
+            MockResponse::ok("")
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let error = device.search("0", "*", "*", 0, 0).await.unwrap_err();
+        assert!(matches!(
+            error,
+            UpnpError::ActionNotSupported { action, .. } if action == "Search"
+        ));
+        assert!(server.actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_and_sort_capabilities() {
+        let server = mock_device(
+            vec![content_directory(&[
+                "GetSearchCapabilities",
+                "GetSortCapabilities",
+            ])],
+            |req| match req.action() {
+                Some("GetSearchCapabilities") => soap_response(
+                    "ContentDirectory",
+                    "GetSearchCapabilities",
+                    "<SearchCaps>dc:title, upnp:artist,upnp:class</SearchCaps>",
+                ),
+                _ => soap_response(
+                    "ContentDirectory",
+                    "GetSortCapabilities",
+                    "<SortCaps></SortCaps>",
+                ),
+            },
+        )
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            device.search_capabilities().await.unwrap(),
+            ["dc:title", "upnp:artist", "upnp:class"]
+        );
+        assert!(device.sort_capabilities().await.unwrap().is_empty());
+    }
+}
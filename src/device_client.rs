@@ -1,10 +1,21 @@
-use std::{collections::HashMap, env, net::TcpListener, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
+    error::UpnpError,
+    eventing,
     parser::{
         deserialize_metadata, parse_av_transport_uri_metadata, parse_current_play_mode,
-        parse_current_track_metadata, parse_last_change, parse_location, parse_transport_state,
+        parse_current_track_metadata, parse_last_change, parse_location_with_client,
+        parse_transport_state,
     },
+    quirks::DeviceQuirks,
+    soap,
+    transport::HttpTransport,
     types::{AVTransportEvent, Device, Event, Service},
     BROADCAST_EVENT,
 };
@@ -16,30 +27,37 @@ use hyper::{
 use hyper::{Body, Request, Response, Server};
 use surf::{Client, Config, Url};
 use tokio::sync::Mutex;
-use xml_builder::{XMLBuilder, XMLElement, XMLVersion};
 
 #[derive(Clone)]
 pub struct DeviceClient {
     base_url: Url,
-    http_client: Client,
+    http_client: Arc<dyn HttpTransport>,
     device: Option<Device>,
     stop: Arc<Mutex<bool>>,
 }
 
 impl DeviceClient {
-    pub fn new(url: &str) -> Result<Self> {
+    pub fn new(url: &str) -> Result<Self, UpnpError> {
+        let client: Client = Config::new()
+            .set_timeout(Some(Duration::from_secs(5)))
+            .try_into()?;
+        Self::with_transport(url, Arc::new(client))
+    }
+
+    /// A client for the device at `url` sending its requests through `transport`, e.g. to
+    /// share the connection pool of the application's own HTTP client.
+    pub fn with_transport(url: &str, transport: Arc<dyn HttpTransport>) -> Result<Self, UpnpError> {
         Ok(Self {
             base_url: Url::parse(url)?,
-            http_client: Config::new()
-                .set_timeout(Some(Duration::from_secs(5)))
-                .try_into()?,
+            http_client: transport,
             device: None,
             stop: Arc::new(Mutex::new(false)),
         })
     }
 
-    pub async fn connect(&mut self) -> Result<Self> {
-        self.device = Some(parse_location(self.base_url.as_str()).await?);
+    pub async fn connect(&mut self) -> Result<Self, UpnpError> {
+        self.device =
+            Some(parse_location_with_client(self.base_url.as_str(), &*self.http_client).await?);
         Ok(Self {
             base_url: self.base_url.clone(),
             http_client: self.http_client.clone(),
@@ -48,6 +66,23 @@ impl DeviceClient {
         })
     }
 
+    /// The address the device's description was fetched from.
+    pub(crate) fn remote_addr(&self) -> Result<SocketAddr> {
+        self.base_url
+            .socket_addrs(|| None)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Could not resolve {}", self.base_url))
+    }
+
+    /// The quirks of the connected device, none until [`Self::connect`] succeeds.
+    #[must_use]
+    pub fn quirks(&self) -> DeviceQuirks {
+        self.device
+            .as_ref()
+            .map_or_else(DeviceQuirks::default, Device::quirks)
+    }
+
     #[must_use]
     pub fn ip(&self) -> String {
         self.base_url.host_str().unwrap().to_string()
@@ -58,22 +93,22 @@ impl DeviceClient {
         service_id: &str,
         action_name: &str,
         params: HashMap<String, String>,
-    ) -> Result<String> {
+    ) -> Result<String, UpnpError> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(anyhow!("Device not connected").into());
         }
         let service_id = resolve_service(service_id);
         let service = self.get_service_description(&service_id).await?;
 
         // check if action is available
-        let action = service.actions.iter().find(|a| a.name == action_name);
-        match action {
-            Some(_) => {
-                self.call_action_internal(&service, action_name, params)
-                    .await
-            }
-            None => Err(anyhow!("Action not found")),
+        if service.find_action(action_name).is_none() {
+            return Err(UpnpError::ActionNotSupported {
+                service_id: service.service_id,
+                action: action_name.to_string(),
+            });
         }
+        self.call_action_internal(&service, action_name, params)
+            .await
     }
 
     async fn call_action_internal(
@@ -81,55 +116,32 @@ impl DeviceClient {
         service: &Service,
         action_name: &str,
         params: HashMap<String, String>,
-    ) -> Result<String> {
-        let control_url = Url::parse(&service.control_url)?;
-
-        let mut xml = XMLBuilder::new()
-            .version(XMLVersion::XML1_1)
-            .encoding("UTF-8".into())
-            .build();
-
-        let mut envelope = XMLElement::new("s:Envelope");
-        envelope.add_attribute("xmlns:s", "http://schemas.xmlsoap.org/soap/envelope/");
-        envelope.add_attribute(
-            "s:encodingStyle",
-            "http://schemas.xmlsoap.org/soap/encoding/",
-        );
-
-        let mut body = XMLElement::new("s:Body");
-        let action = format!("u:{action_name}");
-        let mut action = XMLElement::new(action.as_str());
-        action.add_attribute("xmlns:u", service.service_type.as_str());
-
-        for (name, value) in params {
-            let mut param = XMLElement::new(name.as_str());
-            param.add_text(value).map_err(|e| anyhow!("{:?}", e))?;
-            action.add_child(param).map_err(|e| anyhow!("{:?}", e))?;
-        }
-
-        body.add_child(action).map_err(|e| anyhow!("{:?}", e))?;
-        envelope.add_child(body).map_err(|e| anyhow!("{:?}", e))?;
-
-        xml.set_root_element(envelope);
-
-        let mut writer: Vec<u8> = Vec::new();
-        xml.generate(&mut writer).map_err(|e| anyhow!("{:?}", e))?;
-        let xml = String::from_utf8(writer)?;
-
-        let soap_action = format!("\"{}#{}\"", service.service_type, action_name);
+    ) -> Result<String, UpnpError> {
+        let args = params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let envelope = soap::build_envelope_with_quirks(
+            self.quirks(),
+            &service.service_type,
+            action_name,
+            &args,
+        )?;
+        soap::post(
+            &*self.http_client,
+            &service.control_url,
+            &service.service_type,
+            action_name,
+            envelope,
+        )
+        .await
+    }
 
-        let mut res = self
-            .http_client
-            .post(control_url)
-            .header("Content-Type", "text/xml; charset=\"utf-8\"")
-            .header("Content-Length", xml.len().to_string())
-            .header("SOAPACTION", soap_action)
-            .header("Connection", "close")
-            .body_string(xml.clone())
-            .send()
+    /// The service `service_id` of the connected device, which may be given without its
+    /// `urn:upnp-org:serviceId:` prefix.
+    pub(crate) async fn service(&self, service_id: &str) -> Result<Service> {
+        self.get_service_description(&resolve_service(service_id))
             .await
-            .map_err(|e| anyhow!(e.to_string()))?;
-        res.body_string().await.map_err(|e| anyhow!(e.to_string()))
     }
 
     async fn get_service_description(&self, service_id: &str) -> Result<Service> {
@@ -149,52 +161,40 @@ impl DeviceClient {
         Err(anyhow!("Device not connected"))
     }
 
-    pub async fn subscribe(&mut self, service_id: &str) -> Result<()> {
+    pub async fn subscribe(&mut self, service_id: &str) -> Result<(), UpnpError> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(anyhow!("Device not connected").into());
         }
-        let service_id = resolve_service(service_id);
-        let service = self.get_service_description(&service_id).await?;
-
-        let user_agent = format!(
-            "upnp-client/{} ({})",
-            env!("CARGO_PKG_VERSION"),
-            env::consts::OS
-        );
-
         let (address, port) = self.ensure_eventing_server().await?;
-        let callback = format!("<http://{address}:{port}>");
-
-        let client = hyper::Client::new();
-        let req = hyper::Request::builder()
-            .method("SUBSCRIBE")
-            .uri(service.event_sub_url.clone())
-            .header("CALLBACK", callback)
-            .header("NT", "upnp:event")
-            .header("TIMEOUT", "Second-1800")
-            .header("USER-AGENT", user_agent)
-            .body(hyper::Body::empty())?;
-        client.request(req).await?;
+        self.subscribe_with_callback(service_id, &format!("http://{address}:{port}"))
+            .await?;
         Ok(())
     }
 
-    pub async fn unsubscribe(&mut self, service_id: &str, sid: &str) -> Result<()> {
+    /// Subscribes to the events of `service_id`, to be delivered to `callback`, and
+    /// returns the subscription id (`SID`) the device assigned.
+    pub async fn subscribe_with_callback(
+        &self,
+        service_id: &str,
+        callback: &str,
+    ) -> Result<String, UpnpError> {
+        let service_id = resolve_service(service_id);
+        let service = self.get_service_description(&service_id).await?;
+        Ok(eventing::subscribe(&service.event_sub_url, callback)
+            .await?
+            .sid)
+    }
+
+    pub async fn unsubscribe(&mut self, service_id: &str, sid: &str) -> Result<(), UpnpError> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(anyhow!("Device not connected").into());
         }
         let service_id = resolve_service(service_id);
         let service = self.get_service_description(&service_id).await?;
-        let client = hyper::Client::new();
-        let req = hyper::Request::builder()
-            .method("UNSUBSCRIBE")
-            .uri(service.event_sub_url.clone())
-            .header("SID", sid)
-            .body(hyper::Body::empty())?;
-
-        client.request(req).await?;
+        let unsubscribed = eventing::unsubscribe(&service.event_sub_url, sid).await;
 
         self.release_eventing_server().await?;
-        Ok(())
+        unsubscribed
     }
 
     async fn ensure_eventing_server(&mut self) -> Result<(String, u16)> {
@@ -0,0 +1,321 @@
+//! DIDL-Lite emission, e.g. to describe an object passed to `CreateObject`.
+
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+use xml_builder::{XMLBuilder, XMLElement};
+
+use crate::{
+    parser::format_time,
+    types::{Container, Item, Metadata, ObjectClass},
+};
+
+/// Builds a DIDL-Lite document holding `item` alone. An empty `id` is kept as is, which is
+/// what `CreateObject` expects for objects the server has yet to assign an id to.
+#[must_use]
+pub fn item_didl(item: &Item) -> String {
+    let mut element = object_element("item", &item.id, &item.parent_id, item.restricted);
+    add_child(&mut element, text_element("dc:title", &item.title));
+    add_optional(&mut element, "dc:creator", item.creator.as_deref());
+    add_optional(&mut element, "upnp:artist", item.artist.as_deref());
+    add_optional(&mut element, "upnp:album", item.album.as_deref());
+    add_optional(&mut element, "upnp:genre", item.genre.as_deref());
+    add_optional(
+        &mut element,
+        "upnp:albumArtURI",
+        item.album_art_uri.as_deref(),
+    );
+    add_optional(&mut element, "dc:date", item.date.as_deref());
+    let class = item
+        .object_class
+        .as_ref()
+        .map_or("object.item", ObjectClass::value);
+    add_child(&mut element, text_element("upnp:class", class));
+
+    if !item.url.is_empty() || !item.protocol_info.is_empty() {
+        let mut res = text_element("res", &item.url);
+        res.add_attribute("protocolInfo", &item.protocol_info);
+        if let Some(size) = item.size {
+            res.add_attribute("size", &size.to_string());
+        }
+        if let Some(duration) = &item.duration {
+            res.add_attribute("duration", duration);
+        }
+        add_child(&mut element, res);
+    }
+
+    render(element)
+}
+
+/// Builds a DIDL-Lite document holding `container` alone.
+#[must_use]
+pub fn container_didl(container: &Container) -> String {
+    let mut element = object_element(
+        "container",
+        &container.id,
+        &container.parent_id,
+        container.restricted,
+    );
+    element.add_attribute("searchable", bool_attribute(container.searchable));
+    add_child(&mut element, text_element("dc:title", &container.title));
+    add_optional(&mut element, "dc:creator", container.creator.as_deref());
+    let class = container
+        .object_class
+        .as_ref()
+        .map_or("object.container", ObjectClass::value);
+    add_child(&mut element, text_element("upnp:class", class));
+
+    render(element)
+}
+
+/// Builds the DIDL-Lite document describing a URI handed to a renderer, e.g. as the
+/// `CurrentURIMetaData` of `SetAVTransportURI`.
+#[must_use]
+pub fn metadata_didl(metadata: &Metadata, object_class: &ObjectClass) -> String {
+    let mut didl = XMLElement::new("DIDL-Lite");
+    didl.add_attribute("xmlns", "urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/");
+    didl.add_attribute("xmlns:dc", "http://purl.org/dc/elements/1.1/");
+    didl.add_attribute("xmlns:upnp", "urn:schemas-upnp-org:metadata-1-0/upnp/");
+    didl.add_attribute("xmlns:dlna", "urn:schemas-dlna-org:metadata-1-0/");
+    didl.add_attribute("xmlns:xbmc", "urn:schemas-xbmc-org:metadata-1-0/");
+    didl.add_attribute("xmlns:sec", "http://www.sec.co.kr/");
+
+    let mut item = object_element("item", "0", "-1", false);
+    add_child(&mut item, text_element("dc:title", &metadata.title));
+    add_optional(&mut item, "dc:creator", metadata.creator.as_deref());
+    add_child(&mut item, text_element("upnp:class", object_class.value()));
+    add_optional(&mut item, "upnp:artist", metadata.artist.as_deref());
+    add_optional(&mut item, "upnp:album", metadata.album.as_deref());
+    if let Some(album_art_uri) = &metadata.album_art_uri {
+        let mut album_art = text_element("upnp:albumArtURI", album_art_uri);
+        album_art.add_attribute("dlna:profileID", "JPEG_TN");
+        album_art.add_attribute("xmlns:dlna", "urn:schemas-dlna-org:metadata-1-0/");
+        add_child(&mut item, album_art);
+    }
+    add_optional(&mut item, "upnp:genre", metadata.genre.as_deref());
+
+    let mut res = text_element("res", &metadata.url);
+    res.add_attribute("protocolInfo", &metadata.protocol_info);
+    if let Some(duration) = metadata.duration {
+        res.add_attribute("duration", &format_time(duration.as_secs()));
+    }
+    add_child(&mut item, res);
+
+    add_child(&mut didl, item);
+    to_string(didl)
+}
+
+impl Metadata {
+    /// The minimal metadata of a stream no `ContentDirectory` describes, such as an
+    /// internet radio or a file URL, for `SetAVTransportURI`. Pass it to [`Self::to_didl`]
+    /// with an [`ObjectClass::AudioItem`] for the DIDL-Lite alone.
+    #[must_use]
+    pub fn for_stream(url: &str, title: &str, mime_type: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            title: title.to_string(),
+            protocol_info: format!("http-get:*:{mime_type}:*"),
+            ..Default::default()
+        }
+    }
+
+    /// The DIDL-Lite document describing the URI to a renderer, as [`metadata_didl`]
+    /// builds it.
+    #[must_use]
+    pub fn to_didl(&self, object_class: &ObjectClass) -> String {
+        metadata_didl(self, object_class)
+    }
+}
+
+/// Escapes a DIDL-Lite document so it can be passed as the value of a SOAP argument.
+#[must_use]
+pub fn escape(didl: &str) -> String {
+    escape_str_attribute(didl).to_string()
+}
+
+/// Serializes `object` inside a `DIDL-Lite` root, without the XML declaration.
+fn render(object: XMLElement) -> String {
+    let mut didl = XMLElement::new("DIDL-Lite");
+    didl.add_attribute("xmlns", "urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/");
+    didl.add_attribute("xmlns:dc", "http://purl.org/dc/elements/1.1/");
+    didl.add_attribute("xmlns:upnp", "urn:schemas-upnp-org:metadata-1-0/upnp/");
+    add_child(&mut didl, object);
+    to_string(didl)
+}
+
+/// Serializes `root`, dropping the XML declaration DIDL-Lite fragments are sent without.
+pub(crate) fn to_string(root: XMLElement) -> String {
+    let mut xml = XMLBuilder::new().build();
+    xml.set_root_element(root);
+
+    let mut writer: Vec<u8> = Vec::new();
+    xml.generate(&mut writer).unwrap();
+    String::from_utf8(writer)
+        .unwrap()
+        .replace(r#"<?xml version="1.0" encoding="UTF-8"?>"#, "")
+}
+
+fn object_element(name: &str, id: &str, parent_id: &str, restricted: bool) -> XMLElement {
+    let mut element = XMLElement::new(name);
+    element.add_attribute("id", id);
+    element.add_attribute("parentID", parent_id);
+    element.add_attribute("restricted", bool_attribute(restricted));
+    element
+}
+
+const fn bool_attribute(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+/// `XMLElement::add_text` writes its text verbatim, so it is escaped here.
+fn text_element(name: &str, text: &str) -> XMLElement {
+    let mut element = XMLElement::new(name);
+    element
+        .add_text(escape_str_pcdata(text).to_string())
+        .unwrap();
+    element
+}
+
+fn add_child(element: &mut XMLElement, child: XMLElement) {
+    // Only fails when `element` holds text, which objects never do.
+    element.add_child(child).unwrap();
+}
+
+fn add_optional(element: &mut XMLElement, name: &str, text: Option<&str>) {
+    if let Some(text) = text {
+        add_child(element, text_element(name, text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        didl::{container_didl, item_didl, metadata_didl},
+        parser::{deserialize_content_directory, deserialize_metadata},
+        types::{Container, Item, Metadata, ObjectClass},
+    };
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = Metadata {
+            url: "http://192.168.1.2:8200/track.mp3?id=1&format=mp3".to_string(),
+            title: "Rock & Roll".to_string(),
+            creator: Some("Jimmy Page <jimmy@example.com>".to_string()),
+            artist: Some("Led Zeppelin".to_string()),
+            album: Some("IV".to_string()),
+            album_art_uri: Some("http://192.168.1.2:8200/art.jpg".to_string()),
+            genre: Some("Rock".to_string()),
+            protocol_info: "http-get:*:audio/mpeg:*".to_string(),
+            duration: Some(Duration::from_secs(252)),
+        };
+
+        let object_class = ObjectClass::from("object.item.audioItem.musicTrack");
+        let didl = metadata_didl(&metadata, &object_class);
+        assert_eq!(metadata.to_didl(&object_class), didl);
+        assert!(didl.contains(r#"duration="00:04:12""#));
+        for namespace in [
+            r#"xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/""#,
+            r#"xmlns:dc="http://purl.org/dc/elements/1.1/""#,
+            r#"xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/""#,
+        ] {
+            assert!(didl.contains(namespace));
+        }
+        assert!(didl.contains("<dc:creator>Jimmy Page &lt;jimmy@example.com&gt;</dc:creator>"));
+        assert!(didl.contains("<upnp:class>object.item.audioItem.musicTrack</upnp:class>"));
+        let parsed = deserialize_metadata(&didl).unwrap();
+        assert_eq!(parsed.url, metadata.url);
+        assert_eq!(parsed.title, metadata.title);
+        assert_eq!(parsed.creator, metadata.creator);
+        assert_eq!(parsed.artist, metadata.artist);
+        assert_eq!(parsed.album, metadata.album);
+        assert_eq!(parsed.album_art_uri, metadata.album_art_uri);
+        assert_eq!(parsed.genre, metadata.genre);
+        assert_eq!(parsed.protocol_info, metadata.protocol_info);
+        assert_eq!(parsed.duration, metadata.duration);
+    }
+
+    #[test]
+    fn test_stream_metadata_round_trip() {
+        let metadata = Metadata::for_stream(
+            "http://radio.example.com/live?format=aac&bitrate=128",
+            "Radio & Talk",
+            "audio/aac",
+        );
+        assert_eq!(metadata.protocol_info, "http-get:*:audio/aac:*");
+
+        let didl = metadata.to_didl(&ObjectClass::from("object.item.audioItem"));
+        assert!(didl.contains("<upnp:class>object.item.audioItem</upnp:class>"));
+        assert!(didl.contains(r#"<res protocolInfo="http-get:*:audio/aac:*">"#));
+        assert_eq!(deserialize_metadata(&didl).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_music_track_round_trip() {
+        let item = Item {
+            parent_id: "4".to_string(),
+            title: "Rock & Roll".to_string(),
+            artist: Some("Led Zeppelin".to_string()),
+            album: Some("IV".to_string()),
+            protocol_info: "http-get:*:audio/mpeg:*".to_string(),
+            url: "http://192.168.1.2:8200/track.mp3?id=1&format=mp3".to_string(),
+            size: Some(1024),
+            duration: Some("0:03:40".to_string()),
+            object_class: Some(ObjectClass::from("object.item.audioItem.musicTrack.vendor")),
+            ..Default::default()
+        };
+
+        let didl = item_didl(&item);
+        assert!(didl.contains(r#"id="""#));
+        let (containers, items) = deserialize_content_directory(&didl, "192.168.1.2").unwrap();
+        assert!(containers.is_empty());
+        assert_eq!(items.len(), 1);
+        let parsed = &items[0];
+        assert_eq!(parsed.parent_id, "4");
+        assert_eq!(parsed.title, "Rock & Roll");
+        assert_eq!(parsed.artist.as_deref(), Some("Led Zeppelin"));
+        assert_eq!(parsed.album.as_deref(), Some("IV"));
+        assert_eq!(parsed.object_class, item.object_class);
+        assert_eq!(parsed.protocol_info, item.protocol_info);
+        assert_eq!(parsed.url, item.url);
+        assert_eq!(parsed.size, Some(1024));
+        assert_eq!(parsed.duration.as_deref(), Some("0:03:40"));
+    }
+
+    #[test]
+    fn test_metadata_from_item_keeps_duration() {
+        let item = Item {
+            title: "Song".to_string(),
+            protocol_info: "http-get:*:audio/mpeg:*".to_string(),
+            url: "http://192.168.1.2:8200/track.mp3".to_string(),
+            duration: Some("0:03:40.500".to_string()),
+            ..Default::default()
+        };
+
+        let metadata = Metadata::from(&item);
+        assert_eq!(metadata.duration, Some(Duration::from_millis(220_500)));
+        let didl = metadata_didl(&metadata, &ObjectClass::from("object.item.audioItem"));
+        assert!(didl.contains(r#"duration="00:03:40""#));
+    }
+
+    #[test]
+    fn test_container_round_trip() {
+        let container = Container {
+            parent_id: "0".to_string(),
+            title: "Playlists".to_string(),
+            object_class: Some(ObjectClass::Unknown(
+                "object.container.playlistContainer".to_string(),
+            )),
+            ..Default::default()
+        };
+
+        let (containers, items) =
+            deserialize_content_directory(&container_didl(&container), "").unwrap();
+        assert!(items.is_empty());
+        assert_eq!(containers[0].title, "Playlists");
+        assert_eq!(containers[0].parent_id, "0");
+    }
+}
@@ -1,13 +1,19 @@
 use anyhow::{anyhow, Result};
 use async_stream::stream;
-use futures_util::Stream;
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, SocketAddr};
+use futures_util::{future::join_all, stream::FuturesUnordered, FutureExt, Stream, StreamExt};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::str;
+use std::time::Duration;
 use tokio::net::UdpSocket;
+use tokio::time::{sleep_until, timeout_at, Instant};
+use tokio_util::sync::CancellationToken;
 
-use crate::parser::parse_location;
-use crate::types::Device;
+use crate::error::UpnpError;
+use crate::parser::{fetch_description, fetch_service_descriptions, parse_location, ParserConfig};
+use crate::types::{strip_version, Device, DiscoveryOrigin};
 
 const DISCOVERY_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
                                  HOST: 239.255.255.250:1900\r\n\
@@ -16,7 +22,7 @@ const DISCOVERY_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
                                  ST: ssdp:all\r\n\
                                  \r\n";
 
-pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>> {
+pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>, UpnpError> {
     let any: SocketAddr = ([0, 0, 0, 0], 0).into();
     let socket = UdpSocket::bind(any).await?;
     socket.join_multicast_v4(Ipv4Addr::new(239, 255, 255, 250), Ipv4Addr::new(0, 0, 0, 0))?;
@@ -35,12 +41,8 @@ pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>> {
                 // Receive the discovery response
                 let mut buf = [0; 2048];
                 let (size, _) = socket.recv_from(&mut buf).await?;
-                // Convert the response to a string
-                let response = str::from_utf8(&buf[..size])?;
-                let headers = parse_raw_http_response(response)?;
-                let location = (*headers.get("location")
-                    .ok_or_else(|| anyhow!("Response header missing location"))?).to_string();
-                Ok(location)
+                let response = parse_ssdp_response(str::from_utf8(&buf[..size])?)?;
+                Ok(response.location)
             }
 
             if let Ok(location) = get_next(&socket).await {
@@ -52,14 +54,539 @@ pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>> {
     })
 }
 
+/// The `ST` header of an M-SEARCH request.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SearchTarget {
+    /// `ssdp:all`, every device and service.
+    #[default]
+    All,
+    /// `upnp:rootdevice`, root devices only.
+    RootDevice,
+    /// A device type URN, e.g. `urn:schemas-upnp-org:device:MediaRenderer:1`.
+    DeviceType(String),
+    /// A service type URN, e.g. `urn:schemas-upnp-org:service:AVTransport:1`.
+    ServiceType(String),
+    /// A single device, by its UDN without the `uuid:` prefix, e.g. to find a known
+    /// device again after its address changed.
+    Uuid(String),
+    /// Any other search target, sent verbatim.
+    Custom(String),
+}
+
+impl From<&str> for SearchTarget {
+    fn from(value: &str) -> Self {
+        match value {
+            "ssdp:all" => Self::All,
+            "upnp:rootdevice" => Self::RootDevice,
+            uuid if uuid.starts_with("uuid:") => Self::Uuid(uuid["uuid:".len()..].to_string()),
+            urn if urn.starts_with("urn:") && urn.contains(":device:") => {
+                Self::DeviceType(urn.to_string())
+            }
+            urn if urn.starts_with("urn:") && urn.contains(":service:") => {
+                Self::ServiceType(urn.to_string())
+            }
+            other => Self::Custom(other.to_string()),
+        }
+    }
+}
+
+impl Display for SearchTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "ssdp:all"),
+            Self::RootDevice => write!(f, "upnp:rootdevice"),
+            Self::Uuid(uuid) => write!(f, "uuid:{uuid}"),
+            Self::DeviceType(urn) | Self::ServiceType(urn) | Self::Custom(urn) => {
+                write!(f, "{urn}")
+            }
+        }
+    }
+}
+
+impl SearchTarget {
+    /// Whether an answer with the given `USN` is for this target.
+    ///
+    /// Only [`Self::Uuid`] filters answers, as some devices answer every search
+    /// regardless of its target.
+    #[must_use]
+    pub fn matches(&self, usn: &str) -> bool {
+        match self {
+            Self::Uuid(uuid) => usn
+                .split("::")
+                .next()
+                .and_then(|udn| udn.strip_prefix("uuid:"))
+                .is_some_and(|udn| udn.eq_ignore_ascii_case(uuid)),
+            _ => true,
+        }
+    }
+
+    /// Whether `response` may come from a device this target is searching for.
+    ///
+    /// Besides [`Self::matches`], answers for another device or service type are left out,
+    /// which spares resolving them. Answers with any other `ST`, such as
+    /// `upnp:rootdevice`, are kept, their description alone telling whether they match.
+    #[must_use]
+    pub fn accepts(&self, response: &SsdpResponse) -> bool {
+        let st = Self::from(response.st.trim());
+        let same_type =
+            |urn: &str, other: &str| strip_version(urn).eq_ignore_ascii_case(strip_version(other));
+        self.matches(&response.usn)
+            && match (self, &st) {
+                (Self::DeviceType(urn), Self::DeviceType(other))
+                | (Self::ServiceType(urn), Self::ServiceType(other)) => same_type(urn, other),
+                _ => true,
+            }
+    }
+
+    /// Whether `device`, one of its embedded devices or one of their services is of the
+    /// type this target is searching for. Targets other than types match every device.
+    #[must_use]
+    pub fn matches_device(&self, device: &Device) -> bool {
+        match self {
+            Self::DeviceType(urn) => device.has_device_type(urn),
+            Self::ServiceType(urn) => device.find_service(urn).is_some(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    pub search_target: SearchTarget,
+    /// Maximum number of seconds devices may wait before answering.
+    pub mx: u8,
+    /// How long to listen for answers overall.
+    pub timeout: Duration,
+    /// Where the M-SEARCH request is sent, the SSDP multicast group by default.
+    pub address: SocketAddr,
+    /// The IPv4 address of the local interface to search through, e.g. that of the LAN
+    /// on a host also connected to a VPN. `None` leaves the choice to the OS, which uses
+    /// the interface of its default route.
+    pub interface: Option<Ipv4Addr>,
+    /// Stops listening for answers once cancelled, like reaching `timeout` does, except
+    /// that [`discover_devices`] then fails with [`UpnpError::Cancelled`]. The default
+    /// token is never cancelled.
+    pub cancel: CancellationToken,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            search_target: SearchTarget::All,
+            mx: 2,
+            timeout: Duration::from_secs(5),
+            address: ([239, 255, 255, 250], 1900).into(),
+            interface: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+/// Sends an M-SEARCH request and yields the `LOCATION` of every device answering it.
+///
+/// The stream ends once `options.timeout` has elapsed. Each location is yielded once and
+/// can be passed to [`parse_location`].
+pub async fn discover_locations(
+    options: &DiscoveryOptions,
+) -> Result<impl Stream<Item = String>, UpnpError> {
+    let responses = search(options).await?;
+    Ok(responses.map(|(response, _)| response.location))
+}
+
+/// Searches for the device with the given UDN and returns its current location.
+///
+/// The UDN may include its `uuid:` prefix and the search target of `options` is ignored.
+/// Returns `None` if the device didn't answer before `options.timeout`.
+pub async fn locate_device(
+    udn: &str,
+    options: &DiscoveryOptions,
+) -> Result<Option<String>, UpnpError> {
+    let options = DiscoveryOptions {
+        search_target: SearchTarget::Uuid(udn.trim_start_matches("uuid:").to_string()),
+        ..options.clone()
+    };
+    let locations = discover_locations(&options).await?;
+    tokio::pin!(locations);
+    Ok(locations.next().await)
+}
+
+/// Resolves the devices at `locations`, e.g. known from configuration or from another
+/// discovery mechanism where SSDP multicast is blocked.
+///
+/// The locations are resolved concurrently, and each gets its own result, in the order of
+/// `locations`, so that one failing doesn't affect the others.
+pub async fn resolve_devices(locations: &[String]) -> Vec<Result<Device, UpnpError>> {
+    join_all(locations.iter().map(|location| parse_location(location))).await
+}
+
+/// The outcome of [`discover_devices`].
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredDevices {
+    pub devices: Vec<Device>,
+    /// Why some answering devices are missing from `devices` or are incomplete.
+    pub warnings: Vec<String>,
+}
+
+/// A step of resolving a device found by [`discover_devices`].
+enum Resolution {
+    /// Its description was fetched, with the device it describes.
+    Described(String, SocketAddr, Result<Device, UpnpError>),
+    /// Its SCPDs were fetched as well.
+    Resolved(String, Result<Device, UpnpError>),
+}
+
+/// Sends an M-SEARCH request and resolves the devices answering it, all within
+/// `options.timeout`.
+///
+/// Devices keep being resolved while answers come in, and whatever isn't resolved by the
+/// deadline is given up on: devices whose SCPDs are still pending are returned as their
+/// description alone tells, without actions, and devices whose description is still
+/// pending are left out. Both, like devices that cannot be fetched or parsed, are
+/// reported in [`DiscoveredDevices::warnings`].
+///
+/// Devices not matching a device or service type `options.search_target` are left out,
+/// as filtered by [`SearchTarget::accepts`] and [`SearchTarget::matches_device`].
+pub async fn discover_devices(options: &DiscoveryOptions) -> Result<DiscoveredDevices, UpnpError> {
+    let deadline = Instant::now() + options.timeout;
+    let config = ParserConfig::default();
+    let client = config.client()?;
+    let responses = search(options).await?;
+    tokio::pin!(responses);
+
+    let mut resolutions = FuturesUnordered::new();
+    let mut describing = Vec::new();
+    let mut described = HashMap::new();
+    let mut discovered = DiscoveredDevices::default();
+    let mut listening = true;
+    while listening || !resolutions.is_empty() {
+        tokio::select! {
+            response = responses.next(), if listening => {
+                let Some((response, remote_addr)) = response else {
+                    listening = false;
+                    continue;
+                };
+                let (client, limits) = (client.clone(), config.limits.clone());
+                describing.push(response.location.clone());
+                resolutions.push(
+                    async move {
+                        let device =
+                            fetch_description(&response.location, &client, &limits).await;
+                        Resolution::Described(response.location, remote_addr, device)
+                    }
+                    .boxed(),
+                );
+            }
+            Some(resolution) = resolutions.next() => match resolution {
+                Resolution::Described(location, remote_addr, device) => {
+                    describing.retain(|pending| *pending != location);
+                    let mut device = match device {
+                        Ok(device) if options.search_target.matches_device(&device) => device,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            discovered.warnings.push(format!("Skipped {location}: {e}"));
+                            continue;
+                        }
+                    };
+                    device.discovered_via =
+                        Some(discovery_origin(remote_addr, &mut discovered.warnings));
+                    described.insert(location.clone(), device.clone());
+                    let (client, config) = (client.clone(), config.clone());
+                    resolutions.push(
+                        async move {
+                            let resolved =
+                                fetch_service_descriptions(&mut device, &client, &config).await;
+                            Resolution::Resolved(location, resolved.map(|()| device))
+                        }
+                        .boxed(),
+                    );
+                }
+                Resolution::Resolved(location, device) => {
+                    described.remove(&location);
+                    match device {
+                        Ok(device) => discovered.devices.push(device),
+                        Err(e) => discovered.warnings.push(format!("Skipped {location}: {e}")),
+                    }
+                }
+            },
+            () = sleep_until(deadline) => break,
+            () = options.cancel.cancelled() => break,
+        }
+    }
+    // The answers also stop once cancelled, which may end the loop first.
+    if options.cancel.is_cancelled() {
+        return Err(UpnpError::Cancelled);
+    }
+
+    for (location, device) in described {
+        discovered.warnings.push(format!(
+            "Timed out fetching the service descriptions of {location}, its actions are unknown"
+        ));
+        discovered.devices.push(device);
+    }
+    for location in describing {
+        discovered.warnings.push(format!(
+            "Timed out fetching the description at {location}, skipped"
+        ));
+    }
+    Ok(discovered)
+}
+
+/// Discovers the devices answering a search for `search_target` within `timeout`, which
+/// covers most uses of [`discover_devices`] in a single call.
+///
+/// Devices answering from several locations, e.g. through several interfaces, are
+/// returned once, per their UDN. Devices that cannot be resolved are skipped, and logged
+/// at warn level when the `tracing` feature is enabled.
+pub async fn discover_all(
+    search_target: SearchTarget,
+    timeout: Duration,
+) -> Result<Vec<Device>, UpnpError> {
+    discover_all_at(search_target, timeout, DiscoveryOptions::default().address).await
+}
+
+/// [`discover_all`], searching at `address` rather than the SSDP multicast group.
+async fn discover_all_at(
+    search_target: SearchTarget,
+    timeout: Duration,
+    address: SocketAddr,
+) -> Result<Vec<Device>, UpnpError> {
+    let discovered = discover_devices(&DiscoveryOptions {
+        search_target,
+        timeout,
+        address,
+        ..Default::default()
+    })
+    .await?;
+    #[cfg(feature = "tracing")]
+    for warning in &discovered.warnings {
+        tracing::warn!("{warning}");
+    }
+    let mut seen = HashSet::new();
+    Ok(discovered
+        .devices
+        .into_iter()
+        .filter(|device| seen.insert(device.identity().to_string()))
+        .collect())
+}
+
+/// Yields every answer to an M-SEARCH with a distinct location, along with its source
+/// address.
+async fn search(
+    options: &DiscoveryOptions,
+) -> Result<impl Stream<Item = (SsdpResponse, SocketAddr)>> {
+    let interface = options.interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let socket = UdpSocket::bind((interface, 0)).await?;
+    if let IpAddr::V4(group) = options.address.ip() {
+        if group.is_multicast() {
+            socket.join_multicast_v4(group, interface)?;
+            if options.interface.is_some() {
+                SockRef::from(&socket).set_multicast_if_v4(&interface)?;
+            }
+        }
+    }
+
+    socket
+        .send_to(search_request(options).as_bytes(), options.address)
+        .await?;
+
+    let deadline = Instant::now() + options.timeout;
+    let options = options.clone();
+    Ok(stream! {
+        let mut seen = HashSet::new();
+        let mut buf = [0; 2048];
+        loop {
+            let received = tokio::select! {
+                received = timeout_at(deadline, socket.recv_from(&mut buf)) => received,
+                () = options.cancel.cancelled() => break,
+            };
+            let Ok(received) = received else {
+                break;
+            };
+            let Ok((size, remote_addr)) = received else {
+                continue;
+            };
+            let Ok(datagram) = str::from_utf8(&buf[..size]) else {
+                continue;
+            };
+            if let Ok(response) = parse_ssdp_response(datagram) {
+                if options.search_target.accepts(&response)
+                    && seen.insert(response.location.clone())
+                {
+                    yield (response, remote_addr);
+                }
+            }
+        }
+    })
+}
+
+/// The origin of a device answering from `remote_addr`, without its local interface if
+/// the OS has no route to it, which is reported in `warnings`.
+fn discovery_origin(remote_addr: SocketAddr, warnings: &mut Vec<String>) -> DiscoveryOrigin {
+    let local_if = local_addr_for(remote_addr)
+        .map_err(|e| warnings.push(format!("No local interface for {remote_addr}: {e}")))
+        .ok();
+    DiscoveryOrigin {
+        local_if,
+        remote_addr,
+    }
+}
+
+/// The local address the OS routes traffic to `remote_addr` from, i.e. the address of
+/// the interface a device is reachable through.
+pub(crate) fn local_addr_for(remote_addr: SocketAddr) -> Result<IpAddr> {
+    let any: SocketAddr = match remote_addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = std::net::UdpSocket::bind(any)?;
+    socket.connect(remote_addr)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+fn search_request(options: &DiscoveryOptions) -> String {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: {}\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: {}\r\n\
+         ST: {}\r\n\
+         \r\n",
+        options.address, options.mx, options.search_target
+    )
+}
+
+/// A device advertisement received while listening for SSDP `NOTIFY` messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SsdpEvent {
+    /// `ssdp:alive`, a device or service appeared or renewed its advertisement.
+    Alive {
+        location: String,
+        nt: String,
+        usn: String,
+        max_age: Option<u32>,
+    },
+    /// `ssdp:byebye`, a device or service is leaving the network.
+    ByeBye { nt: String, usn: String },
+}
+
+/// Listens for the `ssdp:alive` and `ssdp:byebye` advertisements devices multicast on
+/// their own, which keeps a device list fresh without sending M-SEARCH requests.
+///
+/// `address` is the SSDP multicast group (`239.255.255.250:1900`) in most cases; any other
+/// address is bound to directly. The stream never ends on its own. Must be called from
+/// within a Tokio runtime.
+pub fn listen_notifications(
+    address: SocketAddr,
+) -> Result<impl Stream<Item = SsdpEvent>, UpnpError> {
+    let socket = match address.ip() {
+        IpAddr::V4(group) if group.is_multicast() => {
+            let socket = bind_reusable((Ipv4Addr::UNSPECIFIED, address.port()).into())?;
+            socket.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+            socket
+        }
+        _ => bind_reusable(address)?,
+    };
+
+    Ok(stream! {
+        let mut buf = [0; 2048];
+        loop {
+            let Ok((size, _)) = socket.recv_from(&mut buf).await else {
+                continue;
+            };
+            let Ok(datagram) = str::from_utf8(&buf[..size]) else {
+                continue;
+            };
+            if let Ok(event) = parse_ssdp_notify(datagram) {
+                yield event;
+            }
+        }
+    })
+}
+
+/// Binds a UDP socket other SSDP listeners on the host can share.
+fn bind_reusable(address: SocketAddr) -> Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Parses an SSDP `NOTIFY` message into an [`SsdpEvent`].
+pub fn parse_ssdp_notify(datagram: &str) -> Result<SsdpEvent, UpnpError> {
+    if !datagram.starts_with("NOTIFY ") {
+        return Err(anyhow!("Not a NOTIFY message").into());
+    }
+    let headers = parse_raw_http_response(datagram)?;
+    let header = |name: &str| headers.get(name).map(|value| (*value).to_string());
+
+    let nt = header("nt").unwrap_or_default();
+    let usn = header("usn").ok_or_else(|| anyhow!("NOTIFY missing USN"))?;
+    match headers.get("nts").copied() {
+        Some("ssdp:alive") => Ok(SsdpEvent::Alive {
+            location: header("location").ok_or_else(|| anyhow!("NOTIFY missing location"))?,
+            nt,
+            usn,
+            max_age: headers
+                .get("cache-control")
+                .and_then(|value| parse_max_age(value)),
+        }),
+        Some("ssdp:byebye") => Ok(SsdpEvent::ByeBye { nt, usn }),
+        nts => Err(anyhow!("Unsupported NOTIFY type {nts:?}").into()),
+    }
+}
+
+/// The headers of an SSDP answer to an M-SEARCH request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SsdpResponse {
+    pub location: String,
+    pub st: String,
+    pub usn: String,
+    pub server: Option<String>,
+    /// The `max-age` directive of `CACHE-CONTROL`, in seconds.
+    pub max_age: Option<u32>,
+}
+
+/// Parses an SSDP answer. Header names are matched case-insensitively and a `LOCATION`
+/// header is required.
+pub fn parse_ssdp_response(datagram: &str) -> Result<SsdpResponse, UpnpError> {
+    let headers = parse_raw_http_response(datagram)?;
+    let header = |name: &str| headers.get(name).map(|value| (*value).to_string());
+
+    Ok(SsdpResponse {
+        location: header("location").ok_or_else(|| anyhow!("Response header missing location"))?,
+        st: header("st").unwrap_or_default(),
+        usn: header("usn").unwrap_or_default(),
+        server: header("server"),
+        max_age: headers
+            .get("cache-control")
+            .and_then(|value| parse_max_age(value)),
+    })
+}
+
+fn parse_max_age(cache_control: &str) -> Option<u32> {
+    cache_control.split(',').find_map(|directive| {
+        let (name, value) = directive.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("max-age") {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
 fn parse_raw_http_response(response_str: &str) -> Result<HashMap<String, &str>> {
     let mut headers = HashMap::new();
 
     match response_str.split("\r\n\r\n").next() {
         Some(header_str) => {
-            for header_line in header_str.split("\r\n") {
+            for header_line in header_str.lines() {
                 if let Some(colon_index) = header_line.find(':') {
-                    let header_name = header_line[0..colon_index].to_ascii_lowercase();
+                    let header_name = header_line[0..colon_index].trim().to_ascii_lowercase();
                     let header_value = header_line[colon_index + 1..].trim();
                     headers.insert(header_name, header_value);
                 }
@@ -69,3 +596,485 @@ fn parse_raw_http_response(response_str: &str) -> Result<HashMap<String, &str>>
         None => Err(anyhow!("Invalid HTTP response")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::{Duration, Instant},
+    };
+
+    use futures_util::StreamExt;
+    use tokio::net::UdpSocket;
+
+    use crate::{
+        discovery::{
+            discover_all_at, discover_devices, discover_locations, discovery_origin,
+            listen_notifications, locate_device, parse_ssdp_notify, parse_ssdp_response,
+            resolve_devices, DiscoveryOptions, SearchTarget, SsdpEvent, SsdpResponse,
+        },
+        error::UpnpError,
+        test_utils::{device_description, mock_device, MockResponse, MockServer, MockService},
+    };
+
+    const SSDP_RESPONSE: &str = "HTTP/1.1 200 OK\r\n\
+        CACHE-CONTROL: max-age=1800\r\n\
+        EXT:\r\n\
+        LOCATION: http://192.168.1.20:49152/description.xml\r\n\
+        SERVER: Linux/5.10 UPnP/1.0 MockRenderer/1.0\r\n\
+        ST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+        USN: uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+        \r\n";
+
+    #[test]
+    fn test_parse_ssdp_response() {
+        assert_eq!(
+            parse_ssdp_response(SSDP_RESPONSE).unwrap(),
+            SsdpResponse {
+                location: "http://192.168.1.20:49152/description.xml".to_string(),
+                st: "urn:schemas-upnp-org:device:MediaRenderer:1".to_string(),
+                usn: "uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::urn:schemas-upnp-org:device:MediaRenderer:1".to_string(),
+                server: Some("Linux/5.10 UPnP/1.0 MockRenderer/1.0".to_string()),
+                max_age: Some(1800),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_ssdp_response_with_mixed_case_headers() {
+        const RESPONSE: &str = "HTTP/1.1 200 OK\r\n\
+            Cache-Control:   no-cache=\"Ext\", MAX-AGE = 900 \r\n\
+            location :  http://10.0.0.5:8200/rootDesc.xml  \r\n\
+            St: upnp:rootdevice\r\n\
+            usn:uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::upnp:rootdevice\r\n\
+            \r\n";
+
+        let response = parse_ssdp_response(RESPONSE).unwrap();
+        assert_eq!(response.location, "http://10.0.0.5:8200/rootDesc.xml");
+        assert_eq!(response.st, "upnp:rootdevice");
+        assert_eq!(
+            response.usn,
+            "uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::upnp:rootdevice"
+        );
+        assert_eq!(response.server, None);
+        assert_eq!(response.max_age, Some(900));
+    }
+
+    #[tokio::test]
+    async fn test_discover_locations() {
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = DiscoveryOptions {
+            search_target: "urn:schemas-upnp-org:device:MediaRenderer:1".into(),
+            mx: 1,
+            timeout: Duration::from_millis(500),
+            address: responder.local_addr().unwrap(),
+            ..Default::default()
+        };
+
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (size, from) = responder.recv_from(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..size]).to_string();
+            assert!(request.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+            assert!(request.contains("ST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n"));
+            assert!(request.contains("MX: 1\r\n"));
+            // Answer twice, as devices commonly do, to check locations are de-duplicated.
+            for _ in 0..2 {
+                responder
+                    .send_to(SSDP_RESPONSE.as_bytes(), from)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let locations = discover_locations(&options)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(locations, vec!["http://192.168.1.20:49152/description.xml"]);
+        assert_eq!(
+            options.search_target,
+            SearchTarget::DeviceType("urn:schemas-upnp-org:device:MediaRenderer:1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_through_interface() {
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut options = DiscoveryOptions {
+            timeout: Duration::from_millis(500),
+            address: responder.local_addr().unwrap(),
+            interface: Some(Ipv4Addr::LOCALHOST),
+            ..Default::default()
+        };
+
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (_, from) = responder.recv_from(&mut buf).await.unwrap();
+            assert_eq!(from.ip(), Ipv4Addr::LOCALHOST);
+            responder
+                .send_to(SSDP_RESPONSE.as_bytes(), from)
+                .await
+                .unwrap();
+        });
+        let locations = discover_locations(&options)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(locations, vec!["http://192.168.1.20:49152/description.xml"]);
+
+        // An address no interface of the host has, from the documentation range.
+        options.interface = Some(Ipv4Addr::new(192, 0, 2, 1));
+        assert!(discover_locations(&options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_locate_device_by_uuid() {
+        const UUID: &str = "4d696e69-444c-164e-9d41-b827eb54e0b3";
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = DiscoveryOptions {
+            timeout: Duration::from_millis(500),
+            address: responder.local_addr().unwrap(),
+            ..Default::default()
+        };
+
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (size, from) = responder.recv_from(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..size]).to_string();
+            assert!(request.contains(&format!("ST: uuid:{UUID}\r\n")));
+            // A device answering regardless of the search target comes first.
+            for (uuid, location) in [
+                (
+                    "00000000-0000-0000-0000-000000000001",
+                    "http://10.0.0.2/desc.xml",
+                ),
+                (UUID, "http://10.0.0.7:49152/desc.xml"),
+            ] {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nLOCATION: {location}\r\nST: uuid:{uuid}\r\nUSN: uuid:{uuid}\r\n\r\n"
+                );
+                responder.send_to(response.as_bytes(), from).await.unwrap();
+            }
+        });
+
+        let location = locate_device(&format!("uuid:{UUID}"), &options)
+            .await
+            .unwrap();
+        assert_eq!(location.as_deref(), Some("http://10.0.0.7:49152/desc.xml"));
+        assert_eq!(
+            SearchTarget::from(format!("uuid:{UUID}").as_str()),
+            SearchTarget::Uuid(UUID.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_devices_records_origin() {
+        let server = mock_device(vec![], |_| unreachable!()).await;
+        let location = server.url("/description.xml");
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let responder_addr = responder.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (_, from) = responder.recv_from(&mut buf).await.unwrap();
+            let response =
+                format!("HTTP/1.1 200 OK\r\nLOCATION: {location}\r\nST: upnp:rootdevice\r\n\r\n");
+            responder.send_to(response.as_bytes(), from).await.unwrap();
+        });
+
+        let options = DiscoveryOptions {
+            timeout: Duration::from_millis(500),
+            address: responder_addr,
+            ..Default::default()
+        };
+        let devices = discover_devices(&options).await.unwrap().devices;
+
+        assert_eq!(devices.len(), 1);
+        let origin = devices[0].discovered_via.unwrap();
+        assert_eq!(origin.remote_addr, responder_addr);
+        assert_eq!(origin.local_if, Some(responder_addr.ip()));
+    }
+
+    #[test]
+    fn test_discovery_origin_without_route() {
+        // Sending to the broadcast address needs `SO_BROADCAST`, so there is no route.
+        let remote_addr = SocketAddr::from(([255, 255, 255, 255], 1900));
+        let mut warnings = Vec::new();
+
+        let origin = discovery_origin(remote_addr, &mut warnings);
+        assert_eq!(origin.local_if, None);
+        assert_eq!(origin.remote_addr, remote_addr);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("255.255.255.255:1900"));
+    }
+
+    #[tokio::test]
+    async fn test_discover_devices_within_deadline() {
+        let service = || MockService::new("AVTransport", &["Play"]);
+        let fast = mock_device(vec![service()], |_| unreachable!()).await;
+        // The slow device's SCPD is served by a listener that never answers.
+        let black_hole = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let description = device_description(&[service()]).replace(
+            "/AVTransport/scpd.xml",
+            &format!("http://{}/scpd.xml", black_hole.local_addr().unwrap()),
+        );
+        let slow = MockServer::start(move |_| MockResponse::ok(description.clone())).await;
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let responder_addr = responder.local_addr().unwrap();
+        let locations = [fast.url("/description.xml"), slow.url("/description.xml")];
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (_, from) = responder.recv_from(&mut buf).await.unwrap();
+            for location in locations {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nLOCATION: {location}\r\nST: upnp:rootdevice\r\n\r\n"
+                );
+                responder.send_to(response.as_bytes(), from).await.unwrap();
+            }
+        });
+
+        let options = DiscoveryOptions {
+            timeout: Duration::from_millis(500),
+            address: responder_addr,
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let discovered = discover_devices(&options).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(discovered.devices.len(), 2);
+        let fast_device = discovered
+            .devices
+            .iter()
+            .find(|device| device.location == fast.url("/description.xml"))
+            .unwrap();
+        assert_eq!(fast_device.services[0].actions.len(), 1);
+        let slow_device = discovered
+            .devices
+            .iter()
+            .find(|device| device.location == slow.url("/description.xml"))
+            .unwrap();
+        assert_eq!(slow_device.services.len(), 1);
+        assert!(slow_device.services[0].actions.is_empty());
+        assert_eq!(discovered.warnings.len(), 1);
+        assert!(discovered.warnings[0].contains(&slow.url("/description.xml")));
+    }
+
+    #[tokio::test]
+    async fn test_discover_devices_of_a_type() {
+        let renderer = mock_device(vec![], |_| unreachable!()).await;
+        let gateway_description = device_description(&[]).replace(
+            "urn:schemas-upnp-org:device:MediaRenderer:1",
+            "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+        );
+        let other_gateway_description = gateway_description.clone();
+        let gateway =
+            MockServer::start(move |_| MockResponse::ok(gateway_description.clone())).await;
+        // Answering every search as a root device, its description has to be fetched.
+        let other_gateway =
+            MockServer::start(move |_| MockResponse::ok(other_gateway_description.clone())).await;
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let responder_addr = responder.local_addr().unwrap();
+        let answers = [
+            (
+                gateway.url("/description.xml"),
+                "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+            ),
+            (other_gateway.url("/description.xml"), "upnp:rootdevice"),
+            (
+                renderer.url("/description.xml"),
+                "urn:schemas-upnp-org:device:MediaRenderer:1",
+            ),
+        ];
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (_, from) = responder.recv_from(&mut buf).await.unwrap();
+            for (location, st) in answers {
+                let response =
+                    format!("HTTP/1.1 200 OK\r\nLOCATION: {location}\r\nST: {st}\r\n\r\n");
+                responder.send_to(response.as_bytes(), from).await.unwrap();
+            }
+        });
+
+        let options = DiscoveryOptions {
+            search_target: "urn:schemas-upnp-org:device:MediaRenderer:1".into(),
+            timeout: Duration::from_millis(500),
+            address: responder_addr,
+            ..Default::default()
+        };
+        let discovered = discover_devices(&options).await.unwrap();
+
+        let locations = discovered
+            .devices
+            .iter()
+            .map(|device| device.location.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [renderer.url("/description.xml")]);
+        assert!(discovered.warnings.is_empty());
+        assert!(gateway.requests().is_empty());
+        assert_eq!(other_gateway.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_devices() {
+        let renderer = mock_device(
+            vec![MockService::new("AVTransport", &["Play"])],
+            |_| unreachable!(),
+        )
+        .await;
+        let missing = MockServer::start(|_| MockResponse {
+            status: 404,
+            ..MockResponse::ok("")
+        })
+        .await;
+
+        let results = resolve_devices(&[
+            missing.url("/description.xml"),
+            renderer.url("/description.xml"),
+        ])
+        .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[0],
+            Err(UpnpError::Http {
+                status: Some(404),
+                ..
+            })
+        ));
+        let device = results[1].as_ref().unwrap();
+        assert_eq!(device.location, renderer.url("/description.xml"));
+        assert_eq!(device.services[0].actions[0].name, "Play");
+    }
+
+    #[tokio::test]
+    async fn test_discover_all() {
+        let service = || MockService::new("AVTransport", &["Play"]);
+        // The same device, reachable at two locations.
+        let first = mock_device(vec![service()], |_| unreachable!()).await;
+        let second = mock_device(vec![service()], |_| unreachable!()).await;
+        let missing = MockServer::start(|_| MockResponse {
+            status: 404,
+            ..MockResponse::ok("")
+        })
+        .await;
+        let locations = [&first, &second, &missing].map(|server| server.url("/description.xml"));
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = responder.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (size, from) = responder.recv_from(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..size]).to_string();
+            assert!(request.contains("ST: urn:schemas-upnp-org:service:AVTransport:1\r\n"));
+            for location in locations {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nLOCATION: {location}\r\nST: upnp:rootdevice\r\n\r\n"
+                );
+                responder.send_to(response.as_bytes(), from).await.unwrap();
+            }
+        });
+
+        let devices = discover_all_at(
+            "urn:schemas-upnp-org:service:AVTransport:1".into(),
+            Duration::from_millis(500),
+            address,
+        )
+        .await
+        .unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id(), "00000000-0000-0000-0000-000000000001");
+        assert_eq!(devices[0].services[0].actions[0].name, "Play");
+        assert_eq!(missing.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_cancelled() {
+        // Never answers the M-SEARCH.
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = DiscoveryOptions {
+            timeout: Duration::from_secs(30),
+            address: silent.local_addr().unwrap(),
+            ..Default::default()
+        };
+        let cancel = options.cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+
+        let started = Instant::now();
+        let error = discover_devices(&options).await.unwrap_err();
+        assert!(matches!(error, UpnpError::Cancelled));
+        let locations = discover_locations(&options)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(locations.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    const BYEBYE: &str = "NOTIFY * HTTP/1.1\r\n\
+        HOST: 239.255.255.250:1900\r\n\
+        NT: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+        NTS: ssdp:byebye\r\n\
+        USN: uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+        \r\n";
+
+    #[test]
+    fn test_parse_ssdp_notify() {
+        assert_eq!(
+            parse_ssdp_notify(BYEBYE).unwrap(),
+            SsdpEvent::ByeBye {
+                nt: "urn:schemas-upnp-org:device:MediaRenderer:1".to_string(),
+                usn: "uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::urn:schemas-upnp-org:device:MediaRenderer:1".to_string(),
+            }
+        );
+
+        let alive = BYEBYE.replace("ssdp:byebye", "ssdp:alive").replace(
+            "HOST: 239.255.255.250:1900\r\n",
+            "HOST: 239.255.255.250:1900\r\nLOCATION: http://192.168.1.20:49152/description.xml\r\nCACHE-CONTROL: max-age=1800\r\n",
+        );
+        assert!(matches!(
+            parse_ssdp_notify(&alive).unwrap(),
+            SsdpEvent::Alive { location, max_age: Some(1800), .. }
+                if location == "http://192.168.1.20:49152/description.xml"
+        ));
+
+        assert!(parse_ssdp_notify(SSDP_RESPONSE).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_listen_notifications() {
+        let address = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let events = listen_notifications(address).unwrap();
+        tokio::pin!(events);
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // Anything that isn't a NOTIFY, such as another control point's M-SEARCH, is skipped.
+        sender
+            .send_to(b"M-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n\r\n", address)
+            .await
+            .unwrap();
+        sender.send_to(BYEBYE.as_bytes(), address).await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(1), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            event,
+            SsdpEvent::ByeBye { usn, .. }
+                if usn == "uuid:4d696e69-444c-164e-9d41-b827eb54e0b3::urn:schemas-upnp-org:device:MediaRenderer:1"
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+use thiserror::Error;
+
+/// The errors of the public API, structured enough for callers to tell failures apart,
+/// e.g. to retry timeouts but not malformed descriptions.
+#[derive(Debug, Error)]
+pub enum UpnpError {
+    /// The request could not be sent, or the device answered it with an error status.
+    /// `status` is `None` when no response was received.
+    #[error("Request to {url} failed: {message}")]
+    Http {
+        url: String,
+        status: Option<u16>,
+        message: String,
+    },
+    /// The device didn't answer in time.
+    #[error("Request to {url} timed out")]
+    Timeout { url: String },
+    /// The operation was aborted through its cancellation token.
+    #[error("Cancelled")]
+    Cancelled,
+    /// A document isn't well-formed XML.
+    #[error("Malformed XML: {0}")]
+    XmlParse(String),
+    /// A document lacks an element the specifications require.
+    #[error("Missing element {name}")]
+    MissingElement { name: String },
+    /// A URL, given or advertised by a device, cannot be parsed.
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// The renderer's `Sink` protocols do not include the content format of a URI.
+    #[error("Renderer does not accept {protocol_info}")]
+    IncompatibleFormat { protocol_info: String },
+    /// The service description does not advertise the action.
+    #[error("Action {action} not found on {service_id}")]
+    ActionNotSupported { service_id: String, action: String },
+    /// A description or SCPD exceeds the budgets of
+    /// [`DescriptionLimits`](crate::parser::DescriptionLimits).
+    #[error("Description at {url} exceeds {limit}")]
+    DescriptionTooComplex { url: String, limit: String },
+    /// A response body is larger than `limit` bytes, and was abandoned rather than read
+    /// in full.
+    #[error("Response from {url} exceeds {limit} bytes")]
+    BodyTooLarge { url: String, limit: usize },
+    /// The device answered an action with a SOAP fault. `code` is the `errorCode` of its
+    /// `UPnPError` detail, 0 when the fault has none.
+    #[error("Action failed with fault {code}: {description}")]
+    SoapFault { code: u32, description: String },
+    /// Any other failure, such as a value of a document that cannot be parsed.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// The crate works with [`anyhow::Error`] internally and converts at the boundary of the
+/// public API, recovering the variants the error was built from or wraps.
+impl From<anyhow::Error> for UpnpError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Self>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        if error.is::<xml::reader::Error>() || error.is::<elementtree::Error>() {
+            Self::XmlParse(error.to_string())
+        } else if error.is::<url::ParseError>() {
+            Self::InvalidUrl(error.to_string())
+        } else {
+            Self::Other(error)
+        }
+    }
+}
+
+impl From<url::ParseError> for UpnpError {
+    fn from(error: url::ParseError) -> Self {
+        Self::InvalidUrl(error.to_string())
+    }
+}
+
+impl From<elementtree::Error> for UpnpError {
+    fn from(error: elementtree::Error) -> Self {
+        Self::XmlParse(error.to_string())
+    }
+}
+
+impl From<xml::reader::Error> for UpnpError {
+    fn from(error: xml::reader::Error) -> Self {
+        Self::XmlParse(error.to_string())
+    }
+}
+
+impl From<std::convert::Infallible> for UpnpError {
+    fn from(error: std::convert::Infallible) -> Self {
+        match error {}
+    }
+}
+
+/// Converts the errors that have no variant of their own into [`UpnpError::Other`].
+macro_rules! from_other {
+    ($($error:ty),* $(,)?) => {$(
+        impl From<$error> for UpnpError {
+            fn from(error: $error) -> Self {
+                Self::Other(error.into())
+            }
+        }
+    )*};
+}
+
+from_other!(
+    std::io::Error,
+    std::num::ParseIntError,
+    std::string::FromUtf8Error,
+    std::str::Utf8Error,
+);
+#[cfg(feature = "client")]
+from_other!(hyper::http::Error);
+
+#[cfg(feature = "client")]
+impl UpnpError {
+    /// The error of a request to `url` that got no response, timeouts aside.
+    pub(crate) fn request_failed(url: &str, error: &impl std::fmt::Display) -> Self {
+        Self::Http {
+            url: url.to_string(),
+            status: None,
+            message: error.to_string(),
+        }
+    }
+
+    /// The error of a request to `url` answered with the non-success `status`.
+    pub(crate) fn status(url: &str, status: u16) -> Self {
+        Self::Http {
+            url: url.to_string(),
+            status: Some(status),
+            message: format!("HTTP {status}"),
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! GENA event subscriptions: the `SUBSCRIBE` requests and the server receiving the
+//! `NOTIFY` requests that follow.
+
+use std::{
+    collections::HashSet,
+    env,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::{stream, Stream};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Request, Response, Server, StatusCode,
+};
+use surf::Url;
+use tokio::sync::{mpsc::unbounded_channel, watch};
+
+use crate::{
+    discovery::local_addr_for,
+    error::UpnpError,
+    parser::parse_notify_message,
+    types::{NotifyMessage, Service, Subscription},
+};
+
+/// The subscription duration asked for; devices are free to grant another one.
+const REQUESTED_TIMEOUT: &str = "Second-1800";
+
+/// How long a `SUBSCRIBE` or `UNSUBSCRIBE` request may take, as for control requests.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a `NOTIFY` for an unknown SID waits for its subscription to be accepted, as
+/// the initial event may arrive before the `SUBSCRIBE` response has been processed.
+const UNKNOWN_SID_GRACE: Duration = Duration::from_secs(1);
+
+/// A `NOTIFY` request received for an event subscription.
+pub(crate) struct Notification {
+    pub sid: String,
+    pub seq: String,
+    pub body: String,
+}
+
+/// The SIDs a callback accepts `NOTIFY` requests for; requests carrying any other SID are
+/// refused.
+#[derive(Clone)]
+pub(crate) struct AcceptedSids(Arc<watch::Sender<HashSet<String>>>);
+
+impl AcceptedSids {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::channel(HashSet::new()).0))
+    }
+
+    pub fn insert(&self, sid: &str) {
+        self.0.send_modify(|sids| {
+            sids.insert(sid.to_string());
+        });
+    }
+
+    pub fn remove(&self, sid: &str) {
+        self.0.send_modify(|sids| {
+            sids.remove(sid);
+        });
+    }
+
+    /// Whether `sid` is accepted, waiting [`UNKNOWN_SID_GRACE`] for it to be inserted.
+    async fn accepts(&self, sid: &str) -> bool {
+        let mut sids = self.0.subscribe();
+        tokio::time::timeout(UNKNOWN_SID_GRACE, sids.wait_for(|sids| sids.contains(sid)))
+            .await
+            .is_ok_and(|accepted| accepted.is_ok())
+    }
+}
+
+impl Service {
+    /// Subscribes to the events of the service, returning the subscription along with the
+    /// events the device sends for it, in the order they are received.
+    ///
+    /// The events are received by a server listening on the interface the device is
+    /// reachable through, which stops once the stream is dropped. The subscription has to
+    /// be renewed with [`Self::renew_subscription`] before its timeout elapses.
+    pub async fn subscribe(
+        &self,
+    ) -> Result<(Subscription, impl Stream<Item = NotifyMessage>), UpnpError> {
+        let (tx, rx) = unbounded_channel();
+        let shutdown = tx.clone();
+        let sids = AcceptedSids::new();
+        let callback = listen(
+            event_addr(&self.event_sub_url)?,
+            sids.clone(),
+            move |notification| {
+                if let Ok(message) =
+                    parse_notify_message(&notification.sid, &notification.seq, &notification.body)
+                {
+                    let _ = tx.send(message);
+                }
+            },
+            async move { shutdown.closed().await },
+        )?;
+        let subscription = subscribe(&self.event_sub_url, &callback).await?;
+        sids.insert(&subscription.sid);
+
+        let events = stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|message| (message, rx))
+        });
+        Ok((subscription, events))
+    }
+
+    /// Extends the subscription `sid` before it expires. The returned subscription carries
+    /// the timeout granted this time, after which it has to be renewed again.
+    pub async fn renew_subscription(&self, sid: &str) -> Result<Subscription, UpnpError> {
+        let req = Request::builder()
+            .method("SUBSCRIBE")
+            .uri(&self.event_sub_url)
+            .header("SID", sid)
+            .header("TIMEOUT", REQUESTED_TIMEOUT)
+            .body(Body::empty())?;
+        let mut subscription = send_subscribe(req).await?;
+        if subscription.sid.is_empty() {
+            subscription.sid = sid.to_string();
+        }
+        Ok(subscription)
+    }
+
+    /// Cancels the subscription `sid`, so the device stops sending its events.
+    pub async fn unsubscribe(&self, sid: &str) -> Result<(), UpnpError> {
+        unsubscribe(&self.event_sub_url, sid).await
+    }
+}
+
+/// Subscribes `callback` to the events published at `event_sub_url`.
+pub(crate) async fn subscribe(event_sub_url: &str, callback: &str) -> Result<Subscription> {
+    let user_agent = format!(
+        "upnp-client/{} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS
+    );
+    let req = Request::builder()
+        .method("SUBSCRIBE")
+        .uri(event_sub_url)
+        .header("CALLBACK", format!("<{callback}>"))
+        .header("NT", "upnp:event")
+        .header("TIMEOUT", REQUESTED_TIMEOUT)
+        .header("USER-AGENT", user_agent)
+        .body(Body::empty())?;
+    send_subscribe(req).await
+}
+
+/// Cancels the subscription `sid` to the events published at `event_sub_url`.
+pub(crate) async fn unsubscribe(event_sub_url: &str, sid: &str) -> Result<(), UpnpError> {
+    let req = Request::builder()
+        .method("UNSUBSCRIBE")
+        .uri(event_sub_url)
+        .header("SID", sid)
+        .body(Body::empty())?;
+    let res = request(req, REQUEST_TIMEOUT).await?;
+    if !res.status().is_success() {
+        return Err(UpnpError::status(event_sub_url, res.status().as_u16()));
+    }
+    Ok(())
+}
+
+async fn send_subscribe(req: Request<Body>) -> Result<Subscription> {
+    let uri = req.uri().to_string();
+    let res = request(req, REQUEST_TIMEOUT).await?;
+    if !res.status().is_success() {
+        return Err(UpnpError::status(&uri, res.status().as_u16()).into());
+    }
+    let header = |name| header_str(res.headers(), name);
+    Ok(Subscription {
+        sid: header("SID").to_string(),
+        timeout: parse_timeout(header("TIMEOUT")),
+    })
+}
+
+/// Sends `req`, failing with [`UpnpError::Timeout`] if no response arrives within `timeout`.
+async fn request(req: Request<Body>, timeout: Duration) -> Result<Response<Body>, UpnpError> {
+    let uri = req.uri().to_string();
+    tokio::time::timeout(timeout, hyper::Client::new().request(req))
+        .await
+        .map_err(|_| UpnpError::Timeout { url: uri.clone() })?
+        .map_err(|e| UpnpError::request_failed(&uri, &e))
+}
+
+/// Parses a `TIMEOUT` header, `Second-1800` or `Second-infinite`. Devices that omit it are
+/// assumed to grant the 1800 seconds the specification recommends.
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("Second-infinite") {
+        return None;
+    }
+    let seconds = value
+        .get(..7)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("Second-"))
+        .and_then(|_| value[7..].parse().ok())
+        .unwrap_or(1800);
+    Some(Duration::from_secs(seconds))
+}
+
+/// Serves the `NOTIFY` requests of event subscriptions, passing each whose SID is in `sids`
+/// to `on_notify`, until `shutdown` completes, and returns the callback URL to subscribe
+/// with.
+///
+/// The server listens on the address family of the interface `device_addr` is reachable
+/// through, so IPv6 devices get an IPv6 callback.
+pub(crate) fn listen<F>(
+    device_addr: SocketAddr,
+    sids: AcceptedSids,
+    on_notify: F,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<String>
+where
+    F: Fn(Notification) + Clone + Send + Sync + 'static,
+{
+    let service = make_service_fn(move |_| {
+        let on_notify = on_notify.clone();
+        let sids = sids.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                let on_notify = on_notify.clone();
+                let sids = sids.clone();
+                async move {
+                    let sid = header_str(req.headers(), "SID").to_string();
+                    if !sids.accepts(&sid).await {
+                        return Ok(Response::builder()
+                            .status(StatusCode::PRECONDITION_FAILED)
+                            .body(Body::empty())
+                            .unwrap_or_default());
+                    }
+                    let seq = header_str(req.headers(), "SEQ").to_string();
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+                    on_notify(Notification {
+                        sid,
+                        seq,
+                        body: String::from_utf8_lossy(&body).into_owned(),
+                    });
+                    Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+    let local_addr = local_addr_for(device_addr)?;
+    let any = match local_addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let server = Server::try_bind(&SocketAddr::new(any, 0))?.serve(service);
+    let callback = format!(
+        "http://{}/",
+        SocketAddr::new(local_addr, server.local_addr().port())
+    );
+    tokio::spawn(server.with_graceful_shutdown(shutdown));
+    Ok(callback)
+}
+
+/// The address of the device publishing events at `event_sub_url`.
+fn event_addr(event_sub_url: &str) -> Result<SocketAddr> {
+    Url::parse(event_sub_url)?
+        .socket_addrs(|| None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {event_sub_url}"))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+    use hyper::{Body, Request};
+    use tokio::net::TcpListener;
+    use xml::escape::escape_str_pcdata;
+
+    use crate::{
+        error::UpnpError,
+        eventing::{listen, parse_timeout, request, AcceptedSids},
+        parser::parse_location,
+        test_utils::{mock_device, MockResponse, MockService, RecordedRequest},
+        types::{StateChange, Subscription},
+    };
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("Second-300"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_timeout("second-300"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_timeout("Second-infinite"), None);
+        assert_eq!(parse_timeout(""), Some(Duration::from_secs(1800)));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        // Accepts the connection but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/event", listener.local_addr().unwrap());
+        let _held = tokio::spawn(async move { listener.accept().await });
+
+        let req = Request::builder()
+            .method("UNSUBSCRIBE")
+            .uri(&url)
+            .header("SID", "uuid:sub-1")
+            .body(Body::empty())
+            .unwrap();
+        let error = request(req, Duration::from_millis(50)).await.unwrap_err();
+        assert!(matches!(error, UpnpError::Timeout { url: timed_out } if timed_out == url));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let server = mock_device(vec![MockService::new("RenderingControl", &[])], |req| {
+            if req.path.ends_with("/event") {
+                MockResponse {
+                    headers: vec![
+                        ("SID".to_string(), "uuid:sub-7".to_string()),
+                        ("TIMEOUT".to_string(), "Second-300".to_string()),
+                    ],
+                    ..MockResponse::ok("")
+                }
+            } else {
+                MockResponse::ok("")
+            }
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let (subscription, events) = device.services[0].subscribe().await.unwrap();
+        assert_eq!(
+            subscription,
+            Subscription {
+                sid: "uuid:sub-7".to_string(),
+                timeout: Some(Duration::from_secs(300)),
+            }
+        );
+
+        let subscribe = server.requests().pop().unwrap();
+        assert_eq!(
+            subscribe.headers.get("nt").map(String::as_str),
+            Some("upnp:event")
+        );
+        let callback = subscribe.headers.get("callback").unwrap();
+        let callback = callback.trim_matches(|c| c == '<' || c == '>');
+        let stranger = hyper::Request::builder()
+            .method("NOTIFY")
+            .uri(callback)
+            .header("NT", "upnp:event")
+            .header("NTS", "upnp:propchange")
+            .header("SID", "uuid:someone-else")
+            .header("SEQ", "0")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let response = hyper::Client::new().request(stranger).await.unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::PRECONDITION_FAILED);
+
+        let last_change = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/"><InstanceID val="0"><Volume channel="Master" val="12"/><Mute channel="Master" val="1"/></InstanceID></Event>"#;
+        let notify = hyper::Request::builder()
+            .method("NOTIFY")
+            .uri(callback)
+            .header("NT", "upnp:event")
+            .header("NTS", "upnp:propchange")
+            .header("SID", "uuid:sub-7")
+            .header("SEQ", "3")
+            .body(hyper::Body::from(format!(
+                r#"<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0"><e:property><LastChange>{}</LastChange></e:property></e:propertyset>"#,
+                escape_str_pcdata(last_change)
+            )))
+            .unwrap();
+        hyper::Client::new().request(notify).await.unwrap();
+
+        let mut events = Box::pin(events);
+        let message = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.sid, "uuid:sub-7");
+        assert_eq!(message.seq, 3);
+        assert_eq!(
+            message.event.changes,
+            vec![
+                StateChange {
+                    instance_id: 0,
+                    name: "Volume".to_string(),
+                    channel: Some("Master".to_string()),
+                    value: "12".to_string(),
+                },
+                StateChange {
+                    instance_id: 0,
+                    name: "Mute".to_string(),
+                    channel: Some("Master".to_string()),
+                    value: "1".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listen_ipv6() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let shutdown = tx.clone();
+        let sids = AcceptedSids::new();
+        sids.insert("uuid:sub-1");
+        let callback = listen(
+            "[::1]:1900".parse().unwrap(),
+            sids,
+            move |notification| {
+                let _ = tx.send(notification.sid);
+            },
+            async move { shutdown.closed().await },
+        )
+        .unwrap();
+        assert!(callback.starts_with("http://[::1]:"), "{callback}");
+
+        let notify = hyper::Request::builder()
+            .method("NOTIFY")
+            .uri(&callback)
+            .header("SID", "uuid:sub-1")
+            .header("SEQ", "0")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let response = hyper::Client::new().request(notify).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(rx.recv().await.unwrap(), "uuid:sub-1");
+    }
+
+    #[tokio::test]
+    async fn test_renew_and_unsubscribe() {
+        let server = mock_device(vec![MockService::new("AVTransport", &[])], |req| {
+            let timeout = match req.method.as_str() {
+                "SUBSCRIBE" if req.headers.contains_key("sid") => "Second-600",
+                _ => "Second-300",
+            };
+            MockResponse {
+                headers: vec![
+                    ("SID".to_string(), "uuid:sub-9".to_string()),
+                    ("TIMEOUT".to_string(), timeout.to_string()),
+                ],
+                ..MockResponse::ok("")
+            }
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        let service = &device.services[0];
+
+        let (subscription, _events) = service.subscribe().await.unwrap();
+        let renewed = service.renew_subscription(&subscription.sid).await.unwrap();
+        assert_eq!(
+            renewed,
+            Subscription {
+                sid: "uuid:sub-9".to_string(),
+                timeout: Some(Duration::from_secs(600)),
+            }
+        );
+        service.unsubscribe(&subscription.sid).await.unwrap();
+
+        let requests = server
+            .requests()
+            .into_iter()
+            .filter(|req| req.path == "/AVTransport/event")
+            .collect::<Vec<_>>();
+        let [subscribe, renew, unsubscribe] = requests.as_slice() else {
+            panic!("expected 3 requests, got {}", requests.len());
+        };
+        let header = |req: &RecordedRequest, name| req.headers.get(name).cloned();
+
+        assert_eq!(subscribe.method, "SUBSCRIBE");
+        assert_eq!(header(subscribe, "nt").as_deref(), Some("upnp:event"));
+        assert!(header(subscribe, "callback").is_some());
+        assert_eq!(header(subscribe, "sid"), None);
+
+        assert_eq!(renew.method, "SUBSCRIBE");
+        assert_eq!(header(renew, "sid").as_deref(), Some("uuid:sub-9"));
+        assert_eq!(header(renew, "timeout").as_deref(), Some("Second-1800"));
+        assert_eq!(header(renew, "nt"), None);
+        assert_eq!(header(renew, "callback"), None);
+
+        assert_eq!(unsubscribe.method, "UNSUBSCRIBE");
+        assert_eq!(header(unsubscribe, "sid").as_deref(), Some("uuid:sub-9"));
+        assert_eq!(header(unsubscribe, "timeout"), None);
+        assert_eq!(header(unsubscribe, "nt"), None);
+        assert_eq!(header(unsubscribe, "callback"), None);
+    }
+}
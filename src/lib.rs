@@ -1,18 +1,45 @@
 #![warn(clippy::all, clippy::nursery, clippy::pedantic, clippy::cargo)]
 #![allow(clippy::missing_errors_doc)]
 
+#[cfg(feature = "client")]
+pub mod av_transport;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "client")]
+pub mod cache;
+#[cfg(feature = "client")]
+pub mod connection_manager;
+#[cfg(feature = "client")]
+pub mod content_directory;
+#[cfg(feature = "client")]
 pub mod device_client;
+pub mod didl;
+#[cfg(feature = "client")]
 pub mod discovery;
+pub mod error;
+#[cfg(feature = "client")]
+pub mod eventing;
+#[cfg(feature = "client")]
 pub mod media_renderer;
+#[cfg(feature = "client")]
 pub mod media_server;
 pub mod parser;
+pub mod quirks;
+#[cfg(feature = "client")]
+pub mod rendering_control;
+#[cfg(feature = "client")]
+pub mod snapshot;
+#[cfg(feature = "client")]
+pub mod soap;
+#[cfg(feature = "client")]
+pub mod transport;
 pub mod types;
 
-use std::sync::{mpsc::Sender, Mutex};
+#[cfg(all(test, feature = "client"))]
+mod test_utils;
 
-use lazy_static::lazy_static;
-use types::Event;
-
-lazy_static! {
-    static ref BROADCAST_EVENT: Mutex<Option<Sender<Event>>> = Mutex::new(None);
+#[cfg(feature = "client")]
+lazy_static::lazy_static! {
+    static ref BROADCAST_EVENT: std::sync::Mutex<Option<std::sync::mpsc::Sender<types::Event>>> =
+        std::sync::Mutex::new(None);
 }
@@ -1,20 +1,36 @@
-use std::{collections::HashMap, sync::mpsc};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
 
-use anyhow::{Error, Ok};
+use anyhow::Error;
 use async_stream::stream;
 use futures_util::Stream;
-use xml_builder::{XMLBuilder, XMLElement};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
 use crate::{
     device_client::DeviceClient,
+    didl,
+    error::UpnpError,
+    eventing::{self, AcceptedSids},
     parser::{
-        parse_duration, parse_position, parse_supported_protocols, parse_transport_info,
-        parse_volume,
+        format_time, parse_duration, parse_last_change, parse_position, parse_protocol_info,
+        parse_renderer_events, parse_supported_protocols, parse_track_duration,
+        parse_transport_info, parse_volume,
+    },
+    types::{
+        Event, LoadOptions, ObjectClass, ProtocolInfo, ProtocolInfoEntry, RendererEvent, Service,
+        Subscription, TransportInfo,
     },
-    types::{Event, LoadOptions, Metadata, ObjectClass, TransportInfo},
     BROADCAST_EVENT,
 };
 
+/// How often services that refuse event subscriptions are polled by
+/// [`MediaRendererClient::events`].
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 pub enum MediaEvents {
     Status,
     Loading,
@@ -27,19 +43,29 @@ pub enum MediaEvents {
 #[derive(Clone)]
 pub struct MediaRendererClient {
     device_client: DeviceClient,
+    sink_protocols: Arc<Mutex<Option<Vec<ProtocolInfoEntry>>>>,
 }
 
 impl MediaRendererClient {
     #[must_use]
-    pub const fn new(device_client: DeviceClient) -> Self {
-        Self { device_client }
+    pub fn new(device_client: DeviceClient) -> Self {
+        Self {
+            device_client,
+            sink_protocols: Arc::default(),
+        }
     }
-    pub async fn load(&self, url: &str, options: LoadOptions) -> Result<(), Error> {
+    pub async fn load(&self, url: &str, options: LoadOptions) -> Result<(), UpnpError> {
         let dlna_features = options.dlna_features.unwrap_or("*".to_string());
         let content_type = options.content_type.unwrap_or("video/mpeg".to_string());
-        let protocol_info = format!("http-get:*:{content_type}:{dlna_features}");
+        let protocol_info = self
+            .device_client
+            .quirks()
+            .protocol_info(&format!("http-get:*:{content_type}:{dlna_features}"));
         let mut metadata = options.metadata.unwrap_or_default();
         metadata.url = url.to_string();
+        if options.validate_protocol {
+            self.ensure_playable(&protocol_info).await?;
+        }
         metadata.protocol_info = protocol_info;
 
         let mut params = HashMap::new();
@@ -47,7 +73,13 @@ impl MediaRendererClient {
         params.insert("CurrentURI".to_string(), url.to_string());
         params.insert(
             "CurrentURIMetaData".to_string(),
-            build_metadata(metadata, options.object_class.unwrap_or(ObjectClass::Video)),
+            didl::metadata_didl(
+                &metadata,
+                &options
+                    .object_class
+                    .clone()
+                    .unwrap_or_else(|| ObjectClass::from("object.item.videoItem")),
+            ),
         );
         self.device_client
             .call_action("AVTransport", "SetAVTransportURI", params)
@@ -60,7 +92,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn play(&self) -> Result<(), Error> {
+    pub async fn play(&self) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         params.insert("Speed".to_string(), "1".to_string());
@@ -70,7 +102,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn pause(&self) -> Result<(), Error> {
+    pub async fn pause(&self) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         self.device_client
@@ -79,7 +111,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn seek(&self, seconds: u64) -> Result<(), Error> {
+    pub async fn seek(&self, seconds: u64) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         params.insert("Unit".to_string(), "REL_TIME".to_string());
@@ -90,7 +122,24 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn stop(&self) -> Result<(), Error> {
+    /// Seeks `delta` seconds forward (or backward when negative) from the current position,
+    /// clamped to the bounds of the track, and returns the position sought to.
+    pub async fn seek_relative(&self, delta: i64) -> Result<u64, UpnpError> {
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        let response = self
+            .device_client
+            .call_action("AVTransport", "GetPositionInfo", params)
+            .await?;
+        let position = parse_position(response.as_str())?;
+        let duration = parse_track_duration(response.as_str())?;
+
+        let target = relative_seek_target(position.into(), duration.map(Into::into), delta);
+        self.seek(target).await?;
+        Ok(target)
+    }
+
+    pub async fn stop(&self) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         self.device_client
@@ -99,7 +148,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn next(&self) -> Result<(), Error> {
+    pub async fn next(&self) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         self.device_client
@@ -108,7 +157,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn previous(&self) -> Result<(), Error> {
+    pub async fn previous(&self) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         self.device_client
@@ -117,12 +166,26 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn set_next(&self, url: &str, options: LoadOptions) -> Result<(), Error> {
+    /// Queues `url` to play after the current track. Fails without contacting renderers
+    /// known to mishandle `SetNextAVTransportURI`.
+    pub async fn set_next(&self, url: &str, options: LoadOptions) -> Result<(), UpnpError> {
+        if self.device_client.quirks().no_next_uri {
+            return Err(UpnpError::ActionNotSupported {
+                service_id: "urn:upnp-org:serviceId:AVTransport".to_string(),
+                action: "SetNextAVTransportURI".to_string(),
+            });
+        }
         let dlna_features = options.dlna_features.unwrap_or("*".to_string());
         let content_type = options.content_type.unwrap_or("video/mpeg".to_string());
-        let protocol_info = format!("http-get:*:{content_type}:{dlna_features}");
+        let protocol_info = self
+            .device_client
+            .quirks()
+            .protocol_info(&format!("http-get:*:{content_type}:{dlna_features}"));
         let mut metadata = options.metadata.unwrap_or_default();
         metadata.url = url.to_string();
+        if options.validate_protocol {
+            self.ensure_playable(&protocol_info).await?;
+        }
         metadata.protocol_info = protocol_info;
 
         let mut params = HashMap::new();
@@ -130,7 +193,13 @@ impl MediaRendererClient {
         params.insert("NextURI".to_string(), url.to_string());
         params.insert(
             "NextURIMetaData".to_string(),
-            build_metadata(metadata, options.object_class.unwrap_or(ObjectClass::Video)),
+            didl::metadata_didl(
+                &metadata,
+                &options
+                    .object_class
+                    .clone()
+                    .unwrap_or_else(|| ObjectClass::from("object.item.videoItem")),
+            ),
         );
         self.device_client
             .call_action("AVTransport", "SetNextAVTransportURI", params)
@@ -138,7 +207,7 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn get_volume(&self) -> Result<u8, Error> {
+    pub async fn get_volume(&self) -> Result<u8, UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         params.insert("Channel".to_string(), "Master".to_string());
@@ -148,10 +217,10 @@ impl MediaRendererClient {
             .call_action("RenderingControl", "GetVolume", params)
             .await?;
 
-        Ok(parse_volume(response.as_str())?)
+        parse_volume(response.as_str())
     }
 
-    pub async fn set_volume(&self, volume: u32) -> Result<(), Error> {
+    pub async fn set_volume(&self, volume: u32) -> Result<(), UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         params.insert("Channel".to_string(), "Master".to_string());
@@ -162,34 +231,89 @@ impl MediaRendererClient {
         Ok(())
     }
 
-    pub async fn get_supported_protocols(&self) -> Result<Vec<String>, Error> {
+    /// Changes the volume by `delta`, clamped to `[0, 100]`, and returns the new volume.
+    /// Fails without setting anything when the current volume can't be read.
+    pub async fn set_volume_relative(&self, delta: i16) -> Result<u8, UpnpError> {
+        let volume = relative_volume(self.get_volume().await?, delta);
+        self.set_volume(volume.into()).await?;
+        Ok(volume)
+    }
+
+    pub async fn get_supported_protocols(&self) -> Result<Vec<String>, UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("ConnectionManager", "GetProtocolInfo", params)
             .await?;
-        Ok(parse_supported_protocols(response.as_str())?)
+        parse_supported_protocols(response.as_str())
+    }
+
+    /// Fetches the renderer's `Source` and `Sink` protocols, keeping the sink list for
+    /// [`Self::can_play`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sink list lock was poisoned.
+    pub async fn get_protocol_info(&self) -> Result<ProtocolInfo, UpnpError> {
+        let response = self
+            .device_client
+            .call_action("ConnectionManager", "GetProtocolInfo", HashMap::new())
+            .await?;
+        let protocol_info = parse_protocol_info(response.as_str())?;
+        *self.sink_protocols.lock().unwrap() = Some(protocol_info.sink.clone());
+        Ok(protocol_info)
+    }
+
+    /// Whether one of the renderer's `Sink` protocols accepts `protocol_info`. The sink list
+    /// is the one last fetched by [`Self::get_protocol_info`]; until then every format is
+    /// assumed to be playable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sink list lock was poisoned.
+    #[must_use]
+    pub fn can_play(&self, protocol_info: &ProtocolInfoEntry) -> bool {
+        self.sink_protocols
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(true, |sink| {
+                sink.iter().any(|entry| entry.accepts(protocol_info))
+            })
+    }
+
+    async fn ensure_playable(&self, protocol_info: &str) -> Result<(), Error> {
+        if self.sink_protocols.lock().unwrap().is_none() {
+            self.get_protocol_info().await?;
+        }
+        if !self.can_play(&protocol_info.parse()?) {
+            return Err(UpnpError::IncompatibleFormat {
+                protocol_info: protocol_info.to_string(),
+            }
+            .into());
+        }
+        Ok(())
     }
 
-    pub async fn get_position(&self) -> Result<u32, Error> {
+    pub async fn get_position(&self) -> Result<u32, UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("AVTransport", "GetPositionInfo", params)
             .await?;
-        Ok(parse_position(response.as_str())?)
+        parse_position(response.as_str())
     }
 
-    pub async fn get_duration(&self) -> Result<u32, Error> {
+    pub async fn get_duration(&self) -> Result<u32, UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("AVTransport", "GetMediaInfo", params)
             .await?;
-        Ok(parse_duration(response.as_str())?)
+        parse_duration(response.as_str())
     }
 
     pub async fn subscribe(&mut self) -> impl Stream<Item = Event> {
@@ -204,86 +328,481 @@ impl MediaRendererClient {
         }
     }
 
-    pub async fn get_transport_info(&self) -> Result<TransportInfo, Error> {
+    /// Streams the transport state, position, volume and track changes of the renderer,
+    /// from both `AVTransport` and `RenderingControl`.
+    ///
+    /// Subscriptions are renewed halfway through their timeout. Services that refuse the
+    /// event subscription, or a renewal, are polled every second instead. The subscriptions
+    /// are cancelled and the pollers stop once the stream is dropped.
+    pub async fn events(&self) -> Result<impl Stream<Item = RendererEvent>, UpnpError> {
+        let (tx, mut rx) = unbounded_channel();
+        let sids = AcceptedSids::new();
+        let callback =
+            listen_for_events(self.device_client.remote_addr()?, sids.clone(), tx.clone())?;
+
+        for service_id in ["AVTransport", "RenderingControl"] {
+            let Ok(service) = self.device_client.service(service_id).await else {
+                tokio::spawn(self.clone().poll_events(service_id, tx.clone()));
+                continue;
+            };
+            match eventing::subscribe(&service.event_sub_url, &callback).await {
+                Ok(subscription) => {
+                    sids.insert(&subscription.sid);
+                    tokio::spawn(self.clone().keep_subscribed(
+                        service_id,
+                        service,
+                        subscription,
+                        sids.clone(),
+                        tx.clone(),
+                    ));
+                }
+                Err(_) => {
+                    tokio::spawn(self.clone().poll_events(service_id, tx.clone()));
+                }
+            }
+        }
+
+        Ok(stream! {
+            while let Some(event) = rx.recv().await {
+                yield event;
+            }
+        })
+    }
+
+    /// Renews `subscription` until the stream is dropped, then cancels it. Falls back to
+    /// polling `service_id` if a renewal fails.
+    async fn keep_subscribed(
+        self,
+        service_id: &str,
+        service: Service,
+        mut subscription: Subscription,
+        sids: AcceptedSids,
+        tx: UnboundedSender<RendererEvent>,
+    ) {
+        loop {
+            let renewal = async {
+                match subscription.timeout {
+                    Some(timeout) => tokio::time::sleep(timeout / 2).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                () = tx.closed() => {
+                    let _ = service.unsubscribe(&subscription.sid).await;
+                    return;
+                }
+                () = renewal => {}
+            }
+            let Ok(renewed) = service.renew_subscription(&subscription.sid).await else {
+                sids.remove(&subscription.sid);
+                self.poll_events(service_id, tx).await;
+                return;
+            };
+            sids.insert(&renewed.sid);
+            subscription = renewed;
+        }
+    }
+
+    async fn poll_events(self, service_id: &str, tx: UnboundedSender<RendererEvent>) {
+        let mut last = Vec::new();
+        while !tx.is_closed() {
+            let current = match service_id {
+                "AVTransport" => self.poll_av_transport().await,
+                _ => self.poll_rendering_control().await,
+            };
+            if let std::result::Result::Ok(current) = current {
+                for event in &current {
+                    if !last.contains(event) && tx.send(event.clone()).is_err() {
+                        return;
+                    }
+                }
+                last = current;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn poll_av_transport(&self) -> Result<Vec<RendererEvent>, Error> {
+        let transport_info = self.get_transport_info().await?;
+        let position = self.get_position().await?;
+        Ok(vec![
+            RendererEvent::TransportStateChanged(transport_info.current_transport_state),
+            RendererEvent::PositionChanged(Duration::from_secs(position.into())),
+        ])
+    }
+
+    async fn poll_rendering_control(&self) -> Result<Vec<RendererEvent>, Error> {
+        Ok(vec![RendererEvent::VolumeChanged(self.get_volume().await?)])
+    }
+
+    pub async fn get_transport_info(&self) -> Result<TransportInfo, UpnpError> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("AVTransport", "GetTransportInfo", params)
             .await?;
-        Ok(parse_transport_info(response.as_str())?)
+        parse_transport_info(response.as_str())
     }
 }
 
-fn build_metadata(m: Metadata, media_type: ObjectClass) -> String {
-    let mut didl = XMLElement::new("DIDL-Lite");
-    didl.add_attribute("xmlns", "urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/");
-    didl.add_attribute("xmlns:dc", "http://purl.org/dc/elements/1.1/");
-    didl.add_attribute("xmlns:upnp", "urn:schemas-upnp-org:metadata-1-0/upnp/");
-    didl.add_attribute("xmlns:dlna", "urn:schemas-dlna-org:metadata-1-0/");
-    didl.add_attribute("xmlns:xbmc", "urn:schemas-xbmc-org:metadata-1-0/");
-    didl.add_attribute("xmlns:sec", "http://www.sec.co.kr/");
+/// Serves the `NOTIFY` requests of event subscriptions until `tx` is closed, forwarding
+/// their `LastChange` variables, and returns the callback URL to subscribe with.
+fn listen_for_events(
+    device_addr: SocketAddr,
+    sids: AcceptedSids,
+    tx: UnboundedSender<RendererEvent>,
+) -> Result<String, Error> {
+    let shutdown = tx.clone();
+    eventing::listen(
+        device_addr,
+        sids,
+        move |notification| {
+            let last_change = parse_last_change(&notification.body)
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            for event in parse_renderer_events(&last_change) {
+                let _ = tx.send(event);
+            }
+        },
+        async move { shutdown.closed().await },
+    )
+}
 
-    let mut item = XMLElement::new("item");
-    item.add_attribute("id", "0");
-    item.add_attribute("parentID", "-1");
-    item.add_attribute("restricted", "false");
+/// The upper bound of the `Volume` state variable of `RenderingControl`.
+const MAX_VOLUME: u8 = 100;
 
-    let mut title = XMLElement::new("dc:title");
-    title.add_text(m.title).unwrap();
-    item.add_child(title).unwrap();
+fn relative_volume(volume: u8, delta: i16) -> u8 {
+    let volume = i16::from(volume)
+        .saturating_add(delta)
+        .clamp(0, MAX_VOLUME.into());
+    u8::try_from(volume).unwrap_or(MAX_VOLUME)
+}
 
-    let mut class = XMLElement::new("upnp:class");
-    class.add_text(media_type.value().to_owned()).unwrap();
-    item.add_child(class).unwrap();
+/// Adds `delta` to `position`, clamping the result to `[0, duration]`. Only the lower
+/// bound applies when the duration is unknown.
+fn relative_seek_target(position: u64, duration: Option<u64>, delta: i64) -> u64 {
+    let target = position.saturating_add_signed(delta);
+    duration.map_or(target, |duration| target.min(duration))
+}
 
-    if let Some(value) = m.artist {
-        let mut artist = XMLElement::new("upnp:artist");
-        artist.add_text(value).unwrap();
-        item.add_child(artist).unwrap();
+#[cfg(test)]
+mod tests {
+    use crate::{
+        device_client::DeviceClient,
+        error::UpnpError,
+        media_renderer::{relative_seek_target, relative_volume, MediaRendererClient},
+        test_utils::{mock_device, soap_response, MockResponse, MockService},
+        types::{LoadOptions, RendererEvent},
+    };
+    use futures_util::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_relative_seek_target() {
+        assert_eq!(relative_seek_target(60, Some(180), 30), 90);
+        assert_eq!(relative_seek_target(170, Some(180), 30), 180);
+        assert_eq!(relative_seek_target(10, Some(180), -30), 0);
+        assert_eq!(relative_seek_target(3600, None, 30), 3630);
     }
 
-    if let Some(value) = m.album {
-        let mut album = XMLElement::new("upnp:album");
-        album.add_text(value).unwrap();
-        item.add_child(album).unwrap();
+    #[test]
+    fn test_relative_volume() {
+        assert_eq!(relative_volume(40, 5), 45);
+        assert_eq!(relative_volume(3, -5), 0);
+        assert_eq!(relative_volume(100, i16::MAX), 100);
     }
 
-    if let Some(value) = m.album_art_uri {
-        let mut album_art = XMLElement::new("upnp:albumArtURI");
-        album_art.add_attribute("dlna:profileID", "JPEG_TN");
-        album_art.add_attribute("xmlns:dlna", "urn:schemas-dlna-org:metadata-1-0/");
-        album_art.add_text(value).unwrap();
-        item.add_child(album_art).unwrap();
+    #[tokio::test]
+    async fn test_set_volume_relative_clamps_to_max() {
+        let server = mock_device(
+            vec![MockService::new(
+                "RenderingControl",
+                &["GetVolume", "SetVolume"],
+            )],
+            |req| match req.action() {
+                Some("GetVolume") => soap_response(
+                    "RenderingControl",
+                    "GetVolume",
+                    "<CurrentVolume>95</CurrentVolume>",
+                ),
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+
+        assert_eq!(media_renderer.set_volume_relative(10).await.unwrap(), 100);
+
+        let set_volume = server.actions().pop().unwrap();
+        assert_eq!(set_volume.action(), Some("SetVolume"));
+        assert_eq!(set_volume.param("DesiredVolume").as_deref(), Some("100"));
     }
 
-    if let Some(value) = m.genre {
-        let mut genre = XMLElement::new("upnp:genre");
-        genre.add_text(value).unwrap();
-        item.add_child(genre).unwrap();
+    #[tokio::test]
+    async fn test_seek_relative() {
+        let server = mock_device(
+            vec![MockService::new("AVTransport", &["GetPositionInfo", "Seek"])],
+            |req| match req.action() {
+                Some("GetPositionInfo") => soap_response(
+                    "AVTransport",
+                    "GetPositionInfo",
+                    "<Track>1</Track><TrackDuration>0:03:00</TrackDuration><RelTime>0:01:00</RelTime>",
+                ),
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+
+        assert_eq!(media_renderer.seek_relative(30).await.unwrap(), 90);
+
+        let seek = server.actions().pop().unwrap();
+        assert_eq!(seek.action(), Some("Seek"));
+        assert_eq!(seek.param("Unit").as_deref(), Some("REL_TIME"));
+        assert_eq!(seek.param("Target").as_deref(), Some("00:01:30"));
     }
 
-    let mut res = XMLElement::new("res");
-    res.add_attribute("protocolInfo", m.protocol_info.as_str());
-    res.add_text(m.url).unwrap();
-    item.add_child(res).unwrap();
+    #[tokio::test]
+    async fn test_load_validates_protocol() {
+        let server = mock_device(
+            vec![
+                MockService::new("AVTransport", &["SetAVTransportURI"]),
+                MockService::new("ConnectionManager", &["GetProtocolInfo"]),
+            ],
+            |req| match req.action() {
+                Some("GetProtocolInfo") => soap_response(
+                    "ConnectionManager",
+                    "GetProtocolInfo",
+                    "<Source></Source><Sink>http-get:*:audio/mpeg:*,http-get:*:audio/L16;rate=44100;channels=2:*</Sink>",
+                ),
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+
+        let options = |content_type: &str| LoadOptions {
+            content_type: Some(content_type.to_string()),
+            validate_protocol: true,
+            ..Default::default()
+        };
+        let error = media_renderer
+            .load("http://192.168.1.2/video.mkv", options("video/x-matroska"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, UpnpError::IncompatibleFormat { .. }));
+        assert!(!server
+            .actions()
+            .iter()
+            .any(|req| req.action() == Some("SetAVTransportURI")));
+
+        media_renderer
+            .load("http://192.168.1.2/song.mp3", options("audio/mpeg"))
+            .await
+            .unwrap();
+        let actions = server.actions();
+        assert_eq!(
+            actions
+                .iter()
+                .filter(|req| req.action() == Some("GetProtocolInfo"))
+                .count(),
+            1
+        );
+        let set_uri = actions.last().unwrap();
+        assert_eq!(set_uri.action(), Some("SetAVTransportURI"));
+        assert!(set_uri
+            .param("CurrentURIMetaData")
+            .unwrap()
+            .trim_start()
+            .starts_with("&lt;DIDL-Lite"));
+    }
 
-    didl.add_child(item).unwrap();
+    #[tokio::test]
+    async fn test_events_merge_notifications_and_polling() {
+        let server = mock_device(
+            vec![
+                MockService::new("AVTransport", &[]),
+                MockService::new("RenderingControl", &["GetVolume"]),
+            ],
+            |req| match (req.path.as_str(), req.action()) {
+                ("/AVTransport/event", _) => MockResponse {
+                    headers: vec![("SID".to_string(), "uuid:sub-1".to_string())],
+                    ..MockResponse::ok("")
+                },
+                ("/RenderingControl/event", _) => MockResponse {
+                    status: 412,
+                    ..MockResponse::ok("")
+                },
+                (_, Some("GetVolume")) => soap_response(
+                    "RenderingControl",
+                    "GetVolume",
+                    "<CurrentVolume>30</CurrentVolume>",
+                ),
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+        let mut events = Box::pin(media_renderer.events().await.unwrap());
+
+        let callback = server
+            .requests()
+            .into_iter()
+            .find(|req| req.path == "/AVTransport/event")
+            .and_then(|req| req.headers.get("callback").cloned())
+            .unwrap();
+        let last_change = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/"><InstanceID val="0"><TransportState val="PLAYING"/></InstanceID></Event>"#;
+        let notify = hyper::Request::builder()
+            .method("NOTIFY")
+            .uri(callback.trim_matches(|c| c == '<' || c == '>'))
+            .header("NT", "upnp:event")
+            .header("NTS", "upnp:propchange")
+            .header("SID", "uuid:sub-1")
+            .body(hyper::Body::from(format!(
+                r#"<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0"><e:property><LastChange>{}</LastChange></e:property></e:propertyset>"#,
+                xml::escape::escape_str_pcdata(last_change)
+            )))
+            .unwrap();
+        hyper::Client::new().request(notify).await.unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(event);
+        }
+        assert!(received.contains(&RendererEvent::VolumeChanged(30)));
+        assert!(received.contains(&RendererEvent::TransportStateChanged("PLAYING".to_string())));
+    }
 
-    let mut xml = XMLBuilder::new().build();
-    xml.set_root_element(didl);
+    #[tokio::test]
+    async fn test_events_renew_and_cancel_subscriptions() {
+        let server = mock_device(
+            vec![
+                MockService::new("AVTransport", &[]),
+                MockService::new("RenderingControl", &[]),
+            ],
+            |req| match req.path.as_str() {
+                "/AVTransport/event" => MockResponse {
+                    headers: vec![
+                        ("SID".to_string(), "uuid:sub-1".to_string()),
+                        ("TIMEOUT".to_string(), "Second-2".to_string()),
+                    ],
+                    ..MockResponse::ok("")
+                },
+                "/RenderingControl/event" => MockResponse {
+                    status: 412,
+                    ..MockResponse::ok("")
+                },
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+        let events = media_renderer.events().await.unwrap();
+        let subscription_requests = || {
+            server
+                .requests()
+                .into_iter()
+                .filter(|req| req.path == "/AVTransport/event")
+                .map(|req| (req.method, req.headers.get("sid").cloned()))
+                .collect::<Vec<_>>()
+        };
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(
+            subscription_requests(),
+            vec![
+                ("SUBSCRIBE".to_string(), None),
+                ("SUBSCRIBE".to_string(), Some("uuid:sub-1".to_string())),
+            ]
+        );
 
-    let mut writer: Vec<u8> = Vec::new();
-    xml.generate(&mut writer).unwrap();
-    let metadata = String::from_utf8(writer)
-        .unwrap()
-        .replace(r#"<?xml version="1.0" encoding="UTF-8"?>"#, "");
-    xml::escape::escape_str_attribute(&metadata).to_string()
-}
+        drop(events);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !subscription_requests()
+                .contains(&("UNSUBSCRIBE".to_string(), Some("uuid:sub-1".to_string())))
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
 
-fn format_time(seconds: u64) -> String {
-    let hours = seconds / 3600;
-    let minutes = (seconds % 3600) / 60;
-    let seconds = seconds % 60;
-    format!("{hours:02}:{minutes:02}:{seconds:02}")
+    #[tokio::test]
+    async fn test_events_poll_when_renewal_fails() {
+        let server = mock_device(
+            vec![
+                MockService::new("AVTransport", &[]),
+                MockService::new("RenderingControl", &["GetVolume"]),
+            ],
+            |req| match (req.path.as_str(), req.action()) {
+                ("/RenderingControl/event", _) if req.headers.contains_key("sid") => MockResponse {
+                    status: 412,
+                    ..MockResponse::ok("")
+                },
+                ("/RenderingControl/event", _) => MockResponse {
+                    headers: vec![
+                        ("SID".to_string(), "uuid:sub-2".to_string()),
+                        ("TIMEOUT".to_string(), "Second-1".to_string()),
+                    ],
+                    ..MockResponse::ok("")
+                },
+                ("/AVTransport/event", _) => MockResponse {
+                    status: 412,
+                    ..MockResponse::ok("")
+                },
+                (_, Some("GetVolume")) => soap_response(
+                    "RenderingControl",
+                    "GetVolume",
+                    "<CurrentVolume>30</CurrentVolume>",
+                ),
+                _ => MockResponse::ok(""),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_renderer = MediaRendererClient::new(device_client);
+        let mut events = Box::pin(media_renderer.events().await.unwrap());
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, RendererEvent::VolumeChanged(30));
+    }
 }
@@ -1,11 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
 use crate::{
     device_client::DeviceClient,
-    parser::parse_browse_response,
-    types::{Container, Item},
+    error::UpnpError,
+    parser::{
+        parse_browse_response, parse_browse_result, parse_search_capabilities,
+        parse_sort_capabilities,
+    },
+    types::{BrowseFlag, BrowseResult, Container, Item},
 };
-use anyhow::Error;
+use async_stream::stream;
+use futures_util::Stream;
 
 #[derive(Clone)]
 pub struct MediaServerClient {
@@ -22,7 +27,7 @@ impl MediaServerClient {
         &self,
         object_id: &str,
         browse_flag: &str,
-    ) -> Result<(Vec<Container>, Vec<Item>), Error> {
+    ) -> Result<(Vec<Container>, Vec<Item>), UpnpError> {
         let mut params = HashMap::new();
         params.insert("ObjectID".to_string(), object_id.to_string());
         params.insert("BrowseFlag".to_string(), browse_flag.to_string());
@@ -41,16 +46,117 @@ impl MediaServerClient {
         parse_browse_response(&response, &ip)
     }
 
-    pub async fn get_sort_capabilities(&self) -> Result<(), Error> {
+    /// Lists the children of the container `object_id`, returning up to
+    /// `requested_count` of them from `starting_index` on, `0` requesting all of them.
+    ///
+    /// As a compatibility fallback for servers that fault with `720`
+    /// (`CannotProcessTheRequest`) or `709` (`UnsupportedOrInvalidSortCriteria`), the
+    /// request is retried once after such a fault. This covers servers that reject
+    /// filters or sort criteria they don't handle, which get the `*` filter and no sort
+    /// criteria on the retry, and servers that only list the children of objects whose
+    /// metadata was browsed first, which get a `BrowseMetadata` request before it.
+    pub async fn browse_children(
+        &self,
+        object_id: &str,
+        filter: &str,
+        sort_criteria: &str,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        let result = self
+            .browse_page(
+                object_id,
+                BrowseFlag::BrowseDirectChildren,
+                filter,
+                sort_criteria,
+                starting_index,
+                requested_count,
+            )
+            .await;
+        match &result {
+            Err(e) if is_browse_quirk(e) => {
+                // Only there to prime the server, so its outcome doesn't matter.
+                let _ = self
+                    .browse_page(object_id, BrowseFlag::BrowseMetadata, "*", "", 0, 0)
+                    .await;
+                self.browse_page(
+                    object_id,
+                    BrowseFlag::BrowseDirectChildren,
+                    "*",
+                    "",
+                    starting_index,
+                    requested_count,
+                )
+                .await
+            }
+            _ => result,
+        }
+    }
+
+    /// Lists the items among the children of the container `object_id`, following
+    /// `TotalMatches`/`NumberReturned` until every one has been yielded, `page_size` at a
+    /// time, `0` asking for as many as the server is willing to return per page.
+    ///
+    /// Pages are browsed like [`Self::browse_children`] does, with the `*` filter and no
+    /// sort criteria. The stream ends early when a server returns an empty page.
+    pub fn browse_all(
+        &self,
+        object_id: &str,
+        page_size: u32,
+    ) -> impl Stream<Item = Result<Item, UpnpError>> {
+        let client = self.clone();
+        let object_id = object_id.to_string();
+        paginate(page_size, move |starting_index, requested_count| {
+            let client = client.clone();
+            let object_id = object_id.clone();
+            async move {
+                client
+                    .browse_children(&object_id, "*", "", starting_index, requested_count)
+                    .await
+            }
+        })
+    }
+
+    async fn browse_page(
+        &self,
+        object_id: &str,
+        flag: BrowseFlag,
+        filter: &str,
+        sort_criteria: &str,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        let mut params = HashMap::new();
+        params.insert("ObjectID".to_string(), object_id.to_string());
+        params.insert("BrowseFlag".to_string(), flag.to_string());
+        params.insert("Filter".to_string(), filter.to_string());
+        params.insert("StartingIndex".to_string(), starting_index.to_string());
+        params.insert("RequestedCount".to_string(), requested_count.to_string());
+        params.insert("SortCriteria".to_string(), sort_criteria.to_string());
+
+        let response = self
+            .device_client
+            .call_action("ContentDirectory", "Browse", params)
+            .await?;
+
+        let ip = self.device_client.ip();
+
+        parse_browse_result(&response, &ip)
+    }
+
+    /// The properties `SortCriteria` may use, `*` alone when the server supports all of
+    /// them and none when it cannot sort.
+    pub async fn get_sort_capabilities(&self) -> Result<Vec<String>, UpnpError> {
         let params = HashMap::new();
-        self.device_client
+        let response = self
+            .device_client
             .call_action("ContentDirectory", "GetSortCapabilities", params)
             .await?;
 
-        todo!()
+        parse_sort_capabilities(&response)
     }
 
-    pub async fn get_system_update_id(&self) -> Result<(), Error> {
+    pub async fn get_system_update_id(&self) -> Result<(), UpnpError> {
         let params = HashMap::new();
         self.device_client
             .call_action("ContentDirectory", "GetSystemUpdateID", params)
@@ -59,25 +165,69 @@ impl MediaServerClient {
         todo!()
     }
 
-    pub async fn get_search_capabilities(&self) -> Result<(), Error> {
+    /// The properties `Search` criteria may use, `*` alone when the server supports all
+    /// of them and none when it cannot search.
+    pub async fn get_search_capabilities(&self) -> Result<Vec<String>, UpnpError> {
         let params = HashMap::new();
-        self.device_client
+        let response = self
+            .device_client
             .call_action("ContentDirectory", "GetSearchCapabilities", params)
             .await?;
 
-        todo!()
+        parse_search_capabilities(&response)
     }
 
-    pub async fn search(&self) -> Result<(), Error> {
-        let params = HashMap::new();
-        self.device_client
+    /// Runs a `Search` on `container_id`, following `TotalMatches`/`NumberReturned` until
+    /// every matching item has been yielded.
+    pub fn search_all(
+        &self,
+        container_id: &str,
+        criteria: &str,
+    ) -> impl Stream<Item = Result<Item, UpnpError>> {
+        let client = self.clone();
+        let container_id = container_id.to_string();
+        let criteria = criteria.to_string();
+        paginate(0, move |starting_index, requested_count| {
+            let client = client.clone();
+            let container_id = container_id.clone();
+            let criteria = criteria.clone();
+            async move {
+                client
+                    .search(&container_id, &criteria, starting_index, requested_count)
+                    .await
+            }
+        })
+    }
+
+    /// Searches the container `container_id` for the objects matching `criteria`,
+    /// returning up to `requested_count` of them from `starting_index` on, `0` requesting
+    /// all of them.
+    pub async fn search(
+        &self,
+        container_id: &str,
+        criteria: &str,
+        starting_index: u32,
+        requested_count: u32,
+    ) -> Result<BrowseResult, UpnpError> {
+        let mut params = HashMap::new();
+        params.insert("ContainerID".to_string(), container_id.to_string());
+        params.insert("SearchCriteria".to_string(), criteria.to_string());
+        params.insert("Filter".to_string(), "*".to_string());
+        params.insert("StartingIndex".to_string(), starting_index.to_string());
+        params.insert("RequestedCount".to_string(), requested_count.to_string());
+        params.insert("SortCriteria".to_string(), String::new());
+
+        let response = self
+            .device_client
             .call_action("ContentDirectory", "Search", params)
             .await?;
 
-        todo!()
+        let ip = self.device_client.ip();
+
+        parse_browse_result(&response, &ip)
     }
 
-    pub async fn update_object(&self) -> Result<(), Error> {
+    pub async fn update_object(&self) -> Result<(), UpnpError> {
         let params = HashMap::new();
         self.device_client
             .call_action("ContentDirectory", "UpdateObject", params)
@@ -86,3 +236,365 @@ impl MediaServerClient {
         todo!()
     }
 }
+
+/// Whether `error` is one of the faults [`MediaServerClient::browse_children`] retries.
+const fn is_browse_quirk(error: &UpnpError) -> bool {
+    matches!(
+        error,
+        UpnpError::SoapFault {
+            code: 709 | 720,
+            ..
+        }
+    )
+}
+
+/// The `RequestedCount` asked for when a server returns nothing for a `RequestedCount`
+/// of 0 although it reports matches.
+const EXPLICIT_PAGE_SIZE: u32 = 100;
+
+/// Pages through `Browse`/`Search` results, asking for `page_size` entries at a time, or
+/// as many as the server is willing to return when it is 0, and resuming from wherever
+/// the server stopped, so servers that cap the page size are handled transparently.
+///
+/// Some servers return an empty page along with a non-zero `TotalMatches` when they need
+/// an explicit `RequestedCount`, so such a page is asked again, once, with
+/// [`EXPLICIT_PAGE_SIZE`]. Any other empty page ends the stream.
+fn paginate<F, Fut>(page_size: u32, fetch_page: F) -> impl Stream<Item = Result<Item, UpnpError>>
+where
+    F: Fn(u32, u32) -> Fut,
+    Fut: Future<Output = Result<BrowseResult, UpnpError>>,
+{
+    stream! {
+        let mut starting_index = 0;
+        let mut requested_count = page_size;
+        loop {
+            let page = match fetch_page(starting_index, requested_count).await {
+                Ok(page) => page,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            };
+            if page.number_returned == 0
+                && starting_index < page.total_matches
+                && requested_count == 0
+            {
+                requested_count = EXPLICIT_PAGE_SIZE;
+                continue;
+            }
+            for item in page.items {
+                yield Ok(item);
+            }
+            starting_index += page.number_returned;
+            if page.number_returned == 0 || starting_index >= page.total_matches {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+
+    use crate::{
+        device_client::DeviceClient,
+        error::UpnpError,
+        media_server::MediaServerClient,
+        test_utils::{escape_didl, mock_device, soap_fault, soap_response, MockService},
+    };
+
+    fn search_page(titles: &[&str], total_matches: usize) -> String {
+        let items = titles
+            .iter()
+            .map(|title| {
+                format!(
+                    r#"<item id="{title}" parentID="0" restricted="1">
+                        <dc:title>{title}</dc:title>
+                        <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                    </item>"#
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat();
+        let didl = format!(
+            r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">{items}</DIDL-Lite>"#
+        );
+        format!(
+            "<Result>{}</Result><NumberReturned>{}</NumberReturned><TotalMatches>{total_matches}</TotalMatches><UpdateID>1</UpdateID>",
+            escape_didl(&didl),
+            titles.len(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Search"])],
+            |_| soap_response("ContentDirectory", "Search", &search_page(&["One"], 3)),
+        )
+        .await;
+
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let result = media_server
+            .search("0", "dc:title contains \"One\"", 0, 1)
+            .await
+            .unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].title, "One");
+        assert_eq!(result.total_matches, 3);
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.param("ContainerID").as_deref(), Some("0"));
+        assert_eq!(
+            request.param("SearchCriteria").as_deref(),
+            Some("dc:title contains \"One\"")
+        );
+        assert_eq!(request.param("RequestedCount").as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn test_search_all_follows_pages() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |req| {
+                let body = match req.param("StartingIndex").as_deref() {
+                    Some("0") => search_page(&["One", "Two"], 3),
+                    _ => search_page(&["Three"], 3),
+                };
+                soap_response("ContentDirectory", "Search", &body)
+            },
+        )
+        .await;
+
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let titles = media_server
+            .search_all("0", "upnp:class derivedfrom \"object.item.audioItem\"")
+            .map(|item| item.unwrap().title)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(titles, vec!["One", "Two", "Three"]);
+
+        let requests = server.actions();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].param("StartingIndex").as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_search_all_retries_an_empty_page_with_an_explicit_count() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |req| {
+                let body = match req.param("RequestedCount").as_deref() {
+                    Some("0") => search_page(&[], 2),
+                    _ => search_page(&["One", "Two"], 2),
+                };
+                soap_response("ContentDirectory", "Search", &body)
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let titles = media_server
+            .search_all("0", "*")
+            .map(|item| item.unwrap().title)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(titles, vec!["One", "Two"]);
+
+        let requests = server.actions();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].param("RequestedCount").as_deref(), Some("100"));
+    }
+
+    #[tokio::test]
+    async fn test_search_all_stops_on_empty_pages() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |_| soap_response("ContentDirectory", "Search", &search_page(&[], 5)),
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let items = tokio::time::timeout(
+            Duration::from_secs(5),
+            media_server.search_all("0", "*").collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        assert!(items.is_empty());
+        assert_eq!(server.actions().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_browse_all_follows_pages() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |req| {
+                let body = match req.param("StartingIndex").as_deref() {
+                    Some("0") => search_page(&["One", "Two"], 3),
+                    _ => search_page(&["Three"], 3),
+                };
+                soap_response("ContentDirectory", "Browse", &body)
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let titles = media_server
+            .browse_all("64", 2)
+            .map(|item| item.unwrap().title)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(titles, vec!["One", "Two", "Three"]);
+
+        let requests = server
+            .actions()
+            .iter()
+            .map(|req| {
+                (
+                    req.param("ObjectID").unwrap(),
+                    req.param("StartingIndex").unwrap(),
+                    req.param("RequestedCount").unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            vec![
+                ("64".to_string(), "0".to_string(), "2".to_string()),
+                ("64".to_string(), "2".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_browse_all_stops_on_an_empty_page() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |_| soap_response("ContentDirectory", "Browse", &search_page(&[], 5)),
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let items = tokio::time::timeout(
+            Duration::from_secs(5),
+            media_server.browse_all("64", 10).collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        assert!(items.is_empty());
+        assert_eq!(server.actions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_browse_children_falls_back_after_a_fault() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |req| match req.param("Filter").as_deref() {
+                Some("*") => soap_response("ContentDirectory", "Browse", &search_page(&["One"], 1)),
+                _ => soap_fault(720, "Cannot process the request"),
+            },
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let result = media_server
+            .browse_children("64", "dc:title,res", "+dc:title", 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].title, "One");
+
+        let requests = server
+            .actions()
+            .iter()
+            .map(|req| {
+                (
+                    req.param("BrowseFlag").unwrap(),
+                    req.param("Filter").unwrap(),
+                    req.param("SortCriteria").unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let request = |flag: &str, filter: &str, sort: &str| {
+            (flag.to_string(), filter.to_string(), sort.to_string())
+        };
+        assert_eq!(
+            requests,
+            vec![
+                request("BrowseDirectChildren", "dc:title,res", "+dc:title"),
+                request("BrowseMetadata", "*", ""),
+                request("BrowseDirectChildren", "*", ""),
+            ]
+        );
+        assert_eq!(
+            server.actions()[2].param("RequestedCount").as_deref(),
+            Some("10")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_browse_children_reports_other_faults() {
+        let server = mock_device(
+            vec![MockService::new("ContentDirectory", &["Browse", "Search"])],
+            |_| soap_fault(701, "No such object"),
+        )
+        .await;
+        let device_client = DeviceClient::new(&server.url("/description.xml"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let media_server = MediaServerClient::new(device_client);
+
+        let error = media_server
+            .browse_children("missing", "*", "", 0, 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            UpnpError::SoapFault { code: 701, description } if description == "No such object"
+        ));
+        assert_eq!(server.actions().len(), 1);
+    }
+}
@@ -1,142 +1,319 @@
-use std::time::Duration;
+use std::{fmt::Write, net::IpAddr, time::Duration};
 
-use crate::types::{Action, Argument, Container, Device, Item, Metadata, Service, TransportInfo};
+use crate::error::UpnpError;
+use crate::types::{
+    protocol_media_type, Action, AllowedValueRange, Argument, BrowseResult, ConnectionInfo,
+    Container, Device, DeviceCapabilities, Icon, Item, LastChangeEvent, MediaInfo, Metadata,
+    NotifyMessage, PositionInfo, ProtocolInfo, ProtocolInfoEntry, RendererEvent, Resource, Service,
+    SpecVersion, StateChange, StateVariable, TransportInfo, TransportSettings,
+};
 use anyhow::{anyhow, Result};
 use elementtree::Element;
-use surf::{http::Method, Client, Config, Url};
+use url::{Host, Url};
+use xml::attribute::OwnedAttribute;
 use xml::reader::XmlEvent;
 use xml::EventReader;
 
-pub async fn parse_location(location: &str) -> Result<Device> {
-    let client: Client = Config::new()
-        .set_timeout(Some(Duration::from_secs(5)))
-        .try_into()?;
-    let req = surf::Request::new(Method::Get, location.parse()?);
-    let xml_root = client
-        .recv_string(req)
-        .await
-        .map_err(|e| anyhow!("Failed to retrieve xml from device endpoint: {}", e))?;
+#[cfg(feature = "client")]
+mod fetch;
 
+#[cfg(feature = "client")]
+pub(crate) use fetch::{decode_body, fetch_description, fetch_service_descriptions};
+#[cfg(feature = "client")]
+pub use fetch::{
+    parse_device_description, parse_device_description_with_client, parse_location,
+    parse_location_with_client, parse_location_with_config, parse_service_description,
+    parse_service_description_with_client, parse_service_description_with_config, parse_services,
+    parse_services_with_client, parse_services_with_config, DescriptionLimits, ParserConfig,
+    RetryPolicy,
+};
+
+const DEVICE_NAMESPACES: [&str; 2] = [
+    "urn:schemas-upnp-org:device-1-0",
+    "urn:schemas-upnp-org:device-2-0",
+];
+
+const SERVICE_NAMESPACES: [&str; 2] = [
+    "urn:schemas-upnp-org:service-1-0",
+    "urn:schemas-upnp-org:service-2-0",
+];
+
+/// Parses the device description fetched from `location`, without fetching anything else.
+///
+/// Every device of the tree comes with its services, whose actions and state variables
+/// stay empty until their SCPD is applied with [`apply_scpd`].
+///
+/// Relative URLs resolve against the description's `URLBase` when it has one, or else
+/// `location` itself, which is taken to be an HTTP URL when it has no scheme.
+pub fn parse_description(location: &str, xml_root: &str) -> Result<Device, UpnpError> {
+    let mut device = parse_device(location, xml_root)?;
+    device.services = parse_service_list(location, xml_root)?;
+    Ok(device)
+}
+
+fn parse_device(location: &str, xml_root: &str) -> Result<Device> {
+    let ns = namespace_of(xml_root, &DEVICE_NAMESPACES)?;
     let mut device = Device {
         location: location.to_string(),
         ..Default::default()
     };
 
-    device.device_type = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}deviceType",
-    )?;
-
-    device.device_type = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}deviceType",
-    )?;
-    device.friendly_name = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}friendlyName",
-    )?;
-    device.manufacturer = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}manufacturer",
-    )?;
-    device.manufacturer_url = match parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}manufacturerURL",
-    )? {
+    let major = parse_attribute(xml_root, ns, "specVersion/major")?;
+    let minor = parse_attribute(xml_root, ns, "specVersion/minor")?;
+    if let Ok(major) = major.trim().parse() {
+        device.spec_version = Some(SpecVersion {
+            major,
+            minor: minor.trim().parse().unwrap_or_default(),
+        });
+    }
+
+    device.device_type = parse_device_attribute(xml_root, ns, "deviceType")?;
+    device.friendly_name = parse_device_attribute(xml_root, ns, "friendlyName")?;
+    device.manufacturer = parse_device_attribute(xml_root, ns, "manufacturer")?;
+    device.manufacturer_url = match parse_device_attribute(xml_root, ns, "manufacturerURL")? {
         url if url.is_empty() => None,
         url => Some(url),
     };
-    device.model_description = match parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}modelDescription",
-    )? {
+    device.model_description = match parse_device_attribute(xml_root, ns, "modelDescription")? {
         description if description.is_empty() => None,
         description => Some(description),
     };
-    device.model_name = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}modelName",
-    )?;
-    device.model_number = match parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}modelNumber",
-    )? {
+    device.model_name = parse_device_attribute(xml_root, ns, "modelName")?;
+    device.model_number = match parse_device_attribute(xml_root, ns, "modelNumber")? {
         number if number.is_empty() => None,
         number => Some(number),
     };
-    device.udn = parse_attribute(
-        &xml_root,
-        "{urn:schemas-upnp-org:device-1-0}device/{urn:schemas-upnp-org:device-1-0}UDN",
-    )?;
+    device.model_url = match parse_device_attribute(xml_root, ns, "modelURL")? {
+        url if url.is_empty() => None,
+        url => Some(url),
+    };
+    device.serial_number = match parse_device_attribute(xml_root, ns, "serialNumber")? {
+        number if number.is_empty() => None,
+        number => Some(number),
+    };
+    device.udn = parse_device_attribute(xml_root, ns, "UDN")?;
 
-    let base_url = location.split('/').take(3).collect::<Vec<&str>>().join("/");
-    device.services = parse_services(&base_url, &xml_root).await?;
+    let root = Element::from_reader(xml_root.as_bytes())?;
+    let base_url = effective_base_url(location, ns, &root)?;
+    device.presentation_url = match parse_device_attribute(xml_root, ns, "presentationURL")? {
+        url if url.trim().is_empty() => None,
+        url => Some(presentation_url(&base_url, url)),
+    };
+    if let Some(xml_device) = child(&root, ns, "device") {
+        device.icons = parse_icons(&base_url, ns, xml_device)?;
+        device.devices = parse_embedded_devices(&base_url, ns, xml_device)?;
+    }
+    device.warnings = check_udns(&device);
 
     Ok(device)
 }
 
-fn parse_attribute(xml_root: &str, xml_name: &str) -> Result<String> {
-    let root = Element::from_reader(xml_root.as_bytes())?;
-    let mut xml_name = xml_name.split('/');
-    match root.find(
-        xml_name
-            .next()
-            .ok_or_else(|| anyhow!("xml_name ended unexpectedly"))?,
-    ) {
-        Some(element) => {
-            let element = element.find(
-                xml_name
-                    .next()
-                    .ok_or_else(|| anyhow!("xml_name ended unexpectedly"))?,
-            );
-            match element {
-                Some(element) => Ok(element.text().to_string()),
-                None => Ok(String::new()),
-            }
+/// The base every relative URL of a description resolves against, whichever device of
+/// the tree it belongs to.
+///
+/// As the `UPnP` Device Architecture specifies, that is the root's `URLBase` when present,
+/// since embedded devices cannot declare their own, or else `location`, the URL the
+/// description was fetched from. A `URLBase` that isn't an absolute URL is ignored.
+fn effective_base_url(location: &str, ns: &str, root: &Element) -> Result<Url> {
+    let url_base = child(root, ns, "URLBase")
+        .and_then(|url_base| Url::parse(url_base.text().trim()).ok())
+        .filter(Url::has_host);
+    url_base.map_or_else(|| parse_base_url(location), Ok)
+}
+
+/// Parses the location of a description, taken to be an HTTP URL when it has no scheme,
+/// like the `LOCATION` of some devices.
+fn parse_base_url(location: &str) -> Result<Url> {
+    let location = location.trim();
+    let url = match Url::parse(location) {
+        Err(url::ParseError::RelativeUrlWithoutBase) if !location.starts_with('/') => {
+            Url::parse(&format!("http://{location}"))
+        }
+        url => url,
+    }?;
+    if url.has_host() {
+        Ok(url)
+    } else {
+        Err(UpnpError::InvalidUrl(format!("{location} has no host")).into())
+    }
+}
+
+/// Parses the `deviceList` of `xml_device`, recursively, along with the service list of
+/// each embedded device.
+fn parse_embedded_devices(base_url: &Url, ns: &str, xml_device: &Element) -> Result<Vec<Device>> {
+    let Some(device_list) = child(xml_device, ns, "deviceList") else {
+        return Ok(vec![]);
+    };
+
+    let mut devices = Vec::new();
+    for xml_device in children(device_list, ns, "device") {
+        let text = |name| child(xml_device, ns, name).map(|element| element.text().to_string());
+        devices.push(Device {
+            device_type: text("deviceType").unwrap_or_default(),
+            friendly_name: text("friendlyName").unwrap_or_default(),
+            manufacturer: text("manufacturer").unwrap_or_default(),
+            manufacturer_url: text("manufacturerURL").filter(|url| !url.is_empty()),
+            model_description: text("modelDescription").filter(|d| !d.is_empty()),
+            model_name: text("modelName").unwrap_or_default(),
+            model_number: text("modelNumber").filter(|number| !number.is_empty()),
+            model_url: text("modelURL").filter(|url| !url.is_empty()),
+            serial_number: text("serialNumber").filter(|number| !number.is_empty()),
+            presentation_url: text("presentationURL")
+                .filter(|url| !url.trim().is_empty())
+                .map(|url| presentation_url(base_url, url)),
+            udn: text("UDN").unwrap_or_default(),
+            icons: parse_icons(base_url, ns, xml_device)?,
+            services: parse_service_elements(base_url, ns, xml_device)?,
+            devices: parse_embedded_devices(base_url, ns, xml_device)?,
+            ..Default::default()
+        });
+    }
+    Ok(devices)
+}
+
+/// The `presentationURL` of a device, which may be relative to the base URL, or as given
+/// when it cannot be resolved.
+fn presentation_url(base_url: &Url, url: String) -> String {
+    build_absolute_url(base_url, &url).unwrap_or(url)
+}
+
+/// Parses the `iconList` of a single `device` element, resolving the icon URLs against
+/// `base_url`.
+fn parse_icons(base_url: &Url, ns: &str, xml_device: &Element) -> Result<Vec<Icon>> {
+    let Some(icon_list) = child(xml_device, ns, "iconList") else {
+        return Ok(vec![]);
+    };
+
+    children(icon_list, ns, "icon")
+        .into_iter()
+        .map(|xml_icon| {
+            let text = |name| {
+                child(xml_icon, ns, name)
+                    .map(|element| element.text().trim().to_string())
+                    .unwrap_or_default()
+            };
+            Ok(Icon {
+                mime_type: text("mimetype"),
+                width: text("width").parse().unwrap_or_default(),
+                height: text("height").parse().unwrap_or_default(),
+                depth: text("depth").parse().unwrap_or_default(),
+                url: build_absolute_url(base_url, &text("url"))?,
+            })
+        })
+        .collect()
+}
+
+/// Reports devices of the tree rooted at `device` that lack a UDN or share one with
+/// another device, which hints at a cloned or proxied description.
+fn check_udns(device: &Device) -> Vec<String> {
+    fn visit<'a>(device: &'a Device, warnings: &mut Vec<String>, seen: &mut Vec<&'a str>) {
+        let udn = device.udn.trim();
+        if udn.is_empty() {
+            warnings.push(format!("Device {:?} has no UDN", device.friendly_name));
+        } else if seen.contains(&udn) {
+            warnings.push(format!(
+                "Device {:?} shares its UDN {udn} with another device",
+                device.friendly_name
+            ));
+        } else {
+            seen.push(udn);
+        }
+        for embedded in &device.devices {
+            visit(embedded, warnings, seen);
         }
-        None => Ok(String::new()),
     }
+
+    let mut warnings = Vec::new();
+    visit(device, &mut warnings, &mut Vec::new());
+    warnings
+}
+
+/// Returns the namespace of the document's root element if it is one of `namespaces`,
+/// falling back to the first (`UPnP` 1.0) namespace otherwise.
+fn namespace_of(xml_root: &str, namespaces: &[&'static str]) -> Result<&'static str> {
+    let root = Element::from_reader(xml_root.as_bytes())?;
+    Ok(namespaces
+        .iter()
+        .find(|ns| root.tag().ns() == Some(**ns))
+        .copied()
+        .unwrap_or(namespaces[0]))
+}
+
+fn parse_device_attribute(xml_root: &str, ns: &str, name: &str) -> Result<String> {
+    parse_attribute(xml_root, ns, &format!("device/{name}"))
 }
 
-pub async fn parse_services(base_url: &str, xml_root: &str) -> Result<Vec<Service>> {
+/// The text of the element at `path` below the root, such as `device/UDN`, or an empty
+/// string when there is none.
+fn parse_attribute(xml_root: &str, ns: &str, path: &str) -> Result<String> {
     let root = Element::from_reader(xml_root.as_bytes())?;
-    let device = root
-        .find("{urn:schemas-upnp-org:device-1-0}device")
-        .ok_or_else(|| anyhow!("Invalid response from device"))?;
+    Ok(path
+        .split('/')
+        .try_fold(&root, |element, name| child(element, ns, name))
+        .map(|element| element.text().to_string())
+        .unwrap_or_default())
+}
 
-    let mut services_with_actions: Vec<Service> = vec![];
-    if let Some(service_list) = device.find("{urn:schemas-upnp-org:device-1-0}serviceList") {
-        let xml_services = service_list.children();
+/// The first child of `element` named `name` in the namespace `ns`, or else, as some
+/// devices declare no namespace or another one, the first child with that local name.
+fn child<'a>(element: &'a Element, ns: &'a str, name: &'a str) -> Option<&'a Element> {
+    element
+        .find((ns, name))
+        .or_else(|| element.children().find(|child| child.tag().name() == name))
+}
+
+/// The children of `element` named `name` in the namespace `ns`, or else those with that
+/// local name, like [`child`].
+fn children<'a>(element: &'a Element, ns: &'a str, name: &'a str) -> Vec<&'a Element> {
+    let children: Vec<_> = element.find_all((ns, name)).collect();
+    if children.is_empty() {
+        element
+            .children()
+            .filter(|child| child.tag().name() == name)
+            .collect()
+    } else {
+        children
+    }
+}
 
-        let mut services = Vec::new();
-        for xml_service in xml_services {
+/// Parses the `serviceList` of a device description, without fetching any SCPD. The
+/// description's `URLBase`, if any, takes precedence over `base_url`.
+fn parse_service_list(base_url: &str, xml_root: &str) -> Result<Vec<Service>> {
+    let ns = namespace_of(xml_root, &DEVICE_NAMESPACES)?;
+    let root = Element::from_reader(xml_root.as_bytes())?;
+    let device = child(&root, ns, "device").ok_or_else(|| missing("device"))?;
+    parse_service_elements(&effective_base_url(base_url, ns, &root)?, ns, device)
+}
+
+/// Parses the `serviceList` of a single `device` element.
+fn parse_service_elements(base_url: &Url, ns: &str, device: &Element) -> Result<Vec<Service>> {
+    let mut services = Vec::new();
+    if let Some(service_list) = child(device, ns, "serviceList") {
+        for xml_service in service_list.children() {
             let mut service = Service {
-                service_type: xml_service
-                    .find("{urn:schemas-upnp-org:device-1-0}serviceType")
-                    .ok_or_else(|| anyhow!("Service missing serviceType"))?
+                service_type: child(xml_service, ns, "serviceType")
+                    .ok_or_else(|| missing("serviceType"))?
                     .text()
                     .to_string(),
-                service_id: xml_service
-                    .find("{urn:schemas-upnp-org:device-1-0}serviceId")
-                    .ok_or_else(|| anyhow!("Service missing serviceId"))?
+                service_id: child(xml_service, ns, "serviceId")
+                    .ok_or_else(|| missing("serviceId"))?
                     .text()
                     .to_string(),
-                control_url: xml_service
-                    .find("{urn:schemas-upnp-org:device-1-0}controlURL")
-                    .ok_or_else(|| anyhow!("Service missing controlURL"))?
+                control_url: child(xml_service, ns, "controlURL")
+                    .ok_or_else(|| missing("controlURL"))?
                     .text()
                     .to_string(),
-                event_sub_url: xml_service
-                    .find("{urn:schemas-upnp-org:device-1-0}eventSubURL")
-                    .ok_or_else(|| anyhow!("Service missing eventSubURL"))?
+                event_sub_url: child(xml_service, ns, "eventSubURL")
+                    .ok_or_else(|| missing("eventSubURL"))?
                     .text()
                     .to_string(),
-                scpd_url: xml_service
-                    .find("{urn:schemas-upnp-org:device-1-0}SCPDURL")
-                    .ok_or_else(|| anyhow!("Service missing SCPDURL"))?
+                scpd_url: child(xml_service, ns, "SCPDURL")
+                    .ok_or_else(|| missing("SCPDURL"))?
                     .text()
                     .to_string(),
                 actions: vec![],
+                state_variables: vec![],
+                raw_scpd: None,
             };
 
             service.control_url = build_absolute_url(base_url, &service.control_url)?;
@@ -145,67 +322,64 @@ pub async fn parse_services(base_url: &str, xml_root: &str) -> Result<Vec<Servic
 
             services.push(service);
         }
-
-        for service in &services {
-            let mut service = service.clone();
-            service.actions = parse_service_description(&service.scpd_url).await?;
-            services_with_actions.push(service);
-        }
     }
 
-    Ok(services_with_actions)
+    Ok(services)
 }
-
-fn build_absolute_url(base_url: &str, relative_url: &str) -> Result<String> {
-    let base_url = Url::parse(base_url)?;
-    Ok(base_url.join(relative_url)?.to_string())
+/// The error of a document lacking the element `name`.
+fn missing(name: &str) -> UpnpError {
+    UpnpError::MissingElement {
+        name: name.to_string(),
+    }
 }
 
-pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
-    let client: Client = Config::new()
-        .set_timeout(Some(Duration::from_secs(5)))
-        .try_into()?;
-    let req = surf::Request::new(Method::Get, scpd_url.parse()?);
+fn build_absolute_url(base_url: &Url, relative_url: &str) -> Result<String> {
+    Ok(base_url.join(relative_url.trim())?.to_string())
+}
 
-    let xml_root = client
-        .recv_string(req)
-        .await
-        .map_err(|e| anyhow!("Failed to retrieve xml response from device: {}", e))?;
+/// Fills the actions and state variables of `service` from its service description
+/// (SCPD), as fetched from its `scpd_url`.
+pub fn apply_scpd(service: &mut Service, xml_root: &str) -> Result<(), UpnpError> {
+    let ns = namespace_of(xml_root, &SERVICE_NAMESPACES)?;
     let root = Element::from_reader(xml_root.as_bytes())?;
+    service.actions = parse_actions(ns, &root)?;
+    service.state_variables = parse_state_variables(ns, &root);
+    Ok(())
+}
 
-    let Some(action_list) = root.find("{urn:schemas-upnp-org:service-1-0}actionList") else {
+fn parse_actions(ns: &str, root: &Element) -> Result<Vec<Action>> {
+    let Some(action_list) = root.find((ns, "actionList")) else {
         return Ok(vec![]);
     };
 
+    // Vendors extend the lists with elements of their own namespaces, which are skipped.
     let mut actions = Vec::new();
-    for xml_action in action_list.children() {
+    for xml_action in action_list.find_all((ns, "action")) {
         let mut action = Action {
             name: xml_action
-                .find("{urn:schemas-upnp-org:service-1-0}name")
-                .ok_or_else(|| anyhow!("Service::Action missing name"))?
+                .find((ns, "name"))
+                .ok_or_else(|| missing("action/name"))?
                 .text()
                 .to_string(),
             arguments: vec![],
         };
 
-        if let Some(arguments) = xml_action.find("{urn:schemas-upnp-org:service-1-0}argumentList") {
-            for xml_argument in arguments.children() {
+        if let Some(arguments) = xml_action.find((ns, "argumentList")) {
+            for xml_argument in arguments.find_all((ns, "argument")) {
                 let argument = Argument {
                     name: xml_argument
-                        .find("{urn:schemas-upnp-org:service-1-0}name")
-                        .ok_or_else(|| anyhow!("Service::Action::Argument missing name"))?
+                        .find((ns, "name"))
+                        .ok_or_else(|| missing("argument/name"))?
                         .text()
                         .to_string(),
                     direction: xml_argument
-                        .find("{urn:schemas-upnp-org:service-1-0}direction")
-                        .ok_or_else(|| anyhow!("Service::Action::Argument missing direction"))?
+                        .find((ns, "direction"))
+                        .ok_or_else(|| missing("argument/direction"))?
                         .text()
                         .to_string(),
                     related_state_variable: xml_argument
-                        .find("{urn:schemas-upnp-org:service-1-0}relatedStateVariable")
-                        .ok_or_else(|| {
-                            anyhow!("Service::Action::Argument missing relatedStateVariable")
-                        })?
+                        .find((ns, "relatedStateVariable"))
+                        .ok_or_else(|| missing("argument/relatedStateVariable"))?
                         .text()
                         .to_string(),
                 };
@@ -217,23 +391,66 @@ pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
     Ok(actions)
 }
 
-pub fn parse_volume(xml_root: &str) -> Result<u8> {
-    let parser = EventReader::from_str(xml_root);
+/// Parses the `serviceStateTable` of an SCPD, leaving out ranges whose bounds aren't
+/// integers.
+fn parse_state_variables(ns: &str, root: &Element) -> Vec<StateVariable> {
+    let Some(state_table) = root.find((ns, "serviceStateTable")) else {
+        return vec![];
+    };
+
+    state_table
+        .find_all((ns, "stateVariable"))
+        .map(|xml_variable| {
+            let text = |name| {
+                xml_variable
+                    .find((ns, name))
+                    .map(|element| element.text().trim().to_string())
+            };
+            let allowed_range = xml_variable
+                .find((ns, "allowedValueRange"))
+                .and_then(|range| {
+                    let bound = |name| range.find((ns, name))?.text().trim().parse().ok();
+                    Some(AllowedValueRange {
+                        minimum: bound("minimum")?,
+                        maximum: bound("maximum")?,
+                        step: bound("step"),
+                    })
+                });
+            StateVariable {
+                name: text("name").unwrap_or_default(),
+                data_type: text("dataType").unwrap_or_default(),
+                default_value: text("defaultValue"),
+                allowed_values: xml_variable
+                    .find((ns, "allowedValueList"))
+                    .map(|list| {
+                        list.find_all((ns, "allowedValue"))
+                            .map(|value| value.text().trim().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                allowed_range,
+                send_events: xml_variable.get_attr("sendEvents") != Some("no"),
+            }
+        })
+        .collect()
+}
+
+pub fn parse_volume(xml_root: &str) -> Result<u8, UpnpError> {
     let mut in_current_volume = false;
     let mut current_volume: Option<u8> = None;
-    for e in parser {
+    for e in XmlEvents::new(xml_root) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            XmlEvent::StartElement { name, .. } => {
                 if name.local_name == "CurrentVolume" {
                     in_current_volume = true;
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
+            XmlEvent::EndElement { name } => {
                 if name.local_name == "CurrentVolume" {
                     in_current_volume = false;
                 }
             }
-            Ok(XmlEvent::Characters(volume)) => {
+            XmlEvent::Characters(volume) => {
                 if in_current_volume {
                     current_volume = Some(volume.parse()?);
                 }
@@ -241,26 +458,51 @@ pub fn parse_volume(xml_root: &str) -> Result<u8> {
             _ => {}
         }
     }
-    current_volume.ok_or_else(|| anyhow!("Invalid response from device"))
+    current_volume.ok_or_else(|| missing("CurrentVolume"))
+}
+
+/// Parses the `CurrentMute` of a `GetMute` response, a `UPnP` boolean (`0`/`1`, or
+/// `false`/`true`).
+pub fn parse_mute(xml_root: &str) -> Result<bool, UpnpError> {
+    let mut in_current_mute = false;
+    let mut current_mute: Option<bool> = None;
+    for e in XmlEvents::new(xml_root) {
+        match e {
+            XmlEvent::StartElement { name, .. } if name.local_name == "CurrentMute" => {
+                in_current_mute = true;
+            }
+            XmlEvent::EndElement { name } if name.local_name == "CurrentMute" => {
+                in_current_mute = false;
+            }
+            XmlEvent::Characters(mute) if in_current_mute => {
+                current_mute = match mute.trim().to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" => Some(true),
+                    "0" | "false" | "no" => Some(false),
+                    _ => return Err(anyhow!("Invalid CurrentMute value: {mute}").into()),
+                };
+            }
+            _ => {}
+        }
+    }
+    current_mute.ok_or_else(|| missing("CurrentMute"))
 }
 
-pub fn parse_duration(xml_root: &str) -> Result<u32> {
-    let parser = EventReader::from_str(xml_root);
+pub fn parse_duration(xml_root: &str) -> Result<u32, UpnpError> {
     let mut in_duration = false;
     let mut duration: Option<String> = None;
-    for e in parser {
+    for e in XmlEvents::new(xml_root) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            XmlEvent::StartElement { name, .. } => {
                 if name.local_name == "MediaDuration" {
                     in_duration = true;
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
+            XmlEvent::EndElement { name } => {
                 if name.local_name == "MediaDuration" {
                     in_duration = false;
                 }
             }
-            Ok(XmlEvent::Characters(duration_str)) => {
+            XmlEvent::Characters(duration_str) => {
                 if in_duration {
                     let duration_str = duration_str.replace(':', "");
                     duration = Some(duration_str);
@@ -270,30 +512,29 @@ pub fn parse_duration(xml_root: &str) -> Result<u32> {
         }
     }
 
-    let duration = duration.ok_or_else(|| anyhow!("Invalid response from device"))?;
+    let duration = duration.ok_or_else(|| missing("MediaDuration"))?;
     let hours = duration[0..2].parse::<u32>()?;
     let minutes = duration[2..4].parse::<u32>()?;
     let seconds = duration[4..6].parse::<u32>()?;
     Ok(hours * 3600 + minutes * 60 + seconds)
 }
 
-pub fn parse_position(xml_root: &str) -> Result<u32> {
-    let parser = EventReader::from_str(xml_root);
+pub fn parse_position(xml_root: &str) -> Result<u32, UpnpError> {
     let mut in_position = false;
     let mut position = None;
-    for e in parser {
+    for e in XmlEvents::new(xml_root) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            XmlEvent::StartElement { name, .. } => {
                 if name.local_name == "RelTime" {
                     in_position = true;
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
+            XmlEvent::EndElement { name } => {
                 if name.local_name == "RelTime" {
                     in_position = false;
                 }
             }
-            Ok(XmlEvent::Characters(position_str)) => {
+            XmlEvent::Characters(position_str) => {
                 if in_position {
                     position = Some(position_str);
                 }
@@ -302,7 +543,7 @@ pub fn parse_position(xml_root: &str) -> Result<u32> {
         }
     }
 
-    let position = position.ok_or_else(|| anyhow!("Invalid response from device"))?;
+    let position = position.ok_or_else(|| missing("RelTime"))?;
     let mut position_iter = position.split(':');
     let hours = position_iter.next().map_or(Ok(0), str::parse)?;
     let minutes = position_iter.next().map_or(Ok(0), str::parse)?;
@@ -310,23 +551,247 @@ pub fn parse_position(xml_root: &str) -> Result<u32> {
     Ok(hours * 3600 + minutes * 60 + seconds)
 }
 
-pub fn parse_supported_protocols(xml_root: &str) -> Result<Vec<String>> {
-    let parser = EventReader::from_str(xml_root);
+/// Parses the `TrackDuration` of a `GetPositionInfo` response, in seconds. Returns
+/// `None` when the duration is unknown, e.g. for live streams.
+pub fn parse_track_duration(xml_root: &str) -> Result<Option<u32>, UpnpError> {
+    Ok(parse_element_text(xml_root, "TrackDuration")?
+        .as_deref()
+        .and_then(parse_time)
+        .filter(|duration| !duration.is_zero())
+        .and_then(|duration| u32::try_from(duration.as_secs()).ok()))
+}
+
+/// Parses a `GetPositionInfo` response.
+pub fn parse_position_info(xml_root: &str) -> Result<PositionInfo, UpnpError> {
+    let time = |element| -> Result<Option<Duration>> {
+        Ok(parse_element_text(xml_root, element)?
+            .as_deref()
+            .and_then(parse_time))
+    };
+    Ok(PositionInfo {
+        track: parse_element_text(xml_root, "Track")?
+            .and_then(|track| track.trim().parse().ok())
+            .unwrap_or_default(),
+        track_duration: time("TrackDuration")?.filter(|duration| !duration.is_zero()),
+        track_metadata: parse_metadata_element(xml_root, "TrackMetaData")?,
+        track_uri: parse_element_text(xml_root, "TrackURI")?.unwrap_or_default(),
+        rel_time: time("RelTime")?,
+        abs_time: time("AbsTime")?,
+    })
+}
+
+/// Parses a `GetMediaInfo` response.
+pub fn parse_media_info(xml_root: &str) -> Result<MediaInfo, UpnpError> {
+    Ok(MediaInfo {
+        nr_tracks: parse_element_text(xml_root, "NrTracks")?
+            .and_then(|tracks| tracks.trim().parse().ok())
+            .unwrap_or_default(),
+        media_duration: parse_element_text(xml_root, "MediaDuration")?
+            .as_deref()
+            .and_then(parse_time)
+            .filter(|duration| !duration.is_zero()),
+        current_uri: parse_element_text(xml_root, "CurrentURI")?.unwrap_or_default(),
+        current_uri_metadata: parse_metadata_element(xml_root, "CurrentURIMetaData")?,
+        current_item: parse_item_element(xml_root, "CurrentURIMetaData")?,
+        next_uri: parse_element_text(xml_root, "NextURI")?
+            .filter(|uri| uri != "NOT_IMPLEMENTED")
+            .unwrap_or_default(),
+        next_item: parse_item_element(xml_root, "NextURIMetaData")?,
+    })
+}
+
+/// Parses a `GetDeviceCapabilities` response.
+pub fn parse_device_capabilities(xml_root: &str) -> Result<DeviceCapabilities, UpnpError> {
+    Ok(DeviceCapabilities {
+        play_media: parse_list_element(xml_root, "PlayMedia")?,
+        rec_media: parse_list_element(xml_root, "RecMedia")?,
+        rec_quality_modes: parse_list_element(xml_root, "RecQualityModes")?,
+    })
+}
+
+/// Parses a `GetCurrentTransportActions` response into the actions valid in the current
+/// state of the transport, such as `Play`, `Pause` or `Seek`.
+pub fn parse_current_transport_actions(xml_root: &str) -> Result<Vec<String>, UpnpError> {
+    Ok(parse_list_element(xml_root, "Actions")?)
+}
+
+/// Parses a `GetSearchCapabilities` response into the properties `Search` criteria may
+/// use: `*` alone when the server supports all of them, none when it cannot search.
+pub fn parse_search_capabilities(xml_root: &str) -> Result<Vec<String>, UpnpError> {
+    Ok(parse_list_element(xml_root, "SearchCaps")?)
+}
+
+/// Parses a `GetSortCapabilities` response into the properties results can be sorted
+/// by: `*` alone when the server supports all of them, none when it cannot sort.
+pub fn parse_sort_capabilities(xml_root: &str) -> Result<Vec<String>, UpnpError> {
+    Ok(parse_list_element(xml_root, "SortCaps")?)
+}
+
+/// The comma-separated values of `element`, without `NOT_IMPLEMENTED`.
+fn parse_list_element(xml_root: &str, element: &str) -> Result<Vec<String>> {
+    Ok(parse_element_text(xml_root, element)?
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && *value != "NOT_IMPLEMENTED")
+        .map(str::to_string)
+        .collect())
+}
+
+/// Parses a `GetTransportSettings` response.
+pub fn parse_transport_settings(xml_root: &str) -> Result<TransportSettings, UpnpError> {
+    Ok(TransportSettings {
+        play_mode: parse_element_text(xml_root, "PlayMode")?
+            .map(|mode| mode.trim().to_string())
+            .unwrap_or_default(),
+        rec_quality_mode: parse_element_text(xml_root, "RecQualityMode")?
+            .map(|mode| mode.trim().to_string())
+            .filter(|mode| !mode.is_empty() && mode != "NOT_IMPLEMENTED"),
+    })
+}
+
+/// The DIDL-Lite carried by `element`, which renderers leave empty or set to
+/// `NOT_IMPLEMENTED` when they have none.
+fn parse_didl_element(xml_root: &str, element: &str) -> Result<Option<String>> {
+    Ok(parse_element_text(xml_root, element)?
+        .filter(|didl| !didl.trim().is_empty() && didl != "NOT_IMPLEMENTED"))
+}
+
+fn parse_metadata_element(xml_root: &str, element: &str) -> Result<Option<Metadata>, UpnpError> {
+    parse_didl_element(xml_root, element)?
+        .map(|didl| deserialize_metadata(&didl))
+        .transpose()
+}
+
+/// The first item of the DIDL-Lite carried by `element`, its URL being that of its first
+/// audio or video resource.
+fn parse_item_element(xml_root: &str, element: &str) -> Result<Option<Item>> {
+    let Some(didl) = parse_didl_element(xml_root, element)? else {
+        return Ok(None);
+    };
+    let (_, items) = deserialize_content_directory(&didl, "")?;
+    Ok(items.into_iter().next())
+}
+
+/// Parses a `UPnP` time value (`H+:MM:SS[.F+]`), such as a `res@duration` or a `RelTime`.
+///
+/// Returns `None` for `NOT_IMPLEMENTED`, empty, negative or otherwise malformed values,
+/// such as fractions that aren't plain digits or a ratio below one, and for times too
+/// long to represent. A leading `+` is tolerated.
+#[must_use]
+pub fn parse_time(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.starts_with('-') {
+        return None;
+    }
+    let value = value.strip_prefix('+').unwrap_or(value);
+
+    let mut parts = value.split(':');
+    let hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let seconds = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let (seconds, nanos) = match seconds.split_once('.') {
+        Some((seconds, fraction)) => (seconds, fraction_nanos(fraction)?),
+        None => (seconds, 0),
+    };
+    let seconds = seconds.parse::<u64>().ok()?;
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+
+    let seconds = hours
+        .checked_mul(3600)?
+        .checked_add(minutes * 60 + seconds)?;
+    Some(Duration::new(seconds, nanos))
+}
+
+/// The nanoseconds of the fraction of a time value, either decimal (`5` for `.5`) or a
+/// ratio below one (`1/2`). Digits beyond nanoseconds are dropped.
+fn fraction_nanos(fraction: &str) -> Option<u32> {
+    let digits = |field: &str| !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit());
+    if let Some((numerator, denominator)) = fraction.split_once('/') {
+        if !digits(numerator) || !digits(denominator) {
+            return None;
+        }
+        let numerator = numerator.parse::<u128>().ok()?;
+        let denominator = denominator.parse::<u128>().ok()?;
+        if numerator >= denominator {
+            return None;
+        }
+        return u32::try_from(numerator.checked_mul(1_000_000_000)? / denominator).ok();
+    }
+    if !digits(fraction) {
+        return None;
+    }
+    let fraction = &fraction[..fraction.len().min(9)];
+    format!("{fraction:0<9}").parse().ok()
+}
+
+/// Normalizes a `dc:date` to `YYYY-MM-DD`, which sorts chronologically as a string.
+///
+/// Accepts ISO 8601 dates, with or without a time and offset after a `T` or a space, such
+/// as `2021-06-01T20:15:00+02:00`. A year or year and month alone, as some servers take
+/// from audio tags, falls on the first day of the period. The time and offset are
+/// dropped, so the day is the one local to the server. Returns `None` for other values and
+/// for days the month doesn't have.
+#[must_use]
+pub fn parse_date(value: &str) -> Option<String> {
+    let value = value.trim();
+    let date = value.split(['T', ' ']).next().unwrap_or_default();
+    let number = |field: &str, digits: usize| {
+        if field.len() == digits && field.bytes().all(|b| b.is_ascii_digit()) {
+            field.parse::<u32>().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = match date.split('-').collect::<Vec<_>>().as_slice() {
+        [year] => (number(year, 4)?, 1, 1),
+        [year, month] => (number(year, 4)?, number(month, 2)?, 1),
+        [year, month, day] => (number(year, 4)?, number(month, 2)?, number(day, 2)?),
+        _ => return None,
+    };
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    Some(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+/// Formats a number of seconds as the `H+:MM:SS` time values of `AVTransport`.
+pub(crate) fn format_time(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    let seconds = seconds % 60;
+    format!("{hours:02}:{minutes:02}:{seconds:02}")
+}
+
+pub fn parse_supported_protocols(xml_root: &str) -> Result<Vec<String>, UpnpError> {
     let mut in_protocol = false;
     let mut protocols = String::new();
-    for e in parser {
+    for e in XmlEvents::new(xml_root) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            XmlEvent::StartElement { name, .. } => {
                 if name.local_name == "Sink" {
                     in_protocol = true;
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
+            XmlEvent::EndElement { name } => {
                 if name.local_name == "Sink" {
                     in_protocol = false;
                 }
             }
-            Ok(XmlEvent::Characters(protocol)) => {
+            XmlEvent::Characters(protocol) => {
                 if in_protocol {
                     protocols = protocol;
                 }
@@ -340,23 +805,147 @@ pub fn parse_supported_protocols(xml_root: &str) -> Result<Vec<String>> {
         .collect())
 }
 
-pub fn parse_last_change(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
+/// Parses the `Source` and `Sink` lists of a `GetProtocolInfo` response, skipping
+/// malformed entries.
+pub fn parse_protocol_info(xml_root: &str) -> Result<ProtocolInfo, UpnpError> {
+    let entries = |element| -> Result<Vec<ProtocolInfoEntry>> {
+        Ok(parse_element_text(xml_root, element)?
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| entry.parse().ok())
+            .collect())
+    };
+    Ok(ProtocolInfo {
+        source: entries("Source")?,
+        sink: entries("Sink")?,
+    })
+}
+
+/// Parses a `GetCurrentConnectionIDs` response, skipping malformed ids.
+pub fn parse_current_connection_ids(xml_root: &str) -> Result<Vec<i32>, UpnpError> {
+    Ok(parse_list_element(xml_root, "ConnectionIDs")?
+        .iter()
+        .filter_map(|id| id.parse().ok())
+        .collect())
+}
+
+/// Parses a `GetCurrentConnectionInfo` response.
+pub fn parse_connection_info(xml_root: &str) -> Result<ConnectionInfo, UpnpError> {
+    let text = |element| -> Result<String> {
+        Ok(parse_element_text(xml_root, element)?
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default())
+    };
+    let instance = |element| -> Result<i32> { Ok(text(element)?.parse().unwrap_or(-1)) };
+    Ok(ConnectionInfo {
+        rcs_id: instance("RcsID")?,
+        av_transport_id: instance("AVTransportID")?,
+        protocol_info: text("ProtocolInfo")?.parse().ok(),
+        direction: text("Direction")?,
+        status: text("Status")?,
+    })
+}
+
+/// Parses a `QueryStateVariable` response into the value of the variable queried.
+pub fn parse_query_state_variable(xml_root: &str) -> Result<String, UpnpError> {
+    parse_element_text(xml_root, "return")?.ok_or_else(|| UpnpError::MissingElement {
+        name: "return".to_string(),
+    })
+}
+
+/// The fault a device answered an action with, or `None` when `xml` isn't a SOAP fault.
+///
+/// The code and description come from the `UPnPError` detail of the fault, falling back
+/// to a code of 0 and the `faultstring` for faults that don't carry one.
+#[must_use]
+pub fn parse_soap_fault(xml: &str) -> Option<UpnpError> {
+    parse_element_text(xml, "Fault").ok()??;
+    let text = |element| parse_element_text(xml, element).ok().flatten();
+    Some(UpnpError::SoapFault {
+        code: text("errorCode")
+            .and_then(|code| code.trim().parse().ok())
+            .unwrap_or_default(),
+        description: text("errorDescription")
+            .or_else(|| text("faultstring"))
+            .map(|description| description.trim().to_string())
+            .unwrap_or_default(),
+    })
+}
+
+/// Decodes a GENA `NOTIFY` request from its `SID` and `SEQ` headers and its body.
+///
+/// Fails when `SEQ` isn't a number, as such a message couldn't be told apart from an
+/// initial event.
+pub fn parse_notify_message(sid: &str, seq: &str, body: &str) -> Result<NotifyMessage, UpnpError> {
+    Ok(NotifyMessage {
+        sid: sid.trim().to_string(),
+        seq: seq
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid SEQ header {seq:?}"))?,
+        event: parse_last_change_event(body)?,
+    })
+}
+
+/// Decodes the body of a GENA `NOTIFY` request: every property of its `propertyset`,
+/// with the variables of a `LastChange` property reported one by one.
+pub fn parse_last_change_event(body: &str) -> Result<LastChangeEvent, UpnpError> {
+    let root = Element::from_reader(body.as_bytes())?;
+    let mut changes = Vec::new();
+    for variable in root.children().flat_map(Element::children) {
+        if variable.tag().name() == "LastChange" {
+            changes.extend(parse_state_changes(variable.text())?);
+        } else {
+            changes.push(StateChange {
+                name: variable.tag().name().to_string(),
+                value: variable.text().to_string(),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(LastChangeEvent { changes })
+}
+
+/// Lists the variables of an (unescaped) `LastChange` document, per `InstanceID`.
+fn parse_state_changes(last_change: &str) -> Result<Vec<StateChange>> {
+    if last_change.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let root = Element::from_reader(last_change.as_bytes())?;
+    let mut changes = Vec::new();
+    for instance in root.children() {
+        let instance_id = instance
+            .get_attr("val")
+            .and_then(|id| id.trim().parse().ok())
+            .unwrap_or_default();
+        for variable in instance.children() {
+            changes.push(StateChange {
+                instance_id,
+                name: variable.tag().name().to_string(),
+                channel: variable.get_attr("channel").map(str::to_string),
+                value: variable.get_attr("val").unwrap_or_default().to_string(),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+pub fn parse_last_change(xml_root: &str) -> Result<Option<String>, UpnpError> {
     let mut result = None;
     let mut in_last_change = false;
-    for e in parser {
+    for e in XmlEvents::new(xml_root) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            XmlEvent::StartElement { name, .. } => {
                 if name.local_name == "LastChange" {
                     in_last_change = true;
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
+            XmlEvent::EndElement { name } => {
                 if name.local_name == "LastChange" {
                     in_last_change = false;
                 }
             }
-            Ok(XmlEvent::Characters(last_change)) => {
+            XmlEvent::Characters(last_change) => {
                 if in_last_change {
                     result = Some(last_change);
                 }
@@ -367,141 +956,251 @@ pub fn parse_last_change(xml_root: &str) -> Result<Option<String>> {
     Ok(result)
 }
 
-pub fn parse_current_play_mode(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut current_play_mode: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
+/// Normalizes the variables of an (unescaped) `AVTransport` or `RenderingControl`
+/// `LastChange` event body into [`RendererEvent`]s. Only the `Master` channel volume is
+/// reported.
+#[must_use]
+pub fn parse_renderer_events(last_change: &str) -> Vec<RendererEvent> {
+    let mut events = Vec::new();
+    for e in XmlEvents::new(last_change) {
+        let XmlEvent::StartElement {
             name, attributes, ..
         } = e
-        {
-            if name.local_name == "CurrentPlayMode" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        current_play_mode = Some(attr.value);
-                    }
+        else {
+            continue;
+        };
+        let attr = |attr_name: &str| {
+            attributes
+                .iter()
+                .find(|attr| attr.name.local_name == attr_name)
+                .map(|attr| attr.value.as_str())
+        };
+        let Some(val) = attr("val") else {
+            continue;
+        };
+        match name.local_name.as_str() {
+            "TransportState" => events.push(RendererEvent::TransportStateChanged(val.to_string())),
+            "RelativeTimePosition" => {
+                if let Some(position) = parse_time(val) {
+                    events.push(RendererEvent::PositionChanged(position));
+                }
+            }
+            "Volume" if attr("channel").map_or(true, |channel| channel == "Master") => {
+                if let Ok(volume) = val.parse() {
+                    events.push(RendererEvent::VolumeChanged(volume));
+                }
+            }
+            "CurrentTrackMetaData" if !val.is_empty() && val != "NOT_IMPLEMENTED" => {
+                if let Ok(metadata) = deserialize_metadata(val) {
+                    events.push(RendererEvent::TrackChanged(metadata));
                 }
             }
+            _ => {}
         }
     }
-    Ok(current_play_mode)
+    events
 }
 
-pub fn parse_transport_state(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut transport_state: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "TransportState" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        transport_state = Some(attr.value);
-                    }
-                }
+pub fn parse_current_play_mode(xml_root: &str) -> Result<Option<String>, UpnpError> {
+    parse_val_attr(xml_root, "CurrentPlayMode")
+}
+
+pub fn parse_transport_state(xml_root: &str) -> Result<Option<String>, UpnpError> {
+    parse_val_attr(xml_root, "TransportState")
+}
+
+pub fn parse_av_transport_uri_metadata(xml_root: &str) -> Result<Option<String>, UpnpError> {
+    parse_val_attr(xml_root, "AVTransportURIMetaData")
+}
+
+pub fn parse_current_track_metadata(xml_root: &str) -> Result<Option<String>, UpnpError> {
+    parse_val_attr(xml_root, "CurrentTrackMetaData")
+}
+
+/// How the DIDL-Lite parsers treat a malformed document.
+///
+/// The other event-based parsers, such as [`parse_volume`] or [`parse_transport_info`],
+/// take no mode and are always lenient: they return what they read before the error, and
+/// only fail if that lacks the value they parse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Keep the objects read before the error, which is reported as a warning. Real
+    /// servers emit the odd invalid character, which shouldn't cost a whole `Browse`.
+    #[default]
+    Lenient,
+    /// Fail with [`UpnpError::XmlParse`].
+    Strict,
+}
+
+/// The events of an XML document, up to its end or up to its first error, which ends the
+/// iteration rather than being yielded. Every event-based parser reads documents through
+/// it, so they all keep what they extracted before an error. Only the DIDL-Lite parsers
+/// report that error, through [`Self::finish`].
+struct XmlEvents<'a> {
+    events: xml::reader::Events<&'a [u8]>,
+    error: Option<xml::reader::Error>,
+}
+
+impl<'a> XmlEvents<'a> {
+    fn new(xml: &'a str) -> Self {
+        Self {
+            events: EventReader::new(xml.as_bytes()).into_iter(),
+            error: None,
+        }
+    }
+
+    const fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The error that ended the iteration, if any, as `mode` has it handled: returned
+    /// when strict, or else pushed to `warnings`.
+    fn finish(self, mode: ParseMode, warnings: &mut Vec<String>) -> Result<(), UpnpError> {
+        match (self.error, mode) {
+            (None, _) => Ok(()),
+            (Some(error), ParseMode::Strict) => Err(error.into()),
+            (Some(error), ParseMode::Lenient) => {
+                warnings.push(format!(
+                    "Malformed XML, kept what preceded the error: {error}"
+                ));
+                Ok(())
             }
         }
     }
-    Ok(transport_state)
 }
 
-pub fn parse_av_transport_uri_metadata(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut av_transport_uri_metadata: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "AVTransportURIMetaData" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        av_transport_uri_metadata = Some(attr.value);
-                    }
-                }
+impl Iterator for XmlEvents<'_> {
+    type Item = XmlEvent;
+
+    fn next(&mut self) -> Option<XmlEvent> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.events.next()? {
+            Ok(event) => Some(event),
+            Err(error) => {
+                self.error = Some(error);
+                None
             }
         }
     }
-    Ok(av_transport_uri_metadata)
 }
 
-pub fn parse_current_track_metadata(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut current_track_metadata: Option<String> = None;
-    for e in parser.into_iter().flatten() {
+/// Extracts the `val` attribute of the last `element` found in a `LastChange` event body,
+/// e.g. `<TransportState val="PLAYING"/>`.
+#[allow(clippy::unnecessary_wraps)]
+fn parse_val_attr(xml_root: &str, element: &str) -> Result<Option<String>, UpnpError> {
+    let mut value: Option<String> = None;
+    for e in XmlEvents::new(xml_root) {
         if let XmlEvent::StartElement {
             name, attributes, ..
         } = e
         {
-            if name.local_name == "CurrentTrackMetaData" {
+            if name.local_name == element {
                 for attr in attributes {
                     if attr.name.local_name == "val" {
-                        current_track_metadata = Some(attr.value);
+                        value = Some(attr.value);
                     }
                 }
             }
         }
     }
-    Ok(current_track_metadata)
+    Ok(value)
+}
+
+/// Extracts the text of the last `element` found in a SOAP response body, e.g.
+/// `<CurrentVolume>42</CurrentVolume>`.
+#[allow(clippy::unnecessary_wraps)]
+fn parse_element_text(xml_root: &str, element: &str) -> Result<Option<String>> {
+    let mut in_element = false;
+    let mut value: Option<String> = None;
+    for e in XmlEvents::new(xml_root) {
+        match e {
+            XmlEvent::StartElement { name, .. } if name.local_name == element => {
+                in_element = true;
+                value = Some(String::new());
+            }
+            XmlEvent::EndElement { name } if name.local_name == element => {
+                in_element = false;
+            }
+            XmlEvent::Characters(text) if in_element => {
+                value = Some(text);
+            }
+            _ => {}
+        }
+    }
+    Ok(value)
 }
 
-pub fn deserialize_metadata(xml: &str) -> Result<Metadata> {
-    let parser = EventReader::from_str(xml);
+/// Parses the DIDL-Lite metadata of the item a renderer is playing. The URL is the text
+/// of its `res`, or its `id` when it has none.
+pub fn deserialize_metadata(xml: &str) -> Result<Metadata, UpnpError> {
     let mut in_title = false;
+    let mut in_creator = false;
     let mut in_artist = false;
     let mut in_album = false;
     let mut in_album_art = false;
+    let mut in_genre = false;
+    let mut in_res = false;
     let mut title: Option<String> = None;
+    let mut creator: Option<String> = None;
     let mut artist: Option<String> = None;
     let mut album: Option<String> = None;
     let mut album_art: Option<String> = None;
-    let mut url: String = String::new();
+    let mut genre: Option<String> = None;
+    let mut id: String = String::new();
+    let mut res: Option<String> = None;
+    let mut protocol_info: String = String::new();
+    let mut duration: Option<Duration> = None;
 
-    for e in parser {
+    for e in XmlEvents::new(xml) {
         match e {
-            Ok(XmlEvent::StartElement {
+            XmlEvent::StartElement {
                 name, attributes, ..
-            }) => {
+            } => {
                 if name.local_name == "item" {
                     for attr in attributes {
                         if attr.name.local_name == "id" {
-                            url = attr.value;
+                            id = attr.value;
                         }
                     }
+                } else if name.local_name == "res" && res.is_none() {
+                    for attr in attributes {
+                        match attr.name.local_name.as_str() {
+                            "protocolInfo" => protocol_info = attr.value,
+                            "duration" => duration = parse_time(&attr.value),
+                            _ => {}
+                        }
+                    }
+                    in_res = true;
                 }
-                if name.local_name == "title" {
-                    in_title = true;
-                }
-                if name.local_name == "artist" {
-                    in_artist = true;
-                }
-                if name.local_name == "album" {
-                    in_album = true;
-                }
-                if name.local_name == "albumArtURI" {
-                    in_album_art = true;
-                }
-            }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "title" {
-                    in_title = false;
-                }
-                if name.local_name == "artist" {
-                    in_artist = false;
-                }
-                if name.local_name == "album" {
-                    in_album = false;
-                }
-                if name.local_name == "albumArtURI" {
-                    in_album_art = false;
+                match name.local_name.as_str() {
+                    "title" => in_title = true,
+                    "creator" => in_creator = true,
+                    "artist" => in_artist = true,
+                    "album" => in_album = true,
+                    "albumArtURI" => in_album_art = true,
+                    "genre" => in_genre = true,
+                    _ => {}
                 }
             }
-            Ok(XmlEvent::Characters(value)) => {
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "title" => in_title = false,
+                "creator" => in_creator = false,
+                "artist" => in_artist = false,
+                "album" => in_album = false,
+                "albumArtURI" => in_album_art = false,
+                "genre" => in_genre = false,
+                "res" => in_res = false,
+                _ => {}
+            },
+            XmlEvent::Characters(value) => {
                 if in_title {
                     title = Some(value.clone());
                 }
+                if in_creator {
+                    creator = Some(value.clone());
+                }
                 if in_artist {
                     artist = Some(value.clone());
                 }
@@ -511,81 +1210,160 @@ pub fn deserialize_metadata(xml: &str) -> Result<Metadata> {
                 if in_album_art {
                     album_art = Some(value.clone());
                 }
+                if in_genre {
+                    genre = Some(value.clone());
+                }
+                if in_res {
+                    res = Some(value.clone());
+                }
             }
             _ => {}
         }
     }
     Ok(Metadata {
         title: title.unwrap_or_default(),
+        creator,
         artist,
         album,
         album_art_uri: album_art,
-        url,
-        ..Default::default()
+        genre,
+        url: res.unwrap_or(id),
+        protocol_info,
+        duration,
     })
 }
 
-pub fn parse_browse_response(xml: &str, ip: &str) -> Result<(Vec<Container>, Vec<Item>)> {
-    let parser = EventReader::from_str(xml);
+pub fn parse_browse_response(
+    xml: &str,
+    ip: &str,
+) -> Result<(Vec<Container>, Vec<Item>), UpnpError> {
+    let result = parse_browse_result(xml, ip)?;
+    Ok((result.containers, result.items))
+}
+
+/// Parses a `Browse` or `Search` response, including the paging counters. A malformed
+/// `Result` yields the objects preceding the error, which is reported in `warnings`.
+pub fn parse_browse_result(xml: &str, ip: &str) -> Result<BrowseResult, UpnpError> {
+    parse_browse_result_with_mode(xml, ip, ParseMode::default())
+}
+
+/// Parses a `Browse` or `Search` response like [`parse_browse_result`], treating a
+/// malformed `Result` as `mode` says.
+pub fn parse_browse_result_with_mode(
+    xml: &str,
+    ip: &str,
+    mode: ParseMode,
+) -> Result<BrowseResult, UpnpError> {
+    let mut reader = XmlEvents::new(xml);
     let mut in_result = false;
-    let mut result: (Vec<Container>, Vec<Item>) = (Vec::new(), Vec::new());
+    let mut in_number_returned = false;
+    let mut in_total_matches = false;
+    let mut in_update_id = false;
+    let mut result = BrowseResult::default();
 
-    for e in parser {
+    for e in &mut reader {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "Result" {
-                    in_result = true;
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "Result" => in_result = true,
+                "NumberReturned" => in_number_returned = true,
+                "TotalMatches" => in_total_matches = true,
+                "UpdateID" => in_update_id = true,
+                _ => {}
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "Result" => in_result = false,
+                "NumberReturned" => in_number_returned = false,
+                "TotalMatches" => in_total_matches = false,
+                "UpdateID" => in_update_id = false,
+                _ => {}
+            },
+            XmlEvent::Characters(value) => {
+                if in_result {
+                    (result.containers, result.items) =
+                        parse_didl(&value, ip, mode, &mut result.warnings)?;
                 }
-            }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "Result" {
-                    in_result = false;
+                if in_number_returned {
+                    result.number_returned = value.trim().parse()?;
                 }
-            }
-            Ok(XmlEvent::Characters(value)) => {
-                if in_result {
-                    result = deserialize_content_directory(&value, ip)?;
+                if in_total_matches {
+                    result.total_matches = value.trim().parse()?;
+                }
+                if in_update_id {
+                    result.update_id = value.trim().parse().ok();
                 }
             }
             _ => {}
         }
     }
+    reader.finish(mode, &mut result.warnings)?;
     Ok(result)
 }
 
-pub fn deserialize_content_directory(xml: &str, ip: &str) -> Result<(Vec<Container>, Vec<Item>)> {
-    let parser = EventReader::from_str(xml);
-    let mut in_container = false;
-    let mut in_item = false;
+/// Parses the containers and items of a DIDL-Lite document.
+///
+/// The URL of an item is its first playable resource served from `ip`, the host of the
+/// server, or else its first playable resource at all. An empty `ip` accepts any host.
+/// A malformed document yields the objects preceding the error.
+pub fn deserialize_content_directory(
+    xml: &str,
+    ip: &str,
+) -> Result<(Vec<Container>, Vec<Item>), UpnpError> {
+    parse_didl(xml, ip, ParseMode::default(), &mut Vec::new())
+}
+
+/// An open DIDL-Lite object, by its index in the containers or items parsed so far.
+#[derive(Debug, Clone, Copy)]
+enum DidlObject {
+    Container(usize),
+    Item(usize),
+}
+
+fn parse_didl(
+    xml: &str,
+    ip: &str,
+    mode: ParseMode,
+    warnings: &mut Vec<String>,
+) -> Result<(Vec<Container>, Vec<Item>), UpnpError> {
+    let mut reader = XmlEvents::new(xml);
+    // The objects enclosing the current element, innermost last, which its text and
+    // resources belong to whatever the order in which objects open and close.
+    let mut open: Vec<DidlObject> = Vec::new();
     let mut in_title = false;
+    let mut in_creator = false;
+    let mut in_date = false;
     let mut in_artist = false;
     let mut in_album = false;
     let mut in_album_art = false;
     let mut in_genre = false;
+    let mut in_icon = false;
     let mut in_class = false;
     let mut in_res = false;
+    let mut in_language = false;
+    let mut languages: Vec<String> = Vec::new();
     let mut containers: Vec<Container> = Vec::new();
     let mut items: Vec<Item> = Vec::new();
 
-    for e in parser {
+    for e in &mut reader {
         match e {
-            Ok(XmlEvent::StartElement {
+            XmlEvent::StartElement {
                 name, attributes, ..
-            }) => match name.local_name.as_str() {
+            } => match name.local_name.as_str() {
                 "container" => {
-                    in_container = true;
                     let mut container = Container::default();
                     for attr in attributes {
-                        if attr.name.local_name == "id" {
-                            container.id = attr.value;
-                        } else if attr.name.local_name == "parentID" {
-                            container.parent_id = attr.value;
+                        match attr.name.local_name.as_str() {
+                            "id" => container.id = attr.value,
+                            "parentID" => container.parent_id = attr.value,
+                            // Optional, and only a hint, so a malformed count is ignored.
+                            "childCount" => container.child_count = attr.value.trim().parse().ok(),
+                            _ => {}
                         }
                     }
+                    open.push(DidlObject::Container(containers.len()));
                     containers.push(container);
                 }
                 "item" => {
-                    in_item = true;
+                    languages.clear();
                     let mut item = Item::default();
                     for attr in attributes {
                         if attr.name.local_name == "id" {
@@ -594,11 +1372,18 @@ pub fn deserialize_content_directory(xml: &str, ip: &str) -> Result<(Vec<Contain
                             item.parent_id = attr.value;
                         }
                     }
+                    open.push(DidlObject::Item(items.len()));
                     items.push(item);
                 }
                 "title" => {
                     in_title = true;
                 }
+                "creator" => {
+                    in_creator = true;
+                }
+                "date" => {
+                    in_date = true;
+                }
                 "artist" => {
                     in_artist = true;
                 }
@@ -611,112 +1396,351 @@ pub fn deserialize_content_directory(xml: &str, ip: &str) -> Result<(Vec<Contain
                 "genre" => {
                     in_genre = true;
                 }
+                "icon" => {
+                    in_icon = true;
+                }
                 "class" => {
                     in_class = true;
                 }
+                "language" => {
+                    in_language = true;
+                }
+                // Containers have no playable resource, so a `res` only matters inside
+                // an item.
                 "res" => {
-                    for attr in attributes {
-                        match attr.name.local_name.as_str() {
-                            "protocolInfo" => {
-                                if attr.value.contains("audio") || attr.value.contains("video") {
-                                    items.last_mut().unwrap().protocol_info = attr.value;
-                                }
-                            }
-                            "size" => {
-                                items.last_mut().unwrap().size = Some(attr.value.parse()?);
-                            }
-                            "duration" => {
-                                items.last_mut().unwrap().duration = Some(attr.value);
-                            }
-                            _ => {}
-                        }
-                    }
+                    let Some(&DidlObject::Item(index)) = open.last() else {
+                        continue;
+                    };
+                    items[index]
+                        .resources
+                        .push(parse_resource(attributes, mode, warnings)?);
                     in_res = true;
                 }
                 _ => {}
             },
-            Ok(XmlEvent::EndElement { name }) => match name.local_name.as_str() {
-                "container" => in_container = false,
-                "item" => in_item = false,
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "container" => {
+                    if let Some(DidlObject::Container(_)) = open.last() {
+                        open.pop();
+                    }
+                }
+                "item" => {
+                    if let Some(&DidlObject::Item(index)) = open.last() {
+                        open.pop();
+                        let item = &mut items[index];
+                        tag_languages(&mut item.resources, &languages);
+                        select_url(item, ip);
+                    }
+                }
+                "language" => in_language = false,
                 "title" => in_title = false,
+                "creator" => in_creator = false,
+                "date" => in_date = false,
                 "artist" => in_artist = false,
                 "album" => in_album = false,
                 "albumArtURI" => in_album_art = false,
                 "genre" => in_genre = false,
+                "icon" => in_icon = false,
                 "class" => in_class = false,
                 "res" => in_res = false,
                 _ => {}
             },
-            Ok(XmlEvent::Characters(value)) => {
-                if in_container {
-                    if let Some(container) = containers.last_mut() {
-                        if in_title {
-                            container.title.clone_from(&value);
-                        }
-                        if in_class {
-                            container.object_class = Some(value.as_str().into());
-                        }
+            XmlEvent::Characters(value) => match open.last() {
+                Some(&DidlObject::Container(index)) => {
+                    let container = &mut containers[index];
+                    if in_title {
+                        container.title.clone_from(&value);
+                    }
+                    if in_creator {
+                        container.creator = Some(value.clone());
+                    }
+                    if in_date {
+                        container.date = Some(value.clone());
+                    }
+                    if in_class {
+                        container.object_class = Some(value.as_str().into());
                     }
                 }
-                if in_item {
-                    if let Some(item) = items.last_mut() {
-                        if in_title {
-                            item.title.clone_from(&value);
-                        }
-                        if in_artist {
-                            item.artist = Some(value.clone());
-                        }
-                        if in_album {
-                            item.album = Some(value.clone());
-                        }
-                        if in_album_art {
-                            item.album_art_uri = Some(value.clone());
-                        }
-                        if in_genre {
-                            item.genre = Some(value.clone());
-                        }
-                        if in_class {
-                            item.object_class = Some(value.as_str().into());
-                        }
-                        if in_res
-                            && item.url.is_empty()
-                            && value.contains(ip)
-                            && (item.protocol_info.contains("audio")
-                                || item.protocol_info.contains("video"))
-                        {
-                            item.url = value.clone();
+                Some(&DidlObject::Item(index)) => {
+                    let item = &mut items[index];
+                    if in_title {
+                        item.title.clone_from(&value);
+                    }
+                    if in_creator {
+                        item.creator = Some(value.clone());
+                    }
+                    if in_date {
+                        item.date = Some(value.clone());
+                    }
+                    if in_artist {
+                        item.artist = Some(value.clone());
+                    }
+                    if in_album {
+                        item.album = Some(value.clone());
+                    }
+                    if in_album_art {
+                        item.album_art_uri = Some(value.clone());
+                    }
+                    if in_genre {
+                        item.genre = Some(value.clone());
+                    }
+                    if in_icon {
+                        item.icon = Some(value.clone());
+                    }
+                    if in_class {
+                        item.object_class = Some(value.as_str().into());
+                    }
+                    if in_language {
+                        languages.push(value.trim().to_string());
+                    }
+                    if in_res {
+                        if let Some(resource) = item.resources.last_mut() {
+                            resource.url = normalize_url(&value);
                         }
                     }
                 }
-            }
+                None => {}
+            },
             _ => {}
         }
     }
+    if reader.failed() {
+        // The objects the error interrupted are missing whatever followed it.
+        for object in open.into_iter().rev() {
+            match object {
+                DidlObject::Container(index) => {
+                    containers.remove(index);
+                }
+                DidlObject::Item(index) => {
+                    items.remove(index);
+                }
+            }
+        }
+    }
+    reader.finish(mode, warnings)?;
     Ok((containers, items))
 }
 
-pub fn parse_transport_info(xml: &str) -> Result<TransportInfo> {
-    let parser = EventReader::from_str(xml);
+/// The resource a `res` element describes through its `attributes`, its URL being the
+/// text of the element. Malformed optional attributes other than `size`, which fails the
+/// parse, are ignored.
+/// Reads the attributes of a `res` element. An invalid `size` fails when `mode` is strict,
+/// or else is dropped with a warning, keeping the rest of the resource.
+fn parse_resource(
+    attributes: Vec<OwnedAttribute>,
+    mode: ParseMode,
+    warnings: &mut Vec<String>,
+) -> Result<Resource, UpnpError> {
+    let mut resource = Resource::default();
+    for attr in attributes {
+        let value = attr.value.trim();
+        match attr.name.local_name.as_str() {
+            "protocolInfo" => {
+                resource.language = protocol_language(&attr.value);
+                resource.protocol_info = attr.value;
+            }
+            "size" => match (value.parse(), mode) {
+                (Ok(size), _) => resource.size = Some(size),
+                (Err(_), ParseMode::Strict) => {
+                    return Err(UpnpError::XmlParse(format!("Invalid res size {value:?}")));
+                }
+                (Err(_), ParseMode::Lenient) => {
+                    warnings.push(format!("Ignored invalid res size {value:?}"));
+                }
+            },
+            "duration" => resource.duration = Some(attr.value),
+            "bitrate" => resource.bitrate = value.parse().ok(),
+            "sampleFrequency" => resource.sample_frequency = value.parse().ok(),
+            "nrAudioChannels" => resource.nr_audio_channels = value.parse().ok(),
+            "resolution" => {
+                if let Some((width, height)) = value.split_once(['x', 'X']) {
+                    resource.width = width.trim().parse().ok();
+                    resource.height = height.trim().parse().ok();
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(resource)
+}
+
+/// The `language` parameter of the fourth field of `protocol_info`, ignoring its case and
+/// any vendor prefix such as in `DLNA.ORG_LANGUAGE`.
+fn protocol_language(protocol_info: &str) -> Option<String> {
+    protocol_info
+        .split(':')
+        .nth(3)?
+        .split(';')
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| {
+            let name = name.trim();
+            name.rsplit(['.', '_'])
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("language"))
+        })
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Gives the `dc:language` values of an item to its resources without a `protocolInfo`
+/// language: the single value to all of them, or else one value per resource in order.
+fn tag_languages(resources: &mut [Resource], languages: &[String]) {
+    let untagged = resources
+        .iter_mut()
+        .filter(|resource| resource.language.is_none() && !resource.is_thumbnail());
+    if let [language] = languages {
+        untagged.for_each(|resource| resource.language = Some(language.clone()));
+    } else {
+        untagged
+            .zip(languages)
+            .for_each(|(resource, language)| resource.language = Some(language.clone()));
+    }
+}
+
+/// Sets the URL, protocol info, size and duration of `item` to those of its first
+/// playable resource served from `host`, or else its first playable resource wherever it
+/// is served from, as servers behind a proxy or advertising a host name rather than their
+/// address do. An empty `host` accepts the first playable resource outright. Items with
+/// nothing playable, such as photos, take the size of their first resource that isn't a
+/// thumbnail.
+fn select_url(item: &mut Item, host: &str) {
+    let playable = || {
+        item.resources
+            .iter()
+            .filter(|resource| is_playable(&resource.protocol_info))
+    };
+    if let Some(resource) = playable()
+        .find(|resource| is_on_host(&resource.url, host))
+        .or_else(|| playable().next())
+    {
+        item.url.clone_from(&resource.url);
+        item.protocol_info.clone_from(&resource.protocol_info);
+        item.size = resource.size;
+        item.duration.clone_from(&resource.duration);
+    } else if let Some(resource) = item
+        .resources
+        .iter()
+        .find(|resource| !resource.is_thumbnail())
+    {
+        item.size = resource.size;
+        item.duration.clone_from(&resource.duration);
+    }
+}
+
+/// Whether `url` points at `host`, an IP address, with or without the brackets of IPv6
+/// ones, or a domain name. An empty `host` matches every URL.
+///
+/// Hosts are compared parsed rather than as text, so that `192.168.1.2` doesn't match
+/// `192.168.1.20` while `fe80::1` matches `[FE80:0::1]`.
+fn is_on_host(url: &str, host: &str) -> bool {
+    let host = host.trim();
+    if host.is_empty() {
+        return true;
+    }
+    let Ok(url) = Url::parse(&normalize_url(url)) else {
+        return false;
+    };
+    let address = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse::<IpAddr>();
+    match (url.host(), address) {
+        (Some(Host::Ipv4(url_address)), Ok(IpAddr::V4(address))) => url_address == address,
+        (Some(Host::Ipv6(url_address)), Ok(IpAddr::V6(address))) => url_address == address,
+        (Some(Host::Domain(domain)), Err(_)) => domain.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// Whether content of `protocol_info` is audio or video, which renderers can play, as
+/// opposed to images.
+fn is_playable(protocol_info: &str) -> bool {
+    matches!(
+        protocol_media_type(protocol_info).as_deref(),
+        Some("audio" | "video")
+    )
+}
+
+/// Percent-encodes the characters some servers leave raw in `res` URLs, so that the result
+/// is a valid URL.
+///
+/// Those are spaces, quotes, non-ASCII characters, stray `%` and the like. Valid URLs,
+/// including already percent-encoded ones, are returned unchanged.
+#[must_use]
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let mut normalized = String::with_capacity(url.len());
+    for (index, c) in url.char_indices() {
+        match c {
+            '%' if is_percent_escape(&url[index + 1..]) => normalized.push(c),
+            ' ' | '"' | '<' | '>' | '`' | '{' | '}' | '|' | '\\' | '^' | '%' => {
+                let _ = write!(normalized, "%{:02X}", c as u8);
+            }
+            c if c.is_ascii_control() || !c.is_ascii() => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    let _ = write!(normalized, "%{byte:02X}");
+                }
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+fn is_percent_escape(rest: &str) -> bool {
+    let rest = rest.as_bytes();
+    rest.len() >= 2 && rest[0].is_ascii_hexdigit() && rest[1].is_ascii_hexdigit()
+}
+
+/// Parses the transport state, status and speed from either:
+///
+/// - a `GetTransportInfo` SOAP response, where they are the text of the
+///   `CurrentTransportState`, `CurrentTransportStatus` and `CurrentSpeed` elements, or
+/// - an (unescaped) `LastChange` event body, where they are the `val` attribute of the
+///   `TransportState`, `TransportStatus` and `TransportPlaySpeed` elements.
+///
+/// Fields missing from the document default to an empty state, an `OK` status and a
+/// speed of `1`, as a `LastChange` event only carries the variables that changed.
+pub fn parse_transport_info(xml: &str) -> Result<TransportInfo, UpnpError> {
     let mut in_transport_state = false;
     let mut in_transport_status = false;
     let mut in_transport_play_speed = false;
     let mut transport_info = TransportInfo::default();
 
-    for e in parser {
+    for e in XmlEvents::new(xml) {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => match name.local_name.as_str() {
-                "CurrentTransportState" => {
-                    in_transport_state = true;
-                }
-                "CurrentTransportStatus" => {
-                    in_transport_status = true;
-                }
-                "CurrentSpeed" => {
-                    in_transport_play_speed = true;
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let val = attributes
+                    .into_iter()
+                    .find(|attr| attr.name.local_name == "val")
+                    .map(|attr| attr.value);
+                match (name.local_name.as_str(), val) {
+                    ("CurrentTransportState", _) => {
+                        in_transport_state = true;
+                    }
+                    ("CurrentTransportStatus", _) => {
+                        in_transport_status = true;
+                    }
+                    ("CurrentSpeed", _) => {
+                        in_transport_play_speed = true;
+                    }
+                    ("TransportState", Some(val)) => {
+                        transport_info.current_transport_state = val;
+                    }
+                    ("TransportStatus", Some(val)) => {
+                        transport_info.current_transport_status = val;
+                    }
+                    ("TransportPlaySpeed", Some(val)) => {
+                        transport_info.current_speed = val;
+                    }
+                    _ => {}
                 }
-                _ => {}
-            },
-            Ok(XmlEvent::EndElement { name }) => match name.local_name.as_str() {
+            }
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
                 "CurrentTransportState" => {
                     in_transport_state = false;
                 }
@@ -728,7 +1752,7 @@ pub fn parse_transport_info(xml: &str) -> Result<TransportInfo> {
                 }
                 _ => {}
             },
-            Ok(XmlEvent::Characters(value)) => {
+            XmlEvent::Characters(value) => {
                 if in_transport_state {
                     transport_info.current_transport_state.clone_from(&value);
                 }
@@ -742,21 +1766,96 @@ pub fn parse_transport_info(xml: &str) -> Result<TransportInfo> {
             _ => {}
         }
     }
+
+    if transport_info.current_transport_status.is_empty() {
+        transport_info.current_transport_status = "OK".to_string();
+    }
+    if transport_info.current_speed.is_empty() {
+        transport_info.current_speed = "1".to_string();
+    }
     Ok(transport_info)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::parse_services;
+    use std::time::Duration;
 
-    #[tokio::test]
-    async fn test_parsing_device_without_service_list() {
+    use url::Url;
+    use xml::escape::escape_str_pcdata;
+
+    use crate::error::UpnpError;
+    use crate::parser::{
+        apply_scpd, deserialize_content_directory, normalize_url, parse_browse_result,
+        parse_browse_result_with_mode, parse_date, parse_description, parse_device,
+        parse_device_capabilities, parse_didl, parse_media_info, parse_notify_message,
+        parse_renderer_events, parse_service_list, parse_sort_capabilities, parse_time,
+        parse_transport_info, parse_transport_settings, parse_val_attr, parse_volume, ParseMode,
+    };
+    use crate::types::{
+        Icon, Item, Metadata, ObjectClass, RendererEvent, Resource, SpecVersion, StateChange,
+    };
+
+    #[test]
+    fn test_parse_description_and_apply_scpd() {
         const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
         <root xmlns="urn:schemas-upnp-org:device-1-0">
-            <specVersion>
-                <major>1</major>
-                <minor>0</minor>
-            </specVersion>
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                <friendlyName>Speaker</friendlyName>
+                <UDN>uuid:speaker</UDN>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:RenderingControl</serviceId>
+                        <SCPDURL>/rc.xml</SCPDURL>
+                        <controlURL>/control/rc</controlURL>
+                        <eventSubURL>/event/rc</eventSubURL>
+                    </service>
+                </serviceList>
+            </device>
+        </root>"#;
+        const SCPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0">
+            <actionList>
+                <action>
+                    <name>GetVolume</name>
+                    <argumentList>
+                        <argument>
+                            <name>CurrentVolume</name>
+                            <direction>out</direction>
+                            <relatedStateVariable>Volume</relatedStateVariable>
+                        </argument>
+                    </argumentList>
+                </action>
+            </actionList>
+            <serviceStateTable>
+                <stateVariable sendEvents="no">
+                    <name>Volume</name>
+                    <dataType>ui2</dataType>
+                </stateVariable>
+            </serviceStateTable>
+        </scpd>"#;
+
+        let mut device =
+            parse_description("http://192.168.1.30:1400/description.xml", XML_ROOT).unwrap();
+        assert_eq!(device.friendly_name, "Speaker");
+        let service = &mut device.services[0];
+        assert_eq!(service.scpd_url, "http://192.168.1.30:1400/rc.xml");
+        assert!(service.actions.is_empty());
+
+        apply_scpd(service, SCPD).unwrap();
+        assert_eq!(service.actions[0].name, "GetVolume");
+        assert_eq!(service.actions[0].arguments[0].name, "CurrentVolume");
+        assert_eq!(service.state_variables[0].name, "Volume");
+        assert!(!service.state_variables[0].send_events);
+    }
+
+    #[test]
+    fn test_parse_inventory_fields() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
             <device>
                 <deviceType>urn:schemas-upnp-org:device:WLANAccessPointDevice:1</deviceType>
                 <friendlyName>NETGEAR47B64C</friendlyName>
@@ -765,18 +1864,1227 @@ mod tests {
                 <modelDescription>NETGEAR Dual Band Access Point</modelDescription>
                 <modelName>WAX214</modelName>
                 <modelNumber>WAX214</modelNumber>
-                <modelURL>https://www.netgear.com</modelURL>
-                <firmwareVersion>2.1.1.3</firmwareVersion>
-                <insightMode>0</insightMode>
-                <serialNumber>XXXXXXXXX</serialNumber>
+                <modelURL>https://www.netgear.com/wax214</modelURL>
+                <serialNumber>6LA1234567890</serialNumber>
                 <UDN>uuid:919ba4ec-ec93-490f-b0e3-80CC9C47B64C</UDN>
-                <presentationURL>http://xxxxxx:1337/</presentationURL>
+                <presentationURL>/index.htm</presentationURL>
+                <deviceList>
+                    <device>
+                        <deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+                        <friendlyName>WANDevice</friendlyName>
+                        <serialNumber>6LA1234567891</serialNumber>
+                        <UDN>uuid:919ba4ec-ec93-490f-b0e3-80CC9C47B64D</UDN>
+                    </device>
+                </deviceList>
+            </device>
+        </root>"#;
+
+        let device = parse_description("http://192.168.1.1:1337/rootDesc.xml", XML_ROOT).unwrap();
+        assert_eq!(
+            device.model_url.as_deref(),
+            Some("https://www.netgear.com/wax214")
+        );
+        assert_eq!(device.serial_number.as_deref(), Some("6LA1234567890"));
+        assert_eq!(
+            device.presentation_url.as_deref(),
+            Some("http://192.168.1.1:1337/index.htm")
+        );
+
+        let embedded = &device.devices[0];
+        assert_eq!(embedded.serial_number.as_deref(), Some("6LA1234567891"));
+        assert_eq!(embedded.model_url, None);
+        assert_eq!(embedded.presentation_url, None);
+    }
+
+    #[test]
+    fn test_parse_icons() {
+        let with_icons = XML_PATH_ROOT.replace(
+            "<serviceList>",
+            "<iconList>
+                <icon>
+                    <mimetype>image/png</mimetype>
+                    <width>48</width>
+                    <height>48</height>
+                    <depth>24</depth>
+                    <url>/icons/sm.png</url>
+                </icon>
+                <icon>
+                    <mimetype>image/jpeg</mimetype>
+                    <width>120</width>
+                    <height>120</height>
+                    <depth>24</depth>
+                    <url>icons/lrg.jpg</url>
+                </icon>
+            </iconList>
+            <serviceList>",
+        );
+
+        let device =
+            parse_description("http://192.168.1.30:1400/xml/device.xml", &with_icons).unwrap();
+        assert_eq!(
+            device.icons,
+            [
+                Icon {
+                    mime_type: "image/png".to_string(),
+                    width: 48,
+                    height: 48,
+                    depth: 24,
+                    url: "http://192.168.1.30:1400/icons/sm.png".to_string(),
+                },
+                Icon {
+                    mime_type: "image/jpeg".to_string(),
+                    width: 120,
+                    height: 120,
+                    depth: 24,
+                    url: "http://192.168.1.30:1400/xml/icons/lrg.jpg".to_string(),
+                },
+            ]
+        );
+
+        let device =
+            parse_description("http://192.168.1.30:1400/xml/device.xml", XML_PATH_ROOT).unwrap();
+        assert!(device.icons.is_empty());
+    }
+
+    #[test]
+    fn test_embedded_devices_sharing_a_udn() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+                <friendlyName>Gateway</friendlyName>
+                <UDN>uuid:gateway</UDN>
+                <deviceList>
+                    <device>
+                        <deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+                        <friendlyName>WAN</friendlyName>
+                        <UDN>uuid:wan</UDN>
+                        <serviceList>
+                            <service>
+                                <serviceType>urn:schemas-upnp-org:service:WANCommonInterfaceConfig:1</serviceType>
+                                <serviceId>urn:upnp-org:serviceId:WANCommonIFC1</serviceId>
+                                <SCPDURL>/wancommon.xml</SCPDURL>
+                                <controlURL>/ctl/CommonIfCfg</controlURL>
+                                <eventSubURL>/evt/CommonIfCfg</eventSubURL>
+                            </service>
+                        </serviceList>
+                    </device>
+                    <device>
+                        <deviceType>urn:schemas-upnp-org:device:LANDevice:1</deviceType>
+                        <friendlyName>LAN</friendlyName>
+                        <UDN>uuid:wan</UDN>
+                    </device>
+                </deviceList>
+            </device>
+        </root>"#;
+
+        let device = parse_device("http://192.168.1.1:5000/rootDesc.xml", XML_ROOT).unwrap();
+        assert_eq!(device.devices.len(), 2);
+        assert_eq!(device.devices[0].friendly_name, "WAN");
+        assert_eq!(
+            device.devices[0].services[0].control_url,
+            "http://192.168.1.1:5000/ctl/CommonIfCfg"
+        );
+        assert_eq!(device.warnings.len(), 1);
+        assert!(device.warnings[0].contains("LAN"));
+        assert!(device.warnings[0].contains("uuid:wan"));
+    }
+
+    #[test]
+    fn test_embedded_urls_resolve_against_url_base() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <URLBase>http://192.168.1.20:49152/upnp/</URLBase>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaServer:1</deviceType>
+                <friendlyName>NAS</friendlyName>
+                <UDN>uuid:nas</UDN>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+                        <SCPDURL>/cds.xml</SCPDURL>
+                        <controlURL>/control/cds</controlURL>
+                        <eventSubURL>/event/cds</eventSubURL>
+                    </service>
+                </serviceList>
+                <deviceList>
+                    <device>
+                        <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                        <friendlyName>Player</friendlyName>
+                        <UDN>uuid:player</UDN>
+                        <serviceList>
+                            <service>
+                                <serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>
+                                <serviceId>urn:upnp-org:serviceId:AVTransport</serviceId>
+                                <SCPDURL>avt.xml</SCPDURL>
+                                <controlURL>control/avt</controlURL>
+                                <eventSubURL>event/avt</eventSubURL>
+                            </service>
+                        </serviceList>
+                    </device>
+                </deviceList>
             </device>
         </root>"#;
+        let location = "http://192.168.1.20:8080/description.xml";
 
-        let result = parse_services("http://xxxxxx:1337/", XML_ROOT)
-            .await
-            .unwrap();
-        assert_eq!(result.len(), 0);
+        let device = parse_device(location, XML_ROOT).unwrap();
+        let embedded = &device.devices[0].services[0];
+        assert_eq!(
+            embedded.control_url,
+            "http://192.168.1.20:49152/upnp/control/avt"
+        );
+        assert_eq!(embedded.scpd_url, "http://192.168.1.20:49152/upnp/avt.xml");
+
+        let services = parse_service_list("http://192.168.1.20:8080", XML_ROOT).unwrap();
+        assert_eq!(
+            services[0].control_url,
+            "http://192.168.1.20:49152/control/cds"
+        );
+
+        let without_url_base =
+            XML_ROOT.replace("<URLBase>http://192.168.1.20:49152/upnp/</URLBase>", "");
+        let device = parse_device(location, &without_url_base).unwrap();
+        assert_eq!(
+            device.devices[0].services[0].control_url,
+            "http://192.168.1.20:8080/control/avt"
+        );
+    }
+
+    const XML_PATH_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                <friendlyName>Speaker</friendlyName>
+                <UDN>uuid:speaker</UDN>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:AVTransport</serviceId>
+                        <SCPDURL>avt.xml</SCPDURL>
+                        <controlURL>/control/avt</controlURL>
+                        <eventSubURL> event/avt </eventSubURL>
+                    </service>
+                </serviceList>
+            </device>
+        </root>"#;
+
+    #[test]
+    fn test_urls_resolve_against_a_location_with_a_path() {
+        let device =
+            parse_description("http://192.168.1.30:1400/xml/device.xml", XML_PATH_ROOT).unwrap();
+        let service = &device.services[0];
+        assert_eq!(service.scpd_url, "http://192.168.1.30:1400/xml/avt.xml");
+        assert_eq!(service.control_url, "http://192.168.1.30:1400/control/avt");
+        assert_eq!(
+            service.event_sub_url,
+            "http://192.168.1.30:1400/xml/event/avt"
+        );
+
+        let device = parse_description("192.168.1.30:1400/xml/device.xml", XML_PATH_ROOT).unwrap();
+        assert_eq!(
+            device.services[0].scpd_url,
+            "http://192.168.1.30:1400/xml/avt.xml"
+        );
+
+        assert!(matches!(
+            parse_description("/xml/device.xml", XML_PATH_ROOT),
+            Err(UpnpError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_url_base_takes_precedence_over_the_location() {
+        let with_url_base = XML_PATH_ROOT.replace(
+            "<device>",
+            "<URLBase> http://192.168.1.30:49153/upnp/ </URLBase><device>",
+        );
+        let device =
+            parse_description("http://192.168.1.30:1400/xml/device.xml", &with_url_base).unwrap();
+        let service = &device.services[0];
+        assert_eq!(service.scpd_url, "http://192.168.1.30:49153/upnp/avt.xml");
+        assert_eq!(service.control_url, "http://192.168.1.30:49153/control/avt");
+
+        let with_invalid_url_base =
+            XML_PATH_ROOT.replace("<device>", "<URLBase>not a url</URLBase><device>");
+        let device = parse_description(
+            "http://192.168.1.30:1400/xml/device.xml",
+            &with_invalid_url_base,
+        )
+        .unwrap();
+        assert_eq!(
+            device.services[0].scpd_url,
+            "http://192.168.1.30:1400/xml/avt.xml"
+        );
+    }
+
+    #[test]
+    fn test_url_base_on_another_host() {
+        let location = "http://192.168.1.30:1400/xml/device.xml";
+        let with_url_base = XML_PATH_ROOT.replace(
+            "<device>",
+            "<URLBase>http://10.0.0.5:8080/legacy/</URLBase><device>",
+        );
+        let service = &parse_description(location, &with_url_base)
+            .unwrap()
+            .services[0];
+        assert_eq!(service.scpd_url, "http://10.0.0.5:8080/legacy/avt.xml");
+        assert_eq!(service.control_url, "http://10.0.0.5:8080/control/avt");
+        assert_eq!(
+            service.event_sub_url,
+            "http://10.0.0.5:8080/legacy/event/avt"
+        );
+
+        for empty in ["<URLBase></URLBase>", "<URLBase> </URLBase>", "<URLBase/>"] {
+            let with_empty_url_base =
+                XML_PATH_ROOT.replace("<device>", &format!("{empty}<device>"));
+            let service = &parse_description(location, &with_empty_url_base)
+                .unwrap()
+                .services[0];
+            assert_eq!(service.control_url, "http://192.168.1.30:1400/control/avt");
+        }
+    }
+
+    #[test]
+    fn test_parse_val_attr() {
+        const LAST_CHANGE: &str = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/">
+            <InstanceID val="0">
+                <TransportState val="PLAYING"/>
+                <CurrentPlayMode val="NORMAL"/>
+                <NumberOfTracks val="3"/>
+            </InstanceID>
+        </Event>"#;
+
+        assert_eq!(
+            parse_val_attr(LAST_CHANGE, "TransportState").unwrap(),
+            Some("PLAYING".to_string())
+        );
+        assert_eq!(
+            parse_val_attr(LAST_CHANGE, "NumberOfTracks").unwrap(),
+            Some("3".to_string())
+        );
+        assert_eq!(parse_val_attr(LAST_CHANGE, "CurrentMute").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parsing_upnp_2_0_device() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-2-0">
+            <specVersion>
+                <major>2</major>
+                <minor>0</minor>
+            </specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaServer:4</deviceType>
+                <friendlyName>Living Room NAS</friendlyName>
+                <manufacturer>ACME</manufacturer>
+                <manufacturerURL>https://acme.example</manufacturerURL>
+                <modelName>NAS-2000</modelName>
+                <modelNumber>2000</modelNumber>
+                <UDN>uuid:5c2d1a8e-0d9f-4f3c-9c3e-1b2a3c4d5e6f</UDN>
+            </device>
+        </root>"#;
+
+        let device = parse_device("http://xxxxxx:1337/desc.xml", XML_ROOT).unwrap();
+        assert_eq!(
+            device.spec_version,
+            Some(SpecVersion { major: 2, minor: 0 })
+        );
+        assert_eq!(
+            device.device_type,
+            "urn:schemas-upnp-org:device:MediaServer:4"
+        );
+        assert_eq!(device.friendly_name, "Living Room NAS");
+        assert_eq!(device.manufacturer, "ACME");
+        assert_eq!(
+            device.manufacturer_url,
+            Some("https://acme.example".to_string())
+        );
+        assert_eq!(device.model_name, "NAS-2000");
+        assert_eq!(device.model_number, Some("2000".to_string()));
+        assert_eq!(device.udn, "uuid:5c2d1a8e-0d9f-4f3c-9c3e-1b2a3c4d5e6f");
+
+        let services = parse_service_list("http://xxxxxx:1337/", XML_ROOT).unwrap();
+        assert_eq!(services.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_description_without_namespace() {
+        const XML_ROOT: &str = r#"<?xml version="1.0"?>
+        <root>
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:Belkin:device:controllee:1</deviceType>
+                <friendlyName>Desk Lamp</friendlyName>
+                <manufacturer>Belkin International Inc.</manufacturer>
+                <modelName>Socket</modelName>
+                <UDN>uuid:Socket-1_0-221517K0101769</UDN>
+                <iconList>
+                    <icon>
+                        <mimetype>image/png</mimetype>
+                        <width>48</width>
+                        <height>48</height>
+                        <depth>24</depth>
+                        <url>icon.png</url>
+                    </icon>
+                </iconList>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:Belkin:service:basicevent:1</serviceType>
+                        <serviceId>urn:Belkin:serviceId:basicevent1</serviceId>
+                        <controlURL>/upnp/control/basicevent1</controlURL>
+                        <eventSubURL>/upnp/event/basicevent1</eventSubURL>
+                        <SCPDURL>/eventservice.xml</SCPDURL>
+                    </service>
+                </serviceList>
+                <deviceList>
+                    <device>
+                        <friendlyName>Night Light</friendlyName>
+                        <UDN>uuid:Socket-1_0-221517K0101769-1</UDN>
+                    </device>
+                </deviceList>
+            </device>
+        </root>"#;
+        let location = "http://192.168.1.40:49153/setup.xml";
+
+        // Devices that declare a namespace of their own are read alike.
+        let vendor = XML_ROOT.replace("<root>", r#"<root xmlns="urn:Belkin:device-1-0">"#);
+        for xml_root in [XML_ROOT, vendor.as_str()] {
+            let device = parse_device(location, xml_root).unwrap();
+            assert_eq!(
+                device.spec_version,
+                Some(SpecVersion { major: 1, minor: 0 })
+            );
+            assert_eq!(device.device_type, "urn:Belkin:device:controllee:1");
+            assert_eq!(device.friendly_name, "Desk Lamp");
+            assert_eq!(device.manufacturer, "Belkin International Inc.");
+            assert_eq!(device.model_name, "Socket");
+            assert_eq!(device.udn, "uuid:Socket-1_0-221517K0101769");
+            assert_eq!(device.icons[0].url, "http://192.168.1.40:49153/icon.png");
+            assert_eq!(device.devices[0].friendly_name, "Night Light");
+
+            let services = parse_service_list(location, xml_root).unwrap();
+            assert_eq!(services.len(), 1);
+            assert_eq!(
+                services[0].control_url,
+                "http://192.168.1.40:49153/upnp/control/basicevent1"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_transport_info_from_soap_response() {
+        const RESPONSE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+        <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+            <s:Body>
+                <u:GetTransportInfoResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+                    <CurrentTransportState>PLAYING</CurrentTransportState>
+                    <CurrentTransportStatus>OK</CurrentTransportStatus>
+                    <CurrentSpeed>2</CurrentSpeed>
+                </u:GetTransportInfoResponse>
+            </s:Body>
+        </s:Envelope>"#;
+
+        let transport_info = parse_transport_info(RESPONSE).unwrap();
+        assert_eq!(transport_info.current_transport_state, "PLAYING");
+        assert_eq!(transport_info.current_transport_status, "OK");
+        assert_eq!(transport_info.current_speed, "2");
+    }
+
+    #[test]
+    fn test_parse_transport_info_from_last_change() {
+        const LAST_CHANGE: &str = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/">
+            <InstanceID val="0">
+                <TransportState val="PAUSED_PLAYBACK"/>
+            </InstanceID>
+        </Event>"#;
+
+        let transport_info = parse_transport_info(LAST_CHANGE).unwrap();
+        assert_eq!(transport_info.current_transport_state, "PAUSED_PLAYBACK");
+        assert_eq!(transport_info.current_transport_status, "OK");
+        assert_eq!(transport_info.current_speed, "1");
+    }
+
+    #[test]
+    fn test_parse_renderer_events() {
+        const AV_TRANSPORT: &str = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/">
+            <InstanceID val="0">
+                <TransportState val="PLAYING"/>
+                <RelativeTimePosition val="0:01:05"/>
+                <CurrentTrackMetaData val="&lt;DIDL-Lite xmlns=&quot;urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/&quot; xmlns:dc=&quot;http://purl.org/dc/elements/1.1/&quot;&gt;&lt;item id=&quot;1&quot;&gt;&lt;dc:title&gt;Song&lt;/dc:title&gt;&lt;res&gt;http://192.168.1.2/song.mp3&lt;/res&gt;&lt;/item&gt;&lt;/DIDL-Lite&gt;"/>
+            </InstanceID>
+        </Event>"#;
+        const RENDERING_CONTROL: &str = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/">
+            <InstanceID val="0">
+                <Volume channel="LF" val="10"/>
+                <Volume channel="Master" val="42"/>
+                <Mute channel="Master" val="0"/>
+            </InstanceID>
+        </Event>"#;
+
+        let events = parse_renderer_events(AV_TRANSPORT);
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            RendererEvent::TransportStateChanged("PLAYING".to_string())
+        );
+        assert_eq!(
+            events[1],
+            RendererEvent::PositionChanged(Duration::from_secs(65))
+        );
+        let RendererEvent::TrackChanged(metadata) = &events[2] else {
+            panic!("expected a track change, got {:?}", events[2]);
+        };
+        assert_eq!(metadata.title, "Song");
+        assert_eq!(metadata.url, "http://192.168.1.2/song.mp3");
+
+        assert_eq!(
+            parse_renderer_events(RENDERING_CONTROL),
+            vec![RendererEvent::VolumeChanged(42)]
+        );
+    }
+
+    #[test]
+    fn test_parse_recorder_capabilities_and_settings() {
+        const CAPABILITIES: &str = r#"<?xml version="1.0"?>
+            <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+                <u:GetDeviceCapabilitiesResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+                    <PlayMedia>NETWORK,HDD</PlayMedia>
+                    <RecMedia>HDD</RecMedia>
+                    <RecQualityModes>0:EP,1:LP,2:SP</RecQualityModes>
+                </u:GetDeviceCapabilitiesResponse>
+            </s:Body></s:Envelope>"#;
+        const SETTINGS: &str = r#"<?xml version="1.0"?>
+            <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+                <u:GetTransportSettingsResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+                    <PlayMode>NORMAL</PlayMode>
+                    <RecQualityMode>2:SP</RecQualityMode>
+                </u:GetTransportSettingsResponse>
+            </s:Body></s:Envelope>"#;
+
+        let capabilities = parse_device_capabilities(CAPABILITIES).unwrap();
+        assert_eq!(capabilities.play_media, ["NETWORK", "HDD"]);
+        assert_eq!(capabilities.rec_media, ["HDD"]);
+        assert_eq!(capabilities.rec_quality_modes, ["0:EP", "1:LP", "2:SP"]);
+        let settings = parse_transport_settings(SETTINGS).unwrap();
+        assert_eq!(settings.play_mode, "NORMAL");
+        assert_eq!(settings.rec_quality_mode.as_deref(), Some("2:SP"));
+    }
+
+    #[test]
+    fn test_parse_sort_capabilities() {
+        let response = |caps: &str| {
+            format!(
+                r#"<?xml version="1.0"?>
+                <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+                    <u:GetSortCapabilitiesResponse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1">
+                        <SortCaps>{caps}</SortCaps>
+                    </u:GetSortCapabilitiesResponse>
+                </s:Body></s:Envelope>"#
+            )
+        };
+
+        assert_eq!(
+            parse_sort_capabilities(&response("dc:title,upnp:album,upnp:originalTrackNumber"))
+                .unwrap(),
+            ["dc:title", "upnp:album", "upnp:originalTrackNumber"]
+        );
+        assert_eq!(parse_sort_capabilities(&response("*")).unwrap(), ["*"]);
+        assert!(parse_sort_capabilities(&response("")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_media_info_items() {
+        let didl = |id: &str, title: &str| {
+            escape_str_pcdata(&format!(
+                r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="{id}" parentID="0" restricted="1"><dc:title>{title}</dc:title><upnp:artist>Artist</upnp:artist><upnp:class>object.item.audioItem.musicTrack</upnp:class><res protocolInfo="http-get:*:audio/flac:*" duration="0:04:10">http://192.168.1.10:8200/{id}.flac</res></item></DIDL-Lite>"#
+            ))
+            .into_owned()
+        };
+        let response = format!(
+            r#"<?xml version="1.0"?>
+            <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+                <u:GetMediaInfoResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+                    <NrTracks>1</NrTracks>
+                    <MediaDuration>0:04:10</MediaDuration>
+                    <CurrentURI>http://192.168.1.10:8200/1.flac</CurrentURI>
+                    <CurrentURIMetaData>{}</CurrentURIMetaData>
+                    <NextURI>http://192.168.1.10:8200/2.flac</NextURI>
+                    <NextURIMetaData>{}</NextURIMetaData>
+                </u:GetMediaInfoResponse>
+            </s:Body></s:Envelope>"#,
+            didl("1", "Current"),
+            didl("2", "Next")
+        );
+
+        let info = parse_media_info(&response).unwrap();
+        let current = info.current_item.unwrap();
+        assert_eq!(current.id, "1");
+        assert_eq!(current.title, "Current");
+        assert_eq!(current.artist.as_deref(), Some("Artist"));
+        assert_eq!(current.url, "http://192.168.1.10:8200/1.flac");
+        assert_eq!(info.next_uri, "http://192.168.1.10:8200/2.flac");
+        let next = info.next_item.unwrap();
+        assert_eq!(next.title, "Next");
+        assert_eq!(next.url, "http://192.168.1.10:8200/2.flac");
+        assert_eq!(next.duration_parsed(), Some(Duration::from_secs(250)));
+
+        let response = response
+            .replace(&didl("1", "Current"), "")
+            .replace(&didl("2", "Next"), "NOT_IMPLEMENTED")
+            .replace("http://192.168.1.10:8200/2.flac", "NOT_IMPLEMENTED");
+        let info = parse_media_info(&response).unwrap();
+        assert!(info.current_item.is_none());
+        assert!(info.next_item.is_none());
+        assert!(info.next_uri.is_empty());
+    }
+
+    #[test]
+    fn test_parse_last_change_event() {
+        const BODY: &str = r#"<?xml version="1.0"?>
+        <e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">
+            <e:property><SinkProtocolInfo>http-get:*:audio/mpeg:*</SinkProtocolInfo></e:property>
+            <e:property>
+                <LastChange>&lt;Event xmlns=&quot;urn:schemas-upnp-org:metadata-1-0/AVT/&quot;&gt;&lt;InstanceID val=&quot;1&quot;&gt;&lt;TransportState val=&quot;STOPPED&quot;/&gt;&lt;/InstanceID&gt;&lt;/Event&gt;</LastChange>
+            </e:property>
+        </e:propertyset>"#;
+
+        let message = parse_notify_message("uuid:sub-1", "0", BODY).unwrap();
+        assert_eq!(message.sid, "uuid:sub-1");
+        assert_eq!(message.seq, 0);
+        assert!(message.is_initial());
+        assert_eq!(
+            message.event.changes,
+            vec![
+                StateChange {
+                    instance_id: 0,
+                    name: "SinkProtocolInfo".to_string(),
+                    channel: None,
+                    value: "http-get:*:audio/mpeg:*".to_string(),
+                },
+                StateChange {
+                    instance_id: 1,
+                    name: "TransportState".to_string(),
+                    channel: None,
+                    value: "STOPPED".to_string(),
+                },
+            ]
+        );
+
+        let message = parse_notify_message("uuid:sub-1", " 8 ", BODY).unwrap();
+        assert!(!message.is_initial());
+        assert!(message.follows(7));
+        assert!(!message.follows(8));
+        assert!(!message.follows(5));
+        let wrapped = parse_notify_message("uuid:sub-1", "1", BODY).unwrap();
+        assert!(wrapped.follows(u32::MAX));
+        assert!(parse_notify_message("uuid:sub-1", "", BODY).is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("0:03:25"), Some(Duration::from_secs(205)));
+        assert_eq!(
+            parse_time("01:00:00.500"),
+            Some(Duration::from_millis(3_600_500))
+        );
+        assert_eq!(parse_time("0:00:01.1/2"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_time("+0:01:00"), Some(Duration::from_secs(60)));
+        assert_eq!(parse_time("-0:01:00"), None);
+        assert_eq!(parse_time("NOT_IMPLEMENTED"), None);
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("0:61:00"), None);
+        assert_eq!(
+            parse_time("0:00:01.123456789123"),
+            Some(Duration::new(1, 123_456_789))
+        );
+        assert_eq!(parse_time("0:00:01.1/-2"), None);
+        assert_eq!(parse_time("0:00:01.nan/1"), None);
+        assert_eq!(parse_time("0:00:01.1e999"), None);
+        assert_eq!(parse_time("0:00:01.5e3"), None);
+        assert_eq!(parse_time("0:00:01.3/2"), None);
+        assert_eq!(parse_time("0:00:01.1/0"), None);
+        assert_eq!(
+            parse_time("0:00:01.1/340282366920938463463374607431768211455"),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            parse_time("0:00:01.340282366920938463463374607431768211454/340282366920938463463374607431768211455"),
+            None
+        );
+        assert_eq!(parse_time("0:00:01."), None);
+        assert_eq!(parse_time("18446744073709551615:00:00"), None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2021-06-01").as_deref(), Some("2021-06-01"));
+        assert_eq!(
+            parse_date("2021-06-01T20:15:00+02:00").as_deref(),
+            Some("2021-06-01")
+        );
+        assert_eq!(
+            parse_date("2021-06-01T20:15:00.000Z").as_deref(),
+            Some("2021-06-01")
+        );
+        assert_eq!(
+            parse_date(" 2021-06-01 20:15:00").as_deref(),
+            Some("2021-06-01")
+        );
+        assert_eq!(parse_date("2021-06").as_deref(), Some("2021-06-01"));
+        assert_eq!(parse_date("2021").as_deref(), Some("2021-01-01"));
+        assert_eq!(parse_date("2021-13-01"), None);
+        assert_eq!(parse_date("2021-02-31"), None);
+        assert_eq!(parse_date("2021-04-31"), None);
+        assert_eq!(parse_date("2021-02-29"), None);
+        assert_eq!(parse_date("2020-02-29").as_deref(), Some("2020-02-29"));
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2000-02-29").as_deref(), Some("2000-02-29"));
+        assert_eq!(parse_date("2021-06-1"), None);
+        assert_eq!(parse_date("01/06/2021"), None);
+        assert_eq!(parse_date("Unknown"), None);
+        assert_eq!(parse_date(""), None);
+    }
+
+    #[test]
+    fn test_item_date_parsed() {
+        let item = |date: &str| Item {
+            date: Some(date.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            item("2021-06-01").date_parsed().as_deref(),
+            Some("2021-06-01")
+        );
+        assert_eq!(
+            item("2021-06-01T08:30:00Z").date_parsed().as_deref(),
+            Some("2021-06-01")
+        );
+        assert_eq!(item("someday").date_parsed(), None);
+        assert_eq!(Item::default().date_parsed(), None);
+    }
+
+    #[test]
+    fn test_didl_date_and_creator() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="10" parentID="0" restricted="1">
+                <dc:title>Podcast</dc:title>
+                <dc:creator>Radio Station</dc:creator>
+                <dc:date>2021-06</dc:date>
+                <upnp:class>object.container.album</upnp:class>
+            </container>
+            <item id="11" parentID="10" restricted="1">
+                <dc:title>Episode</dc:title>
+                <dc:creator>Host</dc:creator>
+                <dc:date>2021-06-01T08:30:00Z</dc:date>
+                <upnp:class>object.item.audioItem</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.2/episode.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (containers, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert_eq!(containers[0].creator.as_deref(), Some("Radio Station"));
+        assert_eq!(containers[0].date_parsed().as_deref(), Some("2021-06-01"));
+        assert_eq!(items[0].creator.as_deref(), Some("Host"));
+        assert_eq!(items[0].date.as_deref(), Some("2021-06-01T08:30:00Z"));
+        assert_eq!(items[0].date_parsed().as_deref(), Some("2021-06-01"));
+        assert_eq!(Metadata::from(&items[0]).creator.as_deref(), Some("Host"));
+    }
+
+    #[test]
+    fn test_item_duration_parsed() {
+        let item = |duration: &str| Item {
+            duration: Some(duration.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            item("0:04:12.000").duration_parsed(),
+            Some(Duration::from_secs(252))
+        );
+        assert_eq!(
+            item("+0:04:12").duration_parsed(),
+            Some(Duration::from_secs(252))
+        );
+        assert_eq!(item("-0:04:12").duration_parsed(), None);
+        assert_eq!(item("NOT_IMPLEMENTED").duration_parsed(), None);
+        assert_eq!(item("").duration_parsed(), None);
+        // Live streams advertise a zero duration.
+        assert_eq!(item("0:00:00").duration_parsed(), None);
+        assert_eq!(Item::default().duration_parsed(), None);
+    }
+
+    #[test]
+    fn test_resource_file_extension() {
+        let resource = |protocol_info: &str, url: &str| Resource {
+            protocol_info: protocol_info.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        };
+
+        for (mime, extension) in [
+            ("audio/mpeg", "mp3"),
+            ("audio/x-flac", "flac"),
+            ("audio/L16;rate=44100;channels=2", "pcm"),
+            ("video/x-matroska", "mkv"),
+            ("video/mp4", "mp4"),
+            ("image/jpeg", "jpg"),
+        ] {
+            let protocol_info = format!("http-get:*:{mime}:*");
+            assert_eq!(
+                resource(&protocol_info, "http://192.168.1.2/stream").file_extension(),
+                Some(extension)
+            );
+        }
+
+        assert_eq!(
+            resource(
+                "http-get:*:application/octet-stream:*",
+                "http://192.168.1.2:8200/MediaItems/22.ogg?transcode=0"
+            )
+            .file_extension(),
+            Some("ogg")
+        );
+        assert_eq!(
+            resource("http-get:*:*:*", "http://192.168.1.2/music/song.opus").file_extension(),
+            Some("opus")
+        );
+        assert_eq!(
+            resource("", "http://media.example.com/stream").file_extension(),
+            None
+        );
+        assert_eq!(
+            resource("", "http://media.example.com").file_extension(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_every_res_is_kept() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <item id="1" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <res protocolInfo="http-get:*:audio/flac:*" size="31000000">http://192.168.1.2/song.flac</res>
+                <res protocolInfo="http-get:*:audio/mpeg:*" duration="0:03:25">http://192.168.1.2/song.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        let resources = &items[0].resources;
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0].url, "http://192.168.1.2/song.flac");
+        assert_eq!(resources[0].size, Some(31_000_000));
+        assert_eq!(resources[0].file_extension(), Some("flac"));
+        assert_eq!(resources[1].duration.as_deref(), Some("0:03:25"));
+        assert_eq!(resources[1].file_extension(), Some("mp3"));
+    }
+
+    #[test]
+    fn test_res_stream_attributes() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <item id="1" parentID="0" restricted="1">
+                <dc:title>Concert</dc:title>
+                <res protocolInfo="http-get:*:video/mp4:*" size="1500000000" duration="1:30:00.000" resolution="1920x1080" bitrate="2500000" sampleFrequency="48000" nrAudioChannels="6">http://192.168.1.2/concert.mp4</res>
+                <res protocolInfo="http-get:*:audio/flac:*" resolution="unknown" bitrate="" sampleFrequency="96000" nrAudioChannels="2">http://192.168.1.2/concert.flac</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        let video = &items[0].resources[0];
+        assert_eq!((video.width, video.height), (Some(1920), Some(1080)));
+        assert_eq!(video.bitrate, Some(2_500_000));
+        assert_eq!(video.sample_frequency, Some(48_000));
+        assert_eq!(video.nr_audio_channels, Some(6));
+        assert_eq!(video.size, Some(1_500_000_000));
+        assert_eq!(items[0].duration.as_deref(), Some("1:30:00.000"));
+
+        let audio = &items[0].resources[1];
+        assert_eq!((audio.width, audio.height), (None, None));
+        assert_eq!(audio.bitrate, None);
+        assert_eq!(audio.sample_frequency, Some(96_000));
+        assert_eq!(audio.nr_audio_channels, Some(2));
+    }
+
+    #[test]
+    fn test_interleaved_objects() {
+        // Some servers nest the items of a container in it rather than listing them apart.
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="10" parentID="0" restricted="1">
+                <dc:title>Album</dc:title>
+                <item id="11" parentID="10" restricted="1">
+                    <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.2/track.mp3</res>
+                    <dc:title>Track</dc:title>
+                    <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                </item>
+                <res protocolInfo="http-get:*:image/jpeg:*">http://192.168.1.2/cover.jpg</res>
+                <upnp:class>object.container.album.musicAlbum</upnp:class>
+            </container>
+            <item id="12" parentID="0" restricted="1">
+                <dc:title>Single</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.2/single.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (containers, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].title, "Album");
+        assert!(matches!(
+            containers[0].object_class,
+            Some(ObjectClass::Album(_))
+        ));
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Track");
+        assert_eq!(items[0].url, "http://192.168.1.2/track.mp3");
+        assert_eq!(items[0].resources.len(), 1);
+        assert!(matches!(
+            items[0].object_class,
+            Some(ObjectClass::MusicTrack(_))
+        ));
+        assert_eq!(items[1].title, "Single");
+        assert_eq!(items[1].url, "http://192.168.1.2/single.mp3");
+    }
+
+    #[test]
+    fn test_res_outside_an_item() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <res protocolInfo="http-get:*:image/jpeg:*" size="none">http://192.168.1.2/stray.jpg</res>
+            <container id="10" parentID="0" restricted="1">
+                <dc:title>Album</dc:title>
+                <upnp:class>object.container.album.musicAlbum</upnp:class>
+                <res protocolInfo="http-get:*:image/jpeg:*" size="unknown">http://192.168.1.2/cover.jpg</res>
+            </container>
+            <item id="11" parentID="10" restricted="1">
+                <dc:title>Track</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*" size="3000000">http://192.168.1.2/track.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (containers, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert_eq!(containers[0].title, "Album");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].size, Some(3_000_000));
+        assert_eq!(items[0].url, "http://192.168.1.2/track.mp3");
+    }
+
+    #[test]
+    fn test_invalid_res_size_strict() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <item id="11" parentID="10" restricted="1">
+                <dc:title>Track</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*" size="3 MB">http://192.168.1.2/track.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let error =
+            parse_didl(DIDL, "192.168.1.2", ParseMode::Strict, &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Malformed XML: Invalid res size "3 MB""#
+        );
+    }
+
+    #[test]
+    fn test_audio_track_languages() {
+        fn languages(item: &Item) -> Vec<Option<&str>> {
+            item.resources
+                .iter()
+                .map(|resource| resource.language.as_deref())
+                .collect()
+        }
+
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="1" parentID="0" restricted="1">
+                <dc:title>Movie</dc:title>
+                <upnp:class>object.item.videoItem.movie</upnp:class>
+                <res protocolInfo="http-get:*:video/mp4:language=en;DLNA.ORG_OP=01">http://192.168.1.2/movie.en.mp4</res>
+                <res protocolInfo="http-get:*:video/mp4:DLNA.ORG_LANGUAGE=fr;DLNA.ORG_OP=01">http://192.168.1.2/movie.fr.mp4</res>
+            </item>
+            <item id="2" parentID="0" restricted="1">
+                <dc:title>Film</dc:title>
+                <upnp:class>object.item.videoItem.movie</upnp:class>
+                <res protocolInfo="http-get:*:video/x-matroska:*">http://192.168.1.2/film.de.mkv</res>
+                <res protocolInfo="http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN">http://192.168.1.2/film.jpg</res>
+                <res protocolInfo="http-get:*:video/x-matroska:*">http://192.168.1.2/film.it.mkv</res>
+                <dc:language>de</dc:language>
+                <dc:language>it</dc:language>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert_eq!(languages(&items[0]), [Some("en"), Some("fr")]);
+        assert_eq!(languages(&items[1]), [Some("de"), None, Some("it")]);
+    }
+
+    #[test]
+    fn test_thumbnail_uri() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="1" parentID="0" restricted="1">
+                <dc:title>Holiday</dc:title>
+                <upnp:class>object.item.videoItem.movie</upnp:class>
+                <res protocolInfo="http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_BL_CIF15_AAC_520;DLNA.ORG_OP=01">http://192.168.1.2/holiday.mp4</res>
+                <res protocolInfo="http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN;DLNA.ORG_CI=1">http://192.168.1.2/holiday.jpg</res>
+            </item>
+            <item id="2" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <upnp:icon>http://192.168.1.2/icon.png</upnp:icon>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.2/song.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert_eq!(
+            items[0].thumbnail_uri(),
+            Some("http://192.168.1.2/holiday.jpg")
+        );
+        assert_eq!(
+            items[1].thumbnail_uri(),
+            Some("http://192.168.1.2/icon.png")
+        );
+
+        let with_album_art = Item {
+            album_art_uri: Some("http://192.168.1.2/art.jpg".to_string()),
+            ..items[0].clone()
+        };
+        assert_eq!(
+            with_album_art.thumbnail_uri(),
+            Some("http://192.168.1.2/art.jpg")
+        );
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        const VALID: &str = "http://192.168.1.10:8200/MediaItems/22.mp3?format=mp3%20hq";
+        assert_eq!(normalize_url(VALID), VALID);
+        assert_eq!(
+            normalize_url("http://192.168.1.10:8200/Music/100% Hits/Café.mp3"),
+            "http://192.168.1.10:8200/Music/100%25%20Hits/Caf%C3%A9.mp3"
+        );
+    }
+
+    #[test]
+    fn test_container_only_listing() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="1" parentID="0" restricted="1" childCount="12">
+                <dc:title>Music</dc:title>
+                <upnp:class>object.container.storageFolder</upnp:class>
+            </container>
+            <container id="2" parentID="0" restricted="1" childCount="3">
+                <dc:title>Pictures</dc:title>
+                <upnp:class>object.container.storageFolder</upnp:class>
+                <res protocolInfo="http-get:*:video/mpeg:*">http://192.168.1.2:8200/folder.mpg</res>
+            </container>
+        </DIDL-Lite>"#;
+
+        let (containers, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        assert!(items.is_empty());
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].id, "1");
+        assert_eq!(containers[0].title, "Music");
+        assert_eq!(containers[0].child_count, Some(12));
+        assert_eq!(containers[1].parent_id, "0");
+        assert_eq!(containers[1].title, "Pictures");
+        assert_eq!(containers[1].child_count, Some(3));
+    }
+
+    #[test]
+    fn test_container_without_child_count() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="1" parentID="0" restricted="1">
+                <dc:title>Music</dc:title>
+            </container>
+            <container id="2" parentID="0" restricted="1" childCount="unknown">
+                <dc:title>Pictures</dc:title>
+            </container>
+        </DIDL-Lite>"#;
+
+        let (containers, _) = deserialize_content_directory(DIDL, "").unwrap();
+        assert_eq!(containers[0].child_count, None);
+        assert_eq!(containers[1].child_count, None);
+        assert_eq!(containers[1].title, "Pictures");
+    }
+
+    #[test]
+    fn test_malformed_browse_result() {
+        // The server escaped the DIDL-Lite once, leaving the `&` of the last title raw.
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="1" parentID="0" restricted="1" childCount="2">
+                <dc:title>Music</dc:title>
+            </container>
+            <item id="2" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.10:8200/2.mp3</res>
+            </item>
+            <item id="3" parentID="0" restricted="1">
+                <dc:title>Tom & Jerry</dc:title>
+                <res protocolInfo="http-get:*:video/mp4:*">http://192.168.1.10:8200/3.mp4</res>
+            </item>
+        </DIDL-Lite>"#;
+        let response = format!(
+            r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:BrowseResponse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1"><Result>{}</Result><NumberReturned>3</NumberReturned><TotalMatches>3</TotalMatches><UpdateID>1</UpdateID></u:BrowseResponse></s:Body></s:Envelope>"#,
+            escape_str_pcdata(DIDL)
+        );
+
+        let result = parse_browse_result(&response, "192.168.1.10").unwrap();
+        assert_eq!(result.containers.len(), 1);
+        assert_eq!(result.containers[0].title, "Music");
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].title, "Song");
+        assert_eq!(result.items[0].url, "http://192.168.1.10:8200/2.mp3");
+        assert_eq!(result.number_returned, 3);
+        assert_eq!(result.warnings.len(), 1);
+
+        let (containers, items) = deserialize_content_directory(DIDL, "").unwrap();
+        assert_eq!((containers.len(), items.len()), (1, 1));
+
+        assert!(matches!(
+            parse_browse_result_with_mode(&response, "192.168.1.10", ParseMode::Strict),
+            Err(UpnpError::XmlParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_res_size_lenient() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <item id="2" parentID="0" restricted="1">
+                <dc:title>Song</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*" size="-1">http://192.168.1.10:8200/2.mp3</res>
+            </item>
+            <item id="3" parentID="0" restricted="1">
+                <dc:title>Other song</dc:title>
+                <res protocolInfo="http-get:*:audio/mpeg:*" size="4096">http://192.168.1.10:8200/3.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+        let response = format!(
+            r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:BrowseResponse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1"><Result>{}</Result><NumberReturned>2</NumberReturned><TotalMatches>2</TotalMatches><UpdateID>1</UpdateID></u:BrowseResponse></s:Body></s:Envelope>"#,
+            escape_str_pcdata(DIDL)
+        );
+
+        let result = parse_browse_result(&response, "192.168.1.10").unwrap();
+        assert_eq!(result.items.len(), 2);
+        assert_eq!(result.items[0].url, "http://192.168.1.10:8200/2.mp3");
+        assert_eq!(result.items[0].resources[0].size, None);
+        assert_eq!(result.items[1].resources[0].size, Some(4096));
+        assert_eq!(
+            result.warnings,
+            vec![r#"Ignored invalid res size "-1""#.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_event_parsers_are_lenient() {
+        // Each response is cut short after the value, as by a dropped connection.
+        assert_eq!(
+            parse_volume("<GetVolumeResponse><CurrentVolume>12</CurrentVolume><Unterminated")
+                .unwrap(),
+            12
+        );
+        assert_eq!(
+            parse_transport_info(
+                "<GetTransportInfoResponse><CurrentTransportState>PLAYING</CurrentTransportState><CurrentSpeed>1<"
+            )
+            .unwrap()
+            .current_transport_state,
+            "PLAYING"
+        );
+        assert_eq!(
+            parse_renderer_events(
+                r#"<Event><InstanceID val="0"><Volume channel="Master" val="7"/>&bogus;"#
+            ),
+            vec![RendererEvent::VolumeChanged(7)]
+        );
+        assert!(matches!(
+            parse_volume("<GetVolumeResponse><Current"),
+            Err(UpnpError::MissingElement { .. })
+        ));
+    }
+
+    #[test]
+    fn test_res_url_with_raw_space() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="64$0" parentID="64" restricted="1">
+                <dc:title>My Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.10:8200/Music/My Song.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.10").unwrap();
+        assert_eq!(items[0].url, "http://192.168.1.10:8200/Music/My%20Song.mp3");
+        assert!(Url::parse(&items[0].url).is_ok());
+    }
+
+    #[test]
+    fn test_ipv6_location() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="64$0" parentID="64" restricted="1">
+                <dc:title>My Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://[fe80::10]:8200/MediaItems/1.mp3</res>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://[FE80:0::1]:8200/MediaItems/1.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let device =
+            parse_description("http://[fe80::1]:8200/rootDesc.xml", XML_PATH_ROOT).unwrap();
+        assert_eq!(device.services[0].scpd_url, "http://[fe80::1]:8200/avt.xml");
+        assert_eq!(
+            device.services[0].control_url,
+            "http://[fe80::1]:8200/control/avt"
+        );
+
+        let host = Url::parse(&device.location)
+            .unwrap()
+            .host_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(host, "[fe80::1]");
+
+        let (_, items) = deserialize_content_directory(DIDL, &host).unwrap();
+        assert_eq!(items[0].url, "http://[FE80:0::1]:8200/MediaItems/1.mp3");
+        let (_, items) = deserialize_content_directory(DIDL, "fe80::1").unwrap();
+        assert_eq!(items[0].url, "http://[FE80:0::1]:8200/MediaItems/1.mp3");
+    }
+
+    #[test]
+    fn test_res_host_is_not_a_prefix_match() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="64$0" parentID="64" restricted="1">
+                <dc:title>My Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.20:8200/MediaItems/1.mp3</res>
+                <res protocolInfo="http-get:*:audio/flac:*" size="2048" duration="0:03:25">http://192.168.1.2:8200/MediaItems/1.flac</res>
+                <res protocolInfo="http-get:*:audio/mp4:*" size="1024" duration="0:03:24">http://192.168.1.20:8200/MediaItems/1.m4a</res>
+                <res protocolInfo="http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN" size="64">http://192.168.1.2:8200/Art/1.jpg</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        let (_, items) = deserialize_content_directory(DIDL, "192.168.1.2").unwrap();
+        let item = &items[0];
+        assert_eq!(item.url, "http://192.168.1.2:8200/MediaItems/1.flac");
+        assert_eq!(item.protocol_info, "http-get:*:audio/flac:*");
+        assert_eq!(item.size, Some(2048));
+        assert_eq!(item.duration.as_deref(), Some("0:03:25"));
+    }
+
+    #[test]
+    fn test_res_on_another_host() {
+        const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <item id="64$0" parentID="64" restricted="1">
+                <dc:title>My Song</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:image/jpeg:*">http://192.168.1.2:8200/Art/1.jpg</res>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://nas.local:8200/MediaItems/1.mp3</res>
+            </item>
+        </DIDL-Lite>"#;
+
+        for host in ["192.168.1.2", "NAS.local", ""] {
+            let (_, items) = deserialize_content_directory(DIDL, host).unwrap();
+            assert_eq!(items[0].url, "http://nas.local:8200/MediaItems/1.mp3");
+        }
     }
 }
@@ -0,0 +1,201 @@
+//! Workarounds for devices that deviate from the specifications, looked up from their
+//! manufacturer and model.
+
+use crate::types::Device;
+
+/// The deviations of a device the high-level wrappers work around.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceQuirks {
+    /// The renderer refuses metadata whose `protocolInfo` lacks the `DLNA.ORG_*`
+    /// parameters, so loads without explicit DLNA features send [`DEFAULT_DLNA_FEATURES`].
+    pub needs_dlna_headers: bool,
+    /// The device rejects SOAP envelopes that aren't XML 1.0, which they are sent as
+    /// instead of XML 1.1.
+    pub strict_soap: bool,
+    /// `SetNextAVTransportURI` is advertised but broken, so gapless loads fail upfront
+    /// rather than leaving the renderer in an unknown state.
+    pub no_next_uri: bool,
+}
+
+impl DeviceQuirks {
+    /// The quirks of either `self` or `other`.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self {
+            needs_dlna_headers: self.needs_dlna_headers || other.needs_dlna_headers,
+            strict_soap: self.strict_soap || other.strict_soap,
+            no_next_uri: self.no_next_uri || other.no_next_uri,
+        }
+    }
+
+    /// `protocol_info` with [`DEFAULT_DLNA_FEATURES`] in place of a wildcard fourth field
+    /// when the device needs DLNA parameters, unchanged otherwise.
+    #[must_use]
+    pub fn protocol_info(self, protocol_info: &str) -> String {
+        match protocol_info.rsplit_once(':') {
+            Some((head, "*")) if self.needs_dlna_headers && head.matches(':').count() == 2 => {
+                format!("{head}:{DEFAULT_DLNA_FEATURES}")
+            }
+            _ => protocol_info.to_string(),
+        }
+    }
+}
+
+/// The DLNA parameters of the `protocolInfo` sent to renderers with
+/// [`DeviceQuirks::needs_dlna_headers`]: streaming with byte seeks, not transcoded.
+pub const DEFAULT_DLNA_FEATURES: &str =
+    "DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+/// A rule giving `quirks` to the models of a manufacturer.
+///
+/// It applies to devices whose manufacturer contains `manufacturer` and whose model name
+/// or number contains `model`, ignoring case. An empty `model` matches every model.
+#[derive(Debug, Clone, Copy)]
+pub struct QuirkRule<'a> {
+    pub manufacturer: &'a str,
+    pub model: &'a str,
+    pub quirks: DeviceQuirks,
+}
+
+impl QuirkRule<'_> {
+    fn matches(&self, device: &Device) -> bool {
+        let contains =
+            |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        contains(&device.manufacturer, self.manufacturer)
+            && (contains(&device.model_name, self.model)
+                || device
+                    .model_number
+                    .as_deref()
+                    .is_some_and(|number| contains(number, self.model)))
+    }
+}
+
+/// The rules [`Device::quirks`] applies.
+pub const KNOWN_QUIRKS: &[QuirkRule<'static>] = &[
+    QuirkRule {
+        manufacturer: "Samsung",
+        model: "",
+        quirks: DeviceQuirks {
+            needs_dlna_headers: true,
+            strict_soap: true,
+            no_next_uri: false,
+        },
+    },
+    // The 2016 to 2018 Tizen TVs (K, M and N series) accept `SetNextAVTransportURI` but
+    // never switch to the next URI.
+    QuirkRule {
+        manufacturer: "Samsung",
+        model: "UE",
+        quirks: DeviceQuirks {
+            needs_dlna_headers: false,
+            strict_soap: false,
+            no_next_uri: true,
+        },
+    },
+    QuirkRule {
+        manufacturer: "LG Electronics",
+        model: "",
+        quirks: DeviceQuirks {
+            needs_dlna_headers: true,
+            strict_soap: false,
+            no_next_uri: false,
+        },
+    },
+];
+
+impl Device {
+    /// The quirks of the device according to [`KNOWN_QUIRKS`].
+    #[must_use]
+    pub fn quirks(&self) -> DeviceQuirks {
+        self.quirks_with(&[])
+    }
+
+    /// The quirks of the device according to [`KNOWN_QUIRKS`] and to `rules`, for devices
+    /// the built-in table doesn't know about.
+    #[must_use]
+    pub fn quirks_with(&self, rules: &[QuirkRule]) -> DeviceQuirks {
+        KNOWN_QUIRKS
+            .iter()
+            .chain(rules)
+            .filter(|rule| rule.matches(self))
+            .fold(DeviceQuirks::default(), |quirks, rule| {
+                quirks.union(rule.quirks)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        quirks::{DeviceQuirks, QuirkRule, DEFAULT_DLNA_FEATURES},
+        types::Device,
+    };
+
+    fn device(manufacturer: &str, model_name: &str) -> Device {
+        Device {
+            manufacturer: manufacturer.to_string(),
+            model_name: model_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_known_samsung_quirks() {
+        assert_eq!(
+            device("Samsung Electronics", "UE55MU6100").quirks(),
+            DeviceQuirks {
+                needs_dlna_headers: true,
+                strict_soap: true,
+                no_next_uri: true,
+            }
+        );
+        assert_eq!(
+            device("Samsung Electronics", "HW-Q90R").quirks(),
+            DeviceQuirks {
+                needs_dlna_headers: true,
+                strict_soap: true,
+                no_next_uri: false,
+            }
+        );
+        assert_eq!(
+            device("Plex, Inc.", "Plex Media Server").quirks(),
+            DeviceQuirks::default()
+        );
+    }
+
+    #[test]
+    fn test_user_quirk_rules() {
+        let rules = [QuirkRule {
+            manufacturer: "acme",
+            model: "Streamer 2",
+            quirks: DeviceQuirks {
+                no_next_uri: true,
+                ..Default::default()
+            },
+        }];
+        assert!(
+            device("ACME", "Streamer 2000")
+                .quirks_with(&rules)
+                .no_next_uri
+        );
+        assert!(!device("ACME", "Streamer 1").quirks_with(&rules).no_next_uri);
+        assert!(!device("ACME", "Streamer 2000").quirks().no_next_uri);
+    }
+
+    #[test]
+    fn test_protocol_info_dlna_features() {
+        let quirks = device("LG Electronics", "OLED55C1").quirks();
+        assert_eq!(
+            quirks.protocol_info("http-get:*:video/mp4:*"),
+            format!("http-get:*:video/mp4:{DEFAULT_DLNA_FEATURES}")
+        );
+        assert_eq!(
+            quirks.protocol_info("http-get:*:audio/mpeg:DLNA.ORG_PN=MP3"),
+            "http-get:*:audio/mpeg:DLNA.ORG_PN=MP3"
+        );
+        assert_eq!(
+            DeviceQuirks::default().protocol_info("http-get:*:video/mp4:*"),
+            "http-get:*:video/mp4:*"
+        );
+    }
+}
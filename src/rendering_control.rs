@@ -0,0 +1,285 @@
+//! Typed helpers for the `RenderingControl` service of a [`Device`].
+
+use anyhow::Result;
+use futures_util::future::try_join;
+
+use crate::{
+    error::UpnpError,
+    parser::{parse_mute, parse_volume},
+    types::{Device, Service},
+};
+
+impl Device {
+    /// The `RenderingControl` service of the device, or an error naming the device when it
+    /// has none.
+    pub fn rendering_control(&self) -> Result<&Service, UpnpError> {
+        self.require_service("RenderingControl")
+    }
+
+    /// The volume of the `Master` channel.
+    pub async fn get_volume(&self) -> Result<u8, UpnpError> {
+        self.channel_volume("Master").await
+    }
+
+    /// The volume of `channel`, `Master` by default, and whether it is muted, requested
+    /// concurrently, e.g. to refresh a volume widget in a single round-trip time.
+    pub async fn get_volume_and_mute(
+        &self,
+        channel: Option<&str>,
+    ) -> Result<(u8, bool), UpnpError> {
+        try_join(
+            self.channel_volume(channel.unwrap_or("Master")),
+            self.get_mute(channel),
+        )
+        .await
+    }
+
+    async fn channel_volume(&self, channel: &str) -> Result<u8, UpnpError> {
+        let response = self
+            .rendering_control()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetVolume",
+                &[("InstanceID", "0"), ("Channel", channel)],
+            )
+            .await?;
+        parse_volume(&response)
+    }
+
+    /// Sets the volume of the `Master` channel and returns the volume actually requested,
+    /// which is clamped to the `allowedValueRange` of the `Volume` state variable when the
+    /// service description declares one.
+    pub async fn set_volume(&self, volume: u16) -> Result<u16, UpnpError> {
+        let service = self.rendering_control()?;
+        let volume = service
+            .argument_state_variable("SetVolume", "DesiredVolume")
+            .and_then(|variable| variable.allowed_range)
+            .map_or(volume, |range| {
+                let clamped = i64::from(volume).clamp(range.minimum, range.maximum);
+                u16::try_from(clamped).unwrap_or(volume)
+            });
+
+        service
+            .invoke_with_quirks(
+                self.quirks(),
+                "SetVolume",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredVolume", &volume.to_string()),
+                ],
+            )
+            .await?;
+        Ok(volume)
+    }
+
+    /// Whether `channel`, `Master` by default, is muted.
+    pub async fn get_mute(&self, channel: Option<&str>) -> Result<bool, UpnpError> {
+        let response = self
+            .rendering_control()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "GetMute",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", channel.unwrap_or("Master")),
+                ],
+            )
+            .await?;
+        parse_mute(&response)
+    }
+
+    /// Mutes or unmutes `channel`, `Master` by default.
+    pub async fn set_mute(&self, channel: Option<&str>, desired: bool) -> Result<(), UpnpError> {
+        self.rendering_control()?
+            .invoke_with_quirks(
+                self.quirks(),
+                "SetMute",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", channel.unwrap_or("Master")),
+                    ("DesiredMute", if desired { "1" } else { "0" }),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_location,
+        test_utils::{mock_device, soap_response, MockResponse, MockService},
+    };
+
+    fn rendering_control() -> MockService {
+        MockService {
+            name: "RenderingControl",
+            actions: vec![
+                (
+                    "GetVolume",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("CurrentVolume", "out"),
+                    ],
+                ),
+                (
+                    "SetVolume",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("DesiredVolume", "in"),
+                    ],
+                ),
+                (
+                    "GetMute",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("CurrentMute", "out"),
+                    ],
+                ),
+                (
+                    "SetMute",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("DesiredMute", "in"),
+                    ],
+                ),
+            ],
+            state_table: r#"<stateVariable sendEvents="no">
+                    <name>Volume</name>
+                    <dataType>ui2</dataType>
+                    <allowedValueRange>
+                        <minimum>0</minimum>
+                        <maximum>50</maximum>
+                        <step>1</step>
+                    </allowedValueRange>
+                </stateVariable>"#,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_volume() {
+        let server = mock_device(vec![rendering_control()], |_| {
+            soap_response(
+                "RenderingControl",
+                "GetVolume",
+                "<CurrentVolume>27</CurrentVolume>",
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(device.get_volume().await.unwrap(), 27);
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("GetVolume"));
+        assert_eq!(request.param("Channel").as_deref(), Some("Master"));
+    }
+
+    #[tokio::test]
+    async fn test_set_volume_clamps_to_allowed_range() {
+        let server = mock_device(vec![rendering_control()], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(device.set_volume(80).await.unwrap(), 50);
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.action(), Some("SetVolume"));
+        assert_eq!(request.param("InstanceID").as_deref(), Some("0"));
+        assert_eq!(request.param("DesiredVolume").as_deref(), Some("50"));
+    }
+
+    #[tokio::test]
+    async fn test_get_mute() {
+        for (value, muted) in [("0", false), ("1", true)] {
+            let server = mock_device(vec![rendering_control()], move |_| {
+                soap_response(
+                    "RenderingControl",
+                    "GetMute",
+                    &format!("<CurrentMute>{value}</CurrentMute>"),
+                )
+            })
+            .await;
+            let device = parse_location(&server.url("/description.xml"))
+                .await
+                .unwrap();
+
+            assert_eq!(device.get_mute(None).await.unwrap(), muted);
+            let request = server.actions().pop().unwrap();
+            assert_eq!(request.action(), Some("GetMute"));
+            assert_eq!(request.param("Channel").as_deref(), Some("Master"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_volume_and_mute() {
+        let server = mock_device(vec![rendering_control()], |req| match req.action() {
+            Some("GetVolume") => soap_response(
+                "RenderingControl",
+                "GetVolume",
+                "<CurrentVolume>33</CurrentVolume>",
+            ),
+            _ => soap_response(
+                "RenderingControl",
+                "GetMute",
+                "<CurrentMute>1</CurrentMute>",
+            ),
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            device.get_volume_and_mute(Some("LF")).await.unwrap(),
+            (33, true)
+        );
+        let mut requests = server.actions();
+        requests.sort_by_key(|req| req.action().map(str::to_string));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].action(), Some("GetMute"));
+        assert_eq!(requests[1].action(), Some("GetVolume"));
+        assert!(requests
+            .iter()
+            .all(|req| req.param("Channel").as_deref() == Some("LF")));
+    }
+
+    #[tokio::test]
+    async fn test_set_mute() {
+        let server = mock_device(vec![rendering_control()], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        device.set_mute(None, true).await.unwrap();
+        device.set_mute(Some("LF"), false).await.unwrap();
+
+        let requests = server.actions();
+        assert_eq!(requests[0].action(), Some("SetMute"));
+        assert_eq!(requests[0].param("InstanceID").as_deref(), Some("0"));
+        assert_eq!(requests[0].param("Channel").as_deref(), Some("Master"));
+        assert_eq!(requests[0].param("DesiredMute").as_deref(), Some("1"));
+        assert_eq!(requests[1].param("Channel").as_deref(), Some("LF"));
+        assert_eq!(requests[1].param("DesiredMute").as_deref(), Some("0"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_rendering_control() {
+        let server = mock_device(vec![], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let error = device.get_volume().await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("has no RenderingControl service"));
+    }
+}
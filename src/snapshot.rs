@@ -0,0 +1,187 @@
+//! Snapshots of the documents describing a device, which rebuild it without any network,
+//! e.g. to turn a real device into a test fixture.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::UpnpError,
+    parser::{decode_body, parse_location_with_client, ParserConfig},
+    transport::{HttpResponse, HttpTransport},
+    types::Device,
+};
+
+/// The description and SCPDs of a device, keyed by the URL they were fetched from.
+///
+/// A snapshot is itself an [`HttpTransport`], serving its documents and answering any
+/// other `GET` with a 404, so it can also be passed to
+/// [`parse_location_with_client`] or [`crate::parser::parse_services_with_client`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceSnapshot {
+    pub location: String,
+    /// The documents as decoded, without any `Content-Encoding`.
+    pub documents: BTreeMap<String, String>,
+}
+
+impl DeviceSnapshot {
+    /// Resolves the device at `location` like [`crate::parser::parse_location`], recording
+    /// every document fetched along the way.
+    pub async fn capture(location: &str) -> Result<(Device, Self), UpnpError> {
+        Self::capture_with_client(location, &ParserConfig::default().client()?).await
+    }
+
+    /// Resolves the device at `location` through `client` like
+    /// [`parse_location_with_client`], recording every document fetched along the way.
+    pub async fn capture_with_client(
+        location: &str,
+        client: &(impl HttpTransport + ?Sized),
+    ) -> Result<(Device, Self), UpnpError> {
+        let recorder = Recorder {
+            client,
+            documents: Mutex::default(),
+        };
+        let device = parse_location_with_client(location, &recorder).await?;
+        let snapshot = Self {
+            location: location.to_string(),
+            documents: recorder
+                .documents
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+        Ok((device, snapshot))
+    }
+}
+
+impl Device {
+    /// Rebuilds the device `snapshot` was captured from, without any network.
+    pub async fn from_snapshot(snapshot: &DeviceSnapshot) -> Result<Self, UpnpError> {
+        parse_location_with_client(&snapshot.location, snapshot).await
+    }
+}
+
+#[async_trait]
+impl HttpTransport for DeviceSnapshot {
+    async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError> {
+        Ok(self.documents.get(url).map_or_else(
+            || HttpResponse {
+                status: 404,
+                ..Default::default()
+            },
+            |document| HttpResponse {
+                status: 200,
+                body: document.clone().into_bytes(),
+                ..Default::default()
+            },
+        ))
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        _headers: &[(&str, &str)],
+        _body: String,
+    ) -> Result<HttpResponse, UpnpError> {
+        Err(UpnpError::Http {
+            url: url.to_string(),
+            status: None,
+            message: "A snapshot cannot invoke actions".to_string(),
+        })
+    }
+}
+
+/// Forwards requests to `client`, keeping the body of every successful `GET`.
+struct Recorder<'a, T: ?Sized> {
+    client: &'a T,
+    documents: Mutex<BTreeMap<String, String>>,
+}
+
+#[async_trait]
+impl<T: HttpTransport + ?Sized> HttpTransport for Recorder<'_, T> {
+    async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError> {
+        let res = self.client.get(url).await?;
+        if res.is_success() {
+            let encoding = res
+                .header("Content-Encoding")
+                .map(|value| value.trim().to_ascii_lowercase());
+            // An undecodable body fails the parse anyway, so there's nothing to keep.
+            if let Ok(document) = decode_body(&res.body, encoding.as_deref()) {
+                self.documents
+                    .lock()
+                    .unwrap()
+                    .insert(url.to_string(), document);
+            }
+        }
+        Ok(res)
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> Result<HttpResponse, UpnpError> {
+        self.client.post(url, headers, body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        snapshot::DeviceSnapshot,
+        test_utils::{mock_device, MockService},
+        types::Device,
+    };
+
+    #[tokio::test]
+    async fn test_capture_and_rebuild_offline() {
+        let server = mock_device(
+            vec![
+                MockService::new("AVTransport", &["Play", "Stop"]),
+                MockService::new("RenderingControl", &["GetVolume"]),
+            ],
+            |_| unreachable!(),
+        )
+        .await;
+        let location = server.url("/description.xml");
+
+        let (captured, snapshot) = DeviceSnapshot::capture(&location).await.unwrap();
+        assert_eq!(snapshot.location, location);
+        assert_eq!(snapshot.documents.len(), 3);
+        assert!(snapshot
+            .documents
+            .contains_key(&server.url("/AVTransport/scpd.xml")));
+        assert_eq!(server.requests().len(), 3);
+
+        let rebuilt = Device::from_snapshot(&snapshot).await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(rebuilt, captured);
+        assert_eq!(rebuilt.friendly_name, captured.friendly_name);
+        assert_eq!(rebuilt.services.len(), 2);
+        assert_eq!(rebuilt.services[0].actions.len(), 2);
+        assert_eq!(rebuilt.services[1].actions[0].name, "GetVolume");
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_snapshot_round_trips_through_json() {
+        let server = mock_device(
+            vec![MockService::new("AVTransport", &["Play"])],
+            |_| unreachable!(),
+        )
+        .await;
+        let (_, snapshot) = DeviceSnapshot::capture(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored: DeviceSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, snapshot);
+    }
+}
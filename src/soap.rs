@@ -0,0 +1,553 @@
+//! SOAP envelopes for invoking service actions.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use surf::{Client, Config};
+use xml::escape::escape_str_pcdata;
+use xml_builder::{XMLBuilder, XMLElement, XMLVersion};
+
+use crate::{
+    error::UpnpError,
+    parser::{parse_query_state_variable, parse_soap_fault},
+    quirks::DeviceQuirks,
+    transport::{traced, HttpTransport},
+    types::Service,
+};
+
+/// The namespace of `QueryStateVariable`, which belongs to the control protocol rather
+/// than to any service type.
+const CONTROL_NAMESPACE: &str = "urn:schemas-upnp-org:control-1-0";
+
+/// Builds the SOAP envelope invoking `action_name` of a service of type `service_type`.
+/// Argument values are escaped, so they can hold arbitrary text such as DIDL-Lite.
+pub fn build_envelope(
+    service_type: &str,
+    action_name: &str,
+    args: &[(&str, &str)],
+) -> Result<String, UpnpError> {
+    build_envelope_with_quirks(DeviceQuirks::default(), service_type, action_name, args)
+}
+
+/// Builds the envelope of [`build_envelope`] in the XML version a device with `quirks`
+/// accepts.
+pub(crate) fn build_envelope_with_quirks(
+    quirks: DeviceQuirks,
+    service_type: &str,
+    action_name: &str,
+    args: &[(&str, &str)],
+) -> Result<String, UpnpError> {
+    let version = if quirks.strict_soap {
+        XMLVersion::XML1_0
+    } else {
+        XMLVersion::XML1_1
+    };
+    let mut xml = XMLBuilder::new()
+        .version(version)
+        .encoding("UTF-8".into())
+        .build();
+
+    let mut envelope = XMLElement::new("s:Envelope");
+    envelope.add_attribute("xmlns:s", "http://schemas.xmlsoap.org/soap/envelope/");
+    envelope.add_attribute(
+        "s:encodingStyle",
+        "http://schemas.xmlsoap.org/soap/encoding/",
+    );
+
+    let mut body = XMLElement::new("s:Body");
+    let action = format!("u:{action_name}");
+    let mut action = XMLElement::new(action.as_str());
+    action.add_attribute("xmlns:u", service_type);
+
+    for (name, value) in args {
+        let mut param = XMLElement::new(name);
+        param
+            .add_text(escape_str_pcdata(value).to_string())
+            .map_err(|e| anyhow!("{e:?}"))?;
+        action.add_child(param).map_err(|e| anyhow!("{e:?}"))?;
+    }
+
+    body.add_child(action).map_err(|e| anyhow!("{e:?}"))?;
+    envelope.add_child(body).map_err(|e| anyhow!("{e:?}"))?;
+
+    xml.set_root_element(envelope);
+
+    let mut writer: Vec<u8> = Vec::new();
+    xml.generate(&mut writer).map_err(|e| anyhow!("{e:?}"))?;
+    Ok(String::from_utf8(writer)?)
+}
+
+/// The value of the `SOAPACTION` header, i.e. `"serviceType#action"` including the quotes.
+#[must_use]
+pub fn soap_action(service_type: &str, action_name: &str) -> String {
+    format!("\"{service_type}#{action_name}\"")
+}
+
+/// Posts `envelope` to `control_url` through `client` and returns the response body,
+/// whatever its status, unless it is a fault, reported as [`UpnpError::SoapFault`].
+pub async fn post(
+    client: &(impl HttpTransport + ?Sized),
+    control_url: &str,
+    service_type: &str,
+    action_name: &str,
+    envelope: String,
+) -> Result<String, UpnpError> {
+    let content_length = envelope.len().to_string();
+    let soap_action = soap_action(service_type, action_name);
+    let headers = [
+        ("Content-Type", "text/xml; charset=\"utf-8\""),
+        ("Content-Length", content_length.as_str()),
+        ("SOAPACTION", soap_action.as_str()),
+        ("Connection", "close"),
+    ];
+    let request = client.post(control_url, &headers, envelope);
+    let res = traced("POST", control_url, request).await?;
+    let body = String::from_utf8(res.body)?;
+    parse_soap_fault(&body).map_or(Ok(body), Err)
+}
+
+fn control_client() -> Result<Client, UpnpError> {
+    Ok(Config::new()
+        .set_timeout(Some(Duration::from_secs(5)))
+        .try_into()?)
+}
+
+impl Service {
+    /// Invokes `action_name` with `args` and returns the raw SOAP response, which makes
+    /// actions the crate doesn't wrap, e.g. vendor-specific ones, reachable.
+    ///
+    /// The action must be advertised by the service description, every argument must be
+    /// one of its input arguments and every input argument must be given. Values are
+    /// checked against the `dataType` of their state variable: booleans such as `true` are
+    /// sent as `1` or `0`, and integers out of the bounds of their type, such as `70000`
+    /// for a `ui2`, fail before anything is sent.
+    pub async fn invoke(
+        &self,
+        action_name: &str,
+        args: &[(&str, &str)],
+    ) -> Result<String, UpnpError> {
+        self.invoke_with_quirks(DeviceQuirks::default(), action_name, args)
+            .await
+    }
+
+    /// Invokes `action_name` like [`Self::invoke`], sending the request through `client`.
+    pub async fn invoke_with_transport(
+        &self,
+        client: &(impl HttpTransport + ?Sized),
+        action_name: &str,
+        args: &[(&str, &str)],
+    ) -> Result<String, UpnpError> {
+        self.invoke_through(client, DeviceQuirks::default(), action_name, args)
+            .await
+    }
+
+    /// Invokes `action_name` like [`Self::invoke`], working around the `quirks` of the
+    /// device the service belongs to.
+    pub(crate) async fn invoke_with_quirks(
+        &self,
+        quirks: DeviceQuirks,
+        action_name: &str,
+        args: &[(&str, &str)],
+    ) -> Result<String, UpnpError> {
+        self.invoke_through(&control_client()?, quirks, action_name, args)
+            .await
+    }
+
+    /// The current value of the state variable `var_name`, read through the
+    /// `QueryStateVariable` action of `UPnP` 1.0, which services answer without advertising
+    /// it. Handy for debugging, or for variables a device leaves out of its `LastChange`
+    /// events.
+    pub async fn query_state_variable(&self, var_name: &str) -> Result<String, UpnpError> {
+        let envelope = build_envelope(
+            CONTROL_NAMESPACE,
+            "QueryStateVariable",
+            &[("u:varName", var_name)],
+        )?;
+        let response = post(
+            &control_client()?,
+            &self.control_url,
+            CONTROL_NAMESPACE,
+            "QueryStateVariable",
+            envelope,
+        )
+        .await?;
+        parse_query_state_variable(&response)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, client, quirks, args),
+            fields(service_id = %self.service_id),
+            err(level = "warn")
+        )
+    )]
+    async fn invoke_through(
+        &self,
+        client: &(impl HttpTransport + ?Sized),
+        quirks: DeviceQuirks,
+        action_name: &str,
+        args: &[(&str, &str)],
+    ) -> Result<String, UpnpError> {
+        let values = self.validate_invocation(action_name, args)?;
+        let args = args
+            .iter()
+            .zip(&values)
+            .map(|((name, _), value)| (*name, value.as_str()))
+            .collect::<Vec<_>>();
+        let envelope = build_envelope_with_quirks(quirks, &self.service_type, action_name, &args)?;
+        post(
+            client,
+            &self.control_url,
+            &self.service_type,
+            action_name,
+            envelope,
+        )
+        .await
+    }
+
+    /// Checks `args` against the declaration of `action_name`, returning their values as
+    /// coerced to the types of their state variables.
+    fn validate_invocation(&self, action_name: &str, args: &[(&str, &str)]) -> Result<Vec<String>> {
+        let action =
+            self.find_action(action_name)
+                .ok_or_else(|| UpnpError::ActionNotSupported {
+                    service_id: self.service_id.clone(),
+                    action: action_name.to_string(),
+                })?;
+        let inputs = action.in_arguments().collect::<Vec<_>>();
+
+        if let Some((name, _)) = args
+            .iter()
+            .find(|(name, _)| !inputs.iter().any(|argument| argument.name == *name))
+        {
+            return Err(anyhow!("Unknown argument {name} for action {action_name}"));
+        }
+        if let Some(argument) = inputs
+            .iter()
+            .find(|argument| !args.iter().any(|(name, _)| argument.name == *name))
+        {
+            return Err(anyhow!(
+                "Missing argument {} for action {action_name}",
+                argument.name
+            ));
+        }
+        args.iter()
+            .map(|(name, value)| {
+                let Some(variable) = self.argument_state_variable(action_name, name) else {
+                    return Ok((*value).to_string());
+                };
+                let data_type = &variable.data_type;
+                coerce_argument(data_type, value).ok_or_else(|| {
+                    anyhow!("Invalid {data_type} {value:?} for argument {name} of {action_name}")
+                })
+            })
+            .collect()
+    }
+}
+
+/// The value sent for `value`, given for an argument of type `data_type`, or `None` when
+/// the device would reject it. Types other than booleans and integers pass as given.
+fn coerce_argument(data_type: &str, value: &str) -> Option<String> {
+    let (minimum, maximum) = match data_type.trim() {
+        "boolean" => {
+            return match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => Some("1".to_string()),
+                "0" | "false" | "no" => Some("0".to_string()),
+                _ => None,
+            }
+        }
+        "ui1" => (0, i128::from(u8::MAX)),
+        "ui2" => (0, i128::from(u16::MAX)),
+        "ui4" => (0, i128::from(u32::MAX)),
+        "ui8" => (0, i128::from(u64::MAX)),
+        "i1" => (i128::from(i8::MIN), i128::from(i8::MAX)),
+        "i2" => (i128::from(i16::MIN), i128::from(i16::MAX)),
+        "i4" | "int" => (i128::from(i32::MIN), i128::from(i32::MAX)),
+        "i8" => (i128::from(i64::MIN), i128::from(i64::MAX)),
+        _ => return Some(value.to_string()),
+    };
+    value
+        .trim()
+        .parse::<i128>()
+        .ok()
+        .filter(|number| (minimum..=maximum).contains(number))
+        .map(|number| number.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_location,
+        test_utils::{mock_device, MockResponse, MockServer, MockService},
+    };
+
+    fn rendering_control() -> MockService {
+        MockService {
+            name: "RenderingControl",
+            actions: vec![(
+                "SetVolume",
+                vec![
+                    ("InstanceID", "in"),
+                    ("Channel", "in"),
+                    ("DesiredVolume", "in"),
+                ],
+            )],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoke_set_volume() {
+        let server = mock_device(vec![rendering_control()], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        device.services[0]
+            .invoke(
+                "SetVolume",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredVolume", "42"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.path, "/RenderingControl/control");
+        assert_eq!(
+            request.headers["soapaction"],
+            "\"urn:schemas-upnp-org:service:RenderingControl:1#SetVolume\""
+        );
+        assert!(request.body.contains(
+            r#"<u:SetVolume xmlns:u="urn:schemas-upnp-org:service:RenderingControl:1">"#
+        ));
+        assert_eq!(request.param("Channel").as_deref(), Some("Master"));
+        assert_eq!(request.param("DesiredVolume").as_deref(), Some("42"));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_validates_against_the_action_list() {
+        let server = mock_device(vec![rendering_control()], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        let service = &device.services[0];
+
+        assert!(service.invoke("GetMute", &[]).await.is_err());
+        assert!(service
+            .invoke("SetVolume", &[("InstanceID", "0"), ("Channel", "Master")])
+            .await
+            .is_err());
+        assert!(service
+            .invoke(
+                "SetVolume",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredVolume", "42"),
+                    ("Balance", "0"),
+                ],
+            )
+            .await
+            .is_err());
+        assert!(server.actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invoke_coerces_argument_types() {
+        let service = MockService {
+            name: "RenderingControl",
+            actions: vec![
+                (
+                    "SetMute",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("DesiredMute", "in"),
+                    ],
+                ),
+                (
+                    "SetVolume",
+                    vec![
+                        ("InstanceID", "in"),
+                        ("Channel", "in"),
+                        ("DesiredVolume", "in"),
+                    ],
+                ),
+            ],
+            state_table: r#"<stateVariable sendEvents="no">
+                    <name>Mute</name>
+                    <dataType>boolean</dataType>
+                </stateVariable>
+                <stateVariable sendEvents="no">
+                    <name>Volume</name>
+                    <dataType>ui2</dataType>
+                </stateVariable>"#,
+        };
+        let server = mock_device(vec![service], |_| MockResponse::ok("")).await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        let service = &device.services[0];
+
+        service
+            .invoke(
+                "SetMute",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredMute", "true"),
+                ],
+            )
+            .await
+            .unwrap();
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.param("DesiredMute").as_deref(), Some("1"));
+        assert_eq!(request.param("Channel").as_deref(), Some("Master"));
+
+        let error = service
+            .invoke(
+                "SetVolume",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredVolume", "70000"),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Invalid ui2 "70000" for argument DesiredVolume of SetVolume"#
+        );
+        assert!(service
+            .invoke(
+                "SetMute",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredMute", "maybe")
+                ],
+            )
+            .await
+            .is_err());
+        assert_eq!(server.actions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invoke_vendor_action() {
+        const DESCRIPTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:sec="http://www.sec.co.kr/dlna">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                <friendlyName>[TV] Living Room</friendlyName>
+                <manufacturer>Samsung Electronics</manufacturer>
+                <modelName>UE55</modelName>
+                <UDN>uuid:0ee3a5c2-3b6e-4c36-a8c1-6c0f7f6e8e4a</UDN>
+                <sec:ProductCap>Resolution:1920X1080</sec:ProductCap>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:samsung.com:service:MainTVAgent2:1</serviceType>
+                        <serviceId>urn:samsung.com:serviceId:MainTVAgent2</serviceId>
+                        <SCPDURL>/MainTVAgent2/scpd.xml</SCPDURL>
+                        <controlURL>/MainTVAgent2/control</controlURL>
+                        <eventSubURL>/MainTVAgent2/event</eventSubURL>
+                    </service>
+                </serviceList>
+            </device>
+        </root>"#;
+        const SCPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0" xmlns:sec="http://www.sec.co.kr/dlna">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <actionList>
+                <action>
+                    <name>X_GetFeatureList</name>
+                    <argumentList>
+                        <argument>
+                            <name>FeatureList</name>
+                            <direction>out</direction>
+                            <relatedStateVariable>A_ARG_TYPE_FeatureList</relatedStateVariable>
+                        </argument>
+                        <sec:X_ArgumentInfo>UTF-8</sec:X_ArgumentInfo>
+                    </argumentList>
+                </action>
+                <sec:X_ActionInfo>MainTVAgent2</sec:X_ActionInfo>
+            </actionList>
+            <serviceStateTable>
+                <stateVariable sendEvents="no">
+                    <name>A_ARG_TYPE_FeatureList</name>
+                    <dataType>string</dataType>
+                </stateVariable>
+            </serviceStateTable>
+        </scpd>"#;
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/description.xml" => MockResponse::ok(DESCRIPTION),
+            "/MainTVAgent2/scpd.xml" => MockResponse::ok(SCPD),
+            _ => MockResponse::ok(""),
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+        let service = &device.services[0];
+        let actions = service
+            .actions
+            .iter()
+            .map(|action| action.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["X_GetFeatureList"]);
+        assert_eq!(service.actions[0].arguments.len(), 1);
+
+        service.invoke("X_GetFeatureList", &[]).await.unwrap();
+
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.path, "/MainTVAgent2/control");
+        assert_eq!(
+            request.headers["soapaction"],
+            "\"urn:samsung.com:service:MainTVAgent2:1#X_GetFeatureList\""
+        );
+        assert!(request
+            .body
+            .contains(r#"<u:X_GetFeatureList xmlns:u="urn:samsung.com:service:MainTVAgent2:1""#));
+    }
+
+    #[tokio::test]
+    async fn test_query_state_variable() {
+        let server = mock_device(vec![rendering_control()], |_| {
+            MockResponse::ok(
+                r#"<?xml version="1.0" encoding="utf-8"?>
+                <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+                    <s:Body>
+                        <u:QueryStateVariableResponse xmlns:u="urn:schemas-upnp-org:control-1-0">
+                            <return>37</return>
+                        </u:QueryStateVariableResponse>
+                    </s:Body>
+                </s:Envelope>"#,
+            )
+        })
+        .await;
+        let device = parse_location(&server.url("/description.xml"))
+            .await
+            .unwrap();
+
+        let value = device.services[0]
+            .query_state_variable("Volume")
+            .await
+            .unwrap();
+
+        assert_eq!(value, "37");
+        let request = server.actions().pop().unwrap();
+        assert_eq!(request.path, "/RenderingControl/control");
+        assert_eq!(
+            request.headers["soapaction"],
+            "\"urn:schemas-upnp-org:control-1-0#QueryStateVariable\""
+        );
+        assert_eq!(request.param("u:varName").as_deref(), Some("Volume"));
+    }
+}
@@ -0,0 +1,301 @@
+//! Helpers for exercising the network code against a local mock device.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use xml::escape::escape_str_pcdata;
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// Name of the SOAP action, taken from the `SOAPACTION` header.
+    pub fn action(&self) -> Option<&str> {
+        self.headers
+            .get("soapaction")
+            .and_then(|value| value.trim_matches('"').split('#').nth(1))
+    }
+
+    /// Text content of the first `<name>` element found in the body.
+    pub fn param(&self, name: &str) -> Option<String> {
+        let start = self.body.find(&format!("<{name}>"))? + name.len() + 2;
+        let end = self.body[start..].find(&format!("</{name}>"))? + start;
+        Some(self.body[start..end].to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+}
+
+type Handler = dyn Fn(&RecordedRequest) -> MockResponse + Send + Sync;
+
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    /// Serves `handler` on the current runtime, hence async although nothing is awaited.
+    #[allow(clippy::unused_async)]
+    pub async fn start(
+        handler: impl Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+    ) -> Self {
+        let handler: Arc<Handler> = Arc::new(handler);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        let service = make_service_fn(move |_| {
+            let handler = handler.clone();
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let handler = handler.clone();
+                    let recorded = recorded.clone();
+                    async move {
+                        let method = req.method().to_string();
+                        let path = req.uri().path().to_string();
+                        let headers = req
+                            .headers()
+                            .iter()
+                            .map(|(name, value)| {
+                                (
+                                    name.as_str().to_ascii_lowercase(),
+                                    value.to_str().unwrap_or_default().to_string(),
+                                )
+                            })
+                            .collect();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let request = RecordedRequest {
+                            method,
+                            path,
+                            headers,
+                            body: String::from_utf8_lossy(&body).to_string(),
+                        };
+                        let response = handler(&request);
+                        recorded.lock().unwrap().push(request);
+
+                        let mut builder = Response::builder().status(response.status);
+                        for (name, value) in response.headers {
+                            builder = builder.header(name, value);
+                        }
+                        Ok::<_, Infallible>(builder.body(Body::from(response.body)).unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self { addr, requests }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Requests made to a control URL, i.e. everything that carried a SOAP action.
+    pub fn actions(&self) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.action().is_some())
+            .collect()
+    }
+}
+
+/// A service exposed by [`mock_device`], with the actions its SCPD advertises as
+/// `(name, [(argument, direction)])` and the raw content of its `serviceStateTable`.
+#[derive(Default)]
+pub struct MockService {
+    pub name: &'static str,
+    pub actions: Vec<(&'static str, Vec<(&'static str, &'static str)>)>,
+    pub state_table: &'static str,
+}
+
+impl MockService {
+    pub fn new(name: &'static str, actions: &[&'static str]) -> Self {
+        Self {
+            name,
+            actions: actions.iter().map(|action| (*action, vec![])).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn service_type(&self) -> String {
+        service_type(self.name)
+    }
+}
+
+pub fn service_type(name: &str) -> String {
+    format!("urn:schemas-upnp-org:service:{name}:1")
+}
+
+pub fn device_description(services: &[MockService]) -> String {
+    let services = services
+        .iter()
+        .map(|service| {
+            format!(
+                "<service>\
+                    <serviceType>{}</serviceType>\
+                    <serviceId>urn:upnp-org:serviceId:{name}</serviceId>\
+                    <SCPDURL>/{name}/scpd.xml</SCPDURL>\
+                    <controlURL>/{name}/control</controlURL>\
+                    <eventSubURL>/{name}/event</eventSubURL>\
+                </service>",
+                service.service_type(),
+                name = service.name,
+            )
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                <friendlyName>Mock Device</friendlyName>
+                <manufacturer>upnp-client</manufacturer>
+                <modelName>Mock</modelName>
+                <UDN>uuid:00000000-0000-0000-0000-000000000001</UDN>
+                <serviceList>{services}</serviceList>
+            </device>
+        </root>"#
+    )
+}
+
+pub fn scpd(service: &MockService) -> String {
+    let actions = service
+        .actions
+        .iter()
+        .map(|(name, arguments)| {
+            let arguments = arguments
+                .iter()
+                .map(|(argument, direction)| {
+                    // `DesiredVolume`/`CurrentVolume` relate to `Volume`, as in the specs.
+                    let variable = argument
+                        .strip_prefix("Desired")
+                        .or_else(|| argument.strip_prefix("Current"))
+                        .map_or_else(|| format!("A_ARG_TYPE_{argument}"), ToString::to_string);
+                    format!(
+                        "<argument>\
+                            <name>{argument}</name>\
+                            <direction>{direction}</direction>\
+                            <relatedStateVariable>{variable}</relatedStateVariable>\
+                        </argument>"
+                    )
+                })
+                .collect::<Vec<_>>()
+                .concat();
+            format!("<action><name>{name}</name><argumentList>{arguments}</argumentList></action>")
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <actionList>{actions}</actionList>
+            <serviceStateTable>{}</serviceStateTable>
+        </scpd>"#,
+        service.state_table
+    )
+}
+
+/// Starts a mock device serving its description at `/description.xml` and the SCPD of
+/// each service, forwarding every other request to `handler`.
+pub async fn mock_device(
+    services: Vec<MockService>,
+    handler: impl Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+) -> MockServer {
+    let description = device_description(&services);
+    let scpds: HashMap<String, String> = services
+        .iter()
+        .map(|service| (format!("/{}/scpd.xml", service.name), scpd(service)))
+        .collect();
+
+    MockServer::start(move |req| {
+        if req.path == "/description.xml" {
+            return MockResponse::ok(description.clone());
+        }
+        if let Some(scpd) = scpds.get(&req.path) {
+            return MockResponse::ok(scpd.clone());
+        }
+        handler(req)
+    })
+    .await
+}
+
+/// Wraps `body` in the SOAP envelope a device answers `action` with.
+pub fn soap_response(service: &str, action: &str, body: &str) -> MockResponse {
+    MockResponse::ok(format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+        <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+            <s:Body>
+                <u:{action}Response xmlns:u="{}">{body}</u:{action}Response>
+            </s:Body>
+        </s:Envelope>"#,
+        service_type(service)
+    ))
+}
+
+/// A SOAP fault carrying the `UPnPError` `code`, answered with a 500 as devices do.
+pub fn soap_fault(code: u32, description: &str) -> MockResponse {
+    MockResponse {
+        status: 500,
+        ..MockResponse::ok(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+                <s:Body>
+                    <s:Fault>
+                        <faultcode>s:Client</faultcode>
+                        <faultstring>UPnPError</faultstring>
+                        <detail>
+                            <UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+                                <errorCode>{code}</errorCode>
+                                <errorDescription>{description}</errorDescription>
+                            </UPnPError>
+                        </detail>
+                    </s:Fault>
+                </s:Body>
+            </s:Envelope>"#
+        ))
+    }
+}
+
+/// Escapes a DIDL-Lite document so it can be embedded in a SOAP `Result` element.
+pub fn escape_didl(didl: &str) -> String {
+    escape_str_pcdata(didl).to_string()
+}
@@ -0,0 +1,318 @@
+//! The HTTP client behind description fetching and action invocation.
+//!
+//! Everything goes through [`HttpTransport`], which [`surf::Client`] implements by default.
+//! Implementing it for another client, e.g. one built on `reqwest` or `hyper`, lets the
+//! crate share its connection pool, proxy settings or TLS configuration.
+
+use std::future::Future;
+
+use async_trait::async_trait;
+use futures_util::AsyncReadExt;
+use surf::{http::Method, Client};
+
+use crate::error::UpnpError;
+
+/// The largest response body a [`surf::Client`] reads, unless wrapped in a
+/// [`LimitedClient`]. Descriptions, SCPDs and `Browse` results of real devices weigh far
+/// less, even for large libraries.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// A response to a request made through an [`HttpTransport`], whatever its status.
+#[derive(Debug, Clone, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// The value of the header `name`, ignoring its case.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[must_use]
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+/// Sends the HTTP requests of the crate.
+///
+/// Implementations return responses whatever their status, and fail only when no
+/// response is received, preferably with [`UpnpError::Http`] or [`UpnpError::Timeout`].
+/// They are written with the [`async_trait`](https://docs.rs/async-trait) attribute.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError>;
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> Result<HttpResponse, UpnpError>;
+}
+
+#[async_trait]
+impl HttpTransport for Client {
+    async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError> {
+        send(self, url, get_request(url)?, DEFAULT_MAX_BODY_SIZE).await
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> Result<HttpResponse, UpnpError> {
+        let req = post_request(url, headers, body)?;
+        send(self, url, req, DEFAULT_MAX_BODY_SIZE).await
+    }
+}
+
+/// A [`surf::Client`] whose requests fail with [`UpnpError::BodyTooLarge`] rather than
+/// read a response body beyond `max_body_size` bytes, which protects long-running
+/// processes from misbehaving devices.
+#[derive(Debug, Clone)]
+pub struct LimitedClient {
+    pub client: Client,
+    pub max_body_size: usize,
+}
+
+#[async_trait]
+impl HttpTransport for LimitedClient {
+    async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError> {
+        send(&self.client, url, get_request(url)?, self.max_body_size).await
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> Result<HttpResponse, UpnpError> {
+        let req = post_request(url, headers, body)?;
+        send(&self.client, url, req, self.max_body_size).await
+    }
+}
+
+fn get_request(url: &str) -> Result<surf::Request, UpnpError> {
+    Ok(surf::Request::new(Method::Get, url.parse()?))
+}
+
+fn post_request(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: String,
+) -> Result<surf::Request, UpnpError> {
+    let mut req = surf::Request::new(Method::Post, url.parse()?);
+    for (name, value) in headers {
+        req.insert_header(*name, *value);
+    }
+    req.set_body(body);
+    Ok(req)
+}
+
+/// Awaits `request`, a `method` request to `url`, reporting its status and duration at
+/// debug level, or its failure at warn level, when the `tracing` feature is enabled.
+pub(crate) async fn traced(
+    method: &str,
+    url: &str,
+    request: impl Future<Output = Result<HttpResponse, UpnpError>>,
+) -> Result<HttpResponse, UpnpError> {
+    #[cfg(feature = "tracing")]
+    {
+        let started = std::time::Instant::now();
+        let result = request.await;
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(res) => tracing::debug!(method, url, status = res.status, ?elapsed, "HTTP request"),
+            Err(e) => tracing::warn!(method, url, error = %e, ?elapsed, "HTTP request failed"),
+        }
+        result
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (method, url);
+        request.await
+    }
+}
+
+async fn send(
+    client: &Client,
+    url: &str,
+    req: surf::Request,
+    max_body_size: usize,
+) -> Result<HttpResponse, UpnpError> {
+    let too_large = || UpnpError::BodyTooLarge {
+        url: url.to_string(),
+        limit: max_body_size,
+    };
+    let mut res = client.send(req).await.map_err(|e| {
+        // The timeout of the client's `Config` fails the request with the error of its timer.
+        if e.downcast_ref::<async_std::future::TimeoutError>()
+            .is_some()
+        {
+            UpnpError::Timeout {
+                url: url.to_string(),
+            }
+        } else {
+            UpnpError::request_failed(url, &e)
+        }
+    })?;
+    if res.len().is_some_and(|len| len > max_body_size) {
+        return Err(too_large());
+    }
+    // Bodies without a `Content-Length` are read one byte past the limit, which tells
+    // a body exceeding it from one filling it exactly.
+    let mut body = Vec::new();
+    res.take_body()
+        .take(
+            u64::try_from(max_body_size)
+                .unwrap_or(u64::MAX)
+                .saturating_add(1),
+        )
+        .read_to_end(&mut body)
+        .await
+        .map_err(|e| UpnpError::request_failed(url, &e))?;
+    if body.len() > max_body_size {
+        return Err(too_large());
+    }
+    let headers = res
+        .iter()
+        .flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(move |value| (name.to_string(), value.to_string()))
+        })
+        .collect();
+    Ok(HttpResponse {
+        status: res.status().into(),
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use async_trait::async_trait;
+
+    use crate::{
+        error::UpnpError,
+        parser::parse_location_with_client,
+        test_utils::{device_description, scpd, MockService},
+        transport::{HttpResponse, HttpTransport},
+    };
+
+    /// Answers from canned bodies, without any I/O, and records the bodies posted.
+    #[derive(Default)]
+    struct CannedTransport {
+        bodies: HashMap<String, String>,
+        posted: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait]
+    impl HttpTransport for CannedTransport {
+        async fn get(&self, url: &str) -> Result<HttpResponse, UpnpError> {
+            Ok(self.bodies.get(url).map_or_else(
+                || HttpResponse {
+                    status: 404,
+                    ..Default::default()
+                },
+                |body| HttpResponse {
+                    status: 200,
+                    body: body.clone().into_bytes(),
+                    ..Default::default()
+                },
+            ))
+        }
+
+        async fn post(
+            &self,
+            url: &str,
+            headers: &[(&str, &str)],
+            body: String,
+        ) -> Result<HttpResponse, UpnpError> {
+            let action = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("SOAPACTION"))
+                .map(|(_, value)| (*value).to_string())
+                .unwrap_or_default();
+            self.posted.lock().unwrap().push((action, body));
+            self.get(url).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_canned_transport() {
+        let service = MockService {
+            name: "RenderingControl",
+            actions: vec![("GetVolume", vec![("InstanceID", "in"), ("Channel", "in")])],
+            ..Default::default()
+        };
+        let transport = CannedTransport {
+            bodies: HashMap::from([
+                (
+                    "http://device/description.xml".to_string(),
+                    device_description(std::slice::from_ref(&service)),
+                ),
+                (
+                    "http://device/RenderingControl/scpd.xml".to_string(),
+                    scpd(&service),
+                ),
+                (
+                    "http://device/RenderingControl/control".to_string(),
+                    "<CurrentVolume>42</CurrentVolume>".to_string(),
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        let device = parse_location_with_client("http://device/description.xml", &transport)
+            .await
+            .unwrap();
+        let service = &device.services[0];
+        assert_eq!(service.actions[0].name, "GetVolume");
+
+        let response = service
+            .invoke_with_transport(
+                &transport,
+                "GetVolume",
+                &[("InstanceID", "0"), ("Channel", "Master")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, "<CurrentVolume>42</CurrentVolume>");
+
+        let (soap_action, body) = transport.posted.lock().unwrap()[0].clone();
+        assert_eq!(
+            soap_action,
+            "\"urn:schemas-upnp-org:service:RenderingControl:1#GetVolume\""
+        );
+        assert!(body.contains("<Channel>Master</Channel>"));
+    }
+
+    #[tokio::test]
+    async fn test_canned_transport_status() {
+        let error = parse_location_with_client(
+            "http://device/description.xml",
+            &CannedTransport::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            UpnpError::Http {
+                status: Some(404),
+                ..
+            }
+        ));
+    }
+}
@@ -1,9 +1,23 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::anyhow;
 
 use owo_colors::OwoColorize;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::UpnpError;
+use crate::parser::{parse_date, parse_time};
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Device {
     pub location: String,
     pub device_type: String,
@@ -13,11 +27,234 @@ pub struct Device {
     pub model_description: Option<String>,
     pub model_name: String,
     pub model_number: Option<String>,
+    pub model_url: Option<String>,
+    pub serial_number: Option<String>,
+    /// The device's web interface, as an absolute URL.
+    pub presentation_url: Option<String>,
     pub services: Vec<Service>,
     pub udn: String,
+    /// The icons of the `iconList`, in the order the description lists them.
+    pub icons: Vec<Icon>,
+    pub spec_version: Option<SpecVersion>,
+    pub discovered_via: Option<DiscoveryOrigin>,
+    /// Devices listed in the `deviceList` of this one.
+    pub devices: Vec<Self>,
+    /// Inconsistencies found while parsing the description, which did not prevent it from
+    /// being parsed.
+    pub warnings: Vec<String>,
+}
+
+impl Device {
+    /// The UDN of the device without its `uuid:` prefix, which identifies it whatever the
+    /// address or interface it answered from.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        let udn = self.udn.trim();
+        udn.strip_prefix("uuid:").unwrap_or(udn)
+    }
+
+    /// What tells the device apart from others: its [`Self::id`], or its location when it
+    /// has no UDN.
+    pub(crate) fn identity(&self) -> &str {
+        match self.id() {
+            "" => &self.location,
+            id => id,
+        }
+    }
+
+    /// The host of the device's location, such as `192.168.1.10` or `[fe80::1]`, which
+    /// its resources are normally served from.
+    #[must_use]
+    pub fn host(&self) -> Option<String> {
+        Url::parse(&self.location)
+            .ok()?
+            .host_str()
+            .map(str::to_string)
+    }
+
+    /// The `scheme://host:port` the device's location is served from, the port being left
+    /// out when it is the default one of the scheme.
+    #[must_use]
+    pub fn origin(&self) -> Option<String> {
+        let url = Url::parse(&self.location).ok()?;
+        url.has_host().then(|| url.origin().ascii_serialization())
+    }
+
+    /// The service at `index` in the order the device's `serviceList` declares them.
+    #[must_use]
+    pub fn service(&self, index: usize) -> Option<&Service> {
+        self.services.get(index)
+    }
+
+    /// Whether this device or one of its embedded devices has a service of type `name`,
+    /// as matched by [`Service::is_type`].
+    #[must_use]
+    pub fn has_service_type(&self, name: &str) -> bool {
+        self.find_service(name).is_some()
+    }
+
+    /// Whether this device or one of its embedded devices is of type `device_type`, either
+    /// a full URN such as `urn:schemas-upnp-org:device:MediaRenderer:1` or a bare name such
+    /// as `MediaRenderer`. Versions and case are ignored, like [`Self::find_service`] does.
+    #[must_use]
+    pub fn has_device_type(&self, device_type: &str) -> bool {
+        let own_type = strip_version(&self.device_type);
+        let matches = if device_type.contains(':') {
+            own_type.eq_ignore_ascii_case(strip_version(device_type))
+        } else {
+            own_type
+                .rsplit(':')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(device_type))
+        };
+        matches
+            || self
+                .devices
+                .iter()
+                .any(|device| device.has_device_type(device_type))
+    }
+
+    /// The first service of type `urn:...:service:{name}:*` of this device or, failing that,
+    /// of one of its embedded devices.
+    #[cfg(feature = "client")]
+    pub(crate) fn require_service(&self, name: &str) -> Result<&Service, UpnpError> {
+        self.find_service(name)
+            .ok_or_else(|| anyhow!("Device {:?} has no {name} service", self.friendly_name).into())
+    }
+
+    /// The first service of type `service_type` of this device or, failing that, of one of
+    /// its embedded devices.
+    ///
+    /// `service_type` is either a full URN, such as
+    /// `urn:schemas-upnp-org:service:AVTransport:1`, or a bare name as accepted by
+    /// [`Service::is_type`]. Versions are ignored either way, so a `:1` type finds a
+    /// service advertised as `:2`, and the comparison ignores case.
+    #[must_use]
+    pub fn find_service(&self, service_type: &str) -> Option<&Service> {
+        let matches = |service: &&Service| {
+            if service_type.contains(':') {
+                strip_version(&service.service_type)
+                    .eq_ignore_ascii_case(strip_version(service_type))
+            } else {
+                service.is_type(service_type)
+            }
+        };
+        self.services.iter().find(matches).or_else(|| {
+            self.devices
+                .iter()
+                .find_map(|d| d.find_service(service_type))
+        })
+    }
+}
+
+/// Devices are equal when their [`Device::id`]s are, so the answers of a device on several
+/// interfaces or to repeated searches collapse into one entry of a `HashSet`. Devices
+/// without a UDN are compared by their location instead.
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+/// `urn` without its trailing `:version`, if it has one.
+pub(crate) fn strip_version(urn: &str) -> &str {
+    let urn = urn.trim();
+    match urn.rsplit_once(':') {
+        Some((rest, version)) if version.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => urn,
+    }
+}
+
+impl Service {
+    /// Whether the service is of type `name`, such as `ContentDirectory`, whatever the
+    /// domain and version of its URN. The comparison ignores case.
+    #[must_use]
+    pub fn is_type(&self, name: &str) -> bool {
+        strip_version(&self.service_type)
+            .rsplit(':')
+            .next()
+            .is_some_and(|service_name| service_name.eq_ignore_ascii_case(name))
+    }
+
+    /// The service type without its version, e.g.
+    /// `urn:schemas-upnp-org:service:AVTransport` for version 1 or 2 alike.
+    #[must_use]
+    pub fn service_type_base(&self) -> &str {
+        strip_version(&self.service_type)
+    }
+
+    /// The version the service type ends with, `None` for the vendor types that have
+    /// none.
+    #[must_use]
+    pub fn service_version(&self) -> Option<u32> {
+        let (_, version) = self.service_type.trim().rsplit_once(':')?;
+        version.parse().ok()
+    }
+
+    /// The action named `name`, as advertised by the service description.
+    #[must_use]
+    pub fn find_action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|action| action.name == name)
+    }
+
+    /// The state variable `argument` of `action` relates to.
+    #[cfg(feature = "client")]
+    pub(crate) fn argument_state_variable(
+        &self,
+        action: &str,
+        argument: &str,
+    ) -> Option<&StateVariable> {
+        let argument = self
+            .find_action(action)?
+            .arguments
+            .iter()
+            .find(|a| a.name == argument)?;
+        self.state_variables
+            .iter()
+            .find(|variable| variable.name == argument.related_state_variable)
+    }
+}
+
+/// Where a device was discovered from: the local interface address it is reachable
+/// through, if the OS could tell, and the address its SSDP answer came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DiscoveryOrigin {
+    pub local_if: Option<IpAddr>,
+    pub remote_addr: SocketAddr,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SpecVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// An icon a device advertises, to be shown next to its name. Devices commonly offer
+/// several sizes and formats to pick from.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Icon {
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Color depth in bits per pixel.
+    pub depth: u32,
+    /// Absolute URL of the image.
+    pub url: String,
 }
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Service {
     pub service_type: String,
     pub service_id: String,
@@ -25,74 +262,259 @@ pub struct Service {
     pub event_sub_url: String,
     pub scpd_url: String,
     pub actions: Vec<Action>,
+    pub state_variables: Vec<StateVariable>,
+    /// The SCPD the service was described by, kept only when enabled through
+    /// `ParserConfig::retain_raw_scpd`.
+    pub raw_scpd: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Action {
     pub name: String,
     pub arguments: Vec<Argument>,
 }
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+impl Action {
+    /// The arguments a caller passes, in the order of the service description.
+    pub fn in_arguments(&self) -> impl Iterator<Item = &Argument> {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.is_direction("in"))
+    }
+
+    /// The arguments the response carries, in the order of the service description.
+    pub fn out_arguments(&self) -> impl Iterator<Item = &Argument> {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.is_direction("out"))
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Argument {
     pub name: String,
     pub direction: String,
     pub related_state_variable: String,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+impl Argument {
+    fn is_direction(&self, direction: &str) -> bool {
+        self.direction.trim().eq_ignore_ascii_case(direction)
+    }
+}
+
+/// A variable of the `serviceStateTable` of a service description.
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateVariable {
+    pub name: String,
+    pub data_type: String,
+    pub default_value: Option<String>,
+    /// The `allowedValueList`, empty when the variable accepts any value of its type.
+    pub allowed_values: Vec<String>,
+    pub allowed_range: Option<AllowedValueRange>,
+    pub send_events: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AllowedValueRange {
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: Option<i64>,
+}
+
+/// The `upnp:class` of a DIDL-Lite object, such as `object.item.audioItem.musicTrack`.
+///
+/// Classes map to the most specific variant they derive from, so
+/// `object.item.videoItem.movie` is a [`Self::VideoItem`], and classes outside of these are
+/// kept as [`Self::Unknown`]. Every variant holds the class string it was made from, which
+/// [`Self::value`] returns unchanged.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ObjectClass {
-    Audio,
-    Video,
-    Image,
-    Container,
+    MusicTrack(String),
+    AudioItem(String),
+    VideoItem(String),
+    Photo(String),
+    StorageFolder(String),
+    Album(String),
+    Artist(String),
+    Unknown(String),
 }
 
 impl From<&str> for ObjectClass {
     fn from(value: &str) -> Self {
-        match value {
-            "object.item.audioItem.musicTrack" => Self::Audio,
-            "object.item.videoItem.movie" => Self::Video,
-            "object.item.imageItem.photo" => Self::Image,
-            // "object.container" => Self::Container,
-            _ => Self::Container,
-        }
+        let value = value.trim();
+        let derives_from = |class: &str| {
+            value
+                .strip_prefix(class)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        };
+        let variant = if derives_from("object.item.audioItem.musicTrack") {
+            Self::MusicTrack
+        } else if derives_from("object.item.audioItem") {
+            Self::AudioItem
+        } else if derives_from("object.item.videoItem") {
+            Self::VideoItem
+        } else if derives_from("object.item.imageItem") {
+            Self::Photo
+        } else if derives_from("object.container.storageFolder") {
+            Self::StorageFolder
+        } else if derives_from("object.container.album") {
+            Self::Album
+        } else if derives_from("object.container.person.musicArtist") {
+            Self::Artist
+        } else {
+            Self::Unknown
+        };
+        variant(value.to_string())
     }
 }
 
 impl ObjectClass {
+    /// The class string the object was made from, e.g. `object.item.videoItem.movie` for a
+    /// [`Self::VideoItem`].
     #[must_use]
-    pub const fn value(&self) -> &'static str {
+    pub fn value(&self) -> &str {
         match self {
-            Self::Audio => "object.item.audioItem.musicTrack",
-            Self::Video => "object.item.videoItem.movie",
-            Self::Image => "object.item.imageItem.photo",
-            Self::Container => "object.container",
+            Self::MusicTrack(class)
+            | Self::AudioItem(class)
+            | Self::VideoItem(class)
+            | Self::Photo(class)
+            | Self::StorageFolder(class)
+            | Self::Album(class)
+            | Self::Artist(class)
+            | Self::Unknown(class) => class,
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Metadata {
     pub url: String,
     pub title: String,
+    /// The `dc:creator`, e.g. the author of a podcast episode or audiobook.
+    pub creator: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
     pub album_art_uri: Option<String>,
     pub genre: Option<String>,
     pub protocol_info: String,
+    /// Sent as the `res@duration`, which some renderers need to show the track length.
+    pub duration: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LoadOptions {
     pub dlna_features: Option<String>,
     pub content_type: Option<String>,
     pub object_class: Option<ObjectClass>,
     pub metadata: Option<Metadata>,
     pub autoplay: bool,
+    /// Check the content type against the renderer's `Sink` protocols before loading,
+    /// failing with [`UpnpError::IncompatibleFormat`](crate::error::UpnpError) rather than
+    /// letting the renderer fault.
+    pub validate_protocol: bool,
+}
+
+/// One `protocol:network:contentFormat:additionalInfo` entry of a `protocolInfo` list,
+/// e.g. `http-get:*:audio/mpeg:*`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProtocolInfoEntry {
+    pub protocol: String,
+    pub network: String,
+    pub content_format: String,
+    pub additional_info: String,
+}
+
+impl FromStr for ProtocolInfoEntry {
+    type Err = UpnpError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut fields = value.trim().splitn(4, ':');
+        let mut next = || {
+            fields
+                .next()
+                .map(ToString::to_string)
+                .ok_or_else(|| UpnpError::Other(anyhow!("Invalid protocolInfo: {value}")))
+        };
+        Ok(Self {
+            protocol: next()?,
+            network: next()?,
+            content_format: next()?,
+            additional_info: next()?,
+        })
+    }
+}
+
+impl Display for ProtocolInfoEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.protocol, self.network, self.content_format, self.additional_info
+        )
+    }
+}
+
+impl ProtocolInfoEntry {
+    /// Whether `candidate` is covered by this entry, taken from a `Sink` list. A `*`
+    /// field accepts anything, content formats are compared on their MIME type alone
+    /// (ignoring case and parameters such as `;rate=44100`), and the additional info is
+    /// only compared when both sides specify it.
+    #[must_use]
+    pub fn accepts(&self, candidate: &Self) -> bool {
+        let mime = |format: &str| {
+            format
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        };
+        let field_matches =
+            |sink: &str, candidate: &str| sink == "*" || candidate == "*" || sink == candidate;
+
+        field_matches(&self.protocol, &candidate.protocol)
+            && field_matches(&self.network, &candidate.network)
+            && (self.content_format == "*"
+                || mime(&self.content_format) == mime(&candidate.content_format))
+            && field_matches(&self.additional_info, &candidate.additional_info)
+    }
+}
+
+/// The `Source` and `Sink` lists returned by `ConnectionManager::GetProtocolInfo`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProtocolInfo {
+    pub source: Vec<ProtocolInfoEntry>,
+    pub sink: Vec<ProtocolInfoEntry>,
+}
+
+/// A connection of a device, as reported by `ConnectionManager::GetCurrentConnectionInfo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectionInfo {
+    /// The `RenderingControl` instance of the connection, `-1` when it has none.
+    pub rcs_id: i32,
+    /// The `AVTransport` instance of the connection, `-1` when it has none.
+    pub av_transport_id: i32,
+    /// `None` when the device leaves it empty or malformed.
+    pub protocol_info: Option<ProtocolInfoEntry>,
+    /// `Input` or `Output`.
+    pub direction: String,
+    /// Such as `OK`, `ContentFormatMismatch` or `Unknown`.
+    pub status: String,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum AVTransportEvent {
     AVTransportURIMetaData {
         sid: String,
@@ -166,7 +588,82 @@ impl Display for AVTransportEvent {
     }
 }
 
+/// An accepted GENA event subscription, to be renewed before `timeout` elapses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Subscription {
+    /// The subscription id (`SID`) the device assigned.
+    pub sid: String,
+    /// `None` when the device grants an `infinite` subscription.
+    pub timeout: Option<Duration>,
+}
+
+/// A GENA `NOTIFY` request received for a subscription.
+///
+/// The event key lets control points notice lost or reordered events: the initial event,
+/// numbered 0, carries the complete state of the service, and each later one increments
+/// the key, wrapping from `u32::MAX` back to 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NotifyMessage {
+    pub sid: String,
+    /// The event key (`SEQ`).
+    pub seq: u32,
+    pub event: LastChangeEvent,
+}
+
+impl NotifyMessage {
+    /// Whether this is the initial event of the subscription, a snapshot of every evented
+    /// variable rather than a change.
+    #[must_use]
+    pub const fn is_initial(&self) -> bool {
+        self.seq == 0
+    }
+
+    /// Whether this message immediately follows the one numbered `previous`, i.e. no
+    /// event was lost or reordered in between.
+    #[must_use]
+    pub const fn follows(&self, previous: u32) -> bool {
+        let expected = match previous {
+            u32::MAX => 1,
+            previous => previous + 1,
+        };
+        self.seq == expected
+    }
+}
+
+/// The state variables reported by a GENA `NOTIFY` request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LastChangeEvent {
+    pub changes: Vec<StateChange>,
+}
+
+/// A state variable reported by an event, either as a property of its own or inside a
+/// `LastChange` property, which also carries an instance and possibly a channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateChange {
+    /// The `InstanceID` the variable belongs to, 0 outside of `LastChange`.
+    pub instance_id: u32,
+    pub name: String,
+    /// The `channel` attribute of channel-specific variables such as `Volume`.
+    pub channel: Option<String>,
+    pub value: String,
+}
+
+/// A change of a renderer's state, whichever service reported it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RendererEvent {
+    TransportStateChanged(String),
+    PositionChanged(Duration),
+    VolumeChanged(u8),
+    TrackChanged(Metadata),
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Event {
     AVTransport(AVTransportEvent),
 }
@@ -182,6 +679,7 @@ impl Display for Event {
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Container {
     pub id: String,
     pub parent_id: String,
@@ -189,6 +687,8 @@ pub struct Container {
     pub creator: Option<String>,
     pub restricted: bool,
     pub searchable: bool,
+    /// The number of children the server reports through `childCount`, e.g. to show next
+    /// to a folder before browsing it.
     pub child_count: Option<u32>,
     pub album_art_uri: Option<String>,
     pub album: Option<String>,
@@ -202,6 +702,7 @@ pub struct Container {
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Item {
     pub id: String,
     pub parent_id: String,
@@ -220,11 +721,758 @@ pub struct Item {
     pub size: Option<u64>,
     pub duration: Option<String>,
     pub object_class: Option<ObjectClass>,
+    /// Every `res` of the item, in document order.
+    pub resources: Vec<Resource>,
+    /// The `upnp:icon`.
+    pub icon: Option<String>,
+}
+
+impl Container {
+    /// The `dc:date` of the container as `YYYY-MM-DD`, e.g. to sort albums by release
+    /// date. See [`parse_date`] for the formats understood.
+    #[must_use]
+    pub fn date_parsed(&self) -> Option<String> {
+        self.date.as_deref().and_then(parse_date)
+    }
+}
+
+/// A `res` element of an item: one way of fetching its content.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Resource {
+    pub url: String,
+    pub protocol_info: String,
+    pub size: Option<u64>,
+    pub duration: Option<String>,
+    /// Width of the video or image, in pixels, from the `resolution` attribute.
+    pub width: Option<u32>,
+    /// Height of the video or image, in pixels, from the `resolution` attribute.
+    pub height: Option<u32>,
+    /// In bytes per second, as DIDL-Lite has it, not bits.
+    pub bitrate: Option<u32>,
+    /// In Hz.
+    pub sample_frequency: Option<u32>,
+    pub nr_audio_channels: Option<u32>,
+    /// The language of the audio track, from a `language` parameter of the `protocolInfo`
+    /// or else a `dc:language` of the item, telling the audio tracks of a video apart.
+    pub language: Option<String>,
+}
+
+impl Resource {
+    /// Whether the resource is a thumbnail, per the `DLNA.ORG_PN` of its `protocolInfo`.
+    #[must_use]
+    pub fn is_thumbnail(&self) -> bool {
+        self.protocol_info
+            .split(':')
+            .nth(3)
+            .unwrap_or_default()
+            .split(';')
+            .any(|param| matches!(param.trim(), "DLNA.ORG_PN=JPEG_TN" | "DLNA.ORG_PN=PNG_TN"))
+    }
+
+    /// The MIME type of the resource, taken from its `protocolInfo`, without parameters.
+    #[must_use]
+    pub fn mime_type(&self) -> Option<&str> {
+        protocol_mime_type(&self.protocol_info)
+    }
+
+    /// A file extension for the resource, guessed from its MIME type and falling back to
+    /// the extension of its URL path.
+    #[must_use]
+    pub fn file_extension(&self) -> Option<&str> {
+        self.mime_type()
+            .and_then(mime_extension)
+            .or_else(|| url_extension(&self.url))
+    }
+}
+
+/// The MIME type of the content format of `protocol_info`, without parameters.
+fn protocol_mime_type(protocol_info: &str) -> Option<&str> {
+    protocol_info
+        .split(':')
+        .nth(2)
+        .and_then(|format| format.split(';').next())
+        .map(str::trim)
+        .filter(|mime| !mime.is_empty() && *mime != "*")
+}
+
+/// The top-level type of the MIME type of `protocol_info`, e.g. `audio` for `audio/mpeg`,
+/// in lowercase.
+pub(crate) fn protocol_media_type(protocol_info: &str) -> Option<String> {
+    protocol_mime_type(protocol_info)
+        .and_then(|mime| mime.split_once('/'))
+        .map(|(media_type, _)| media_type.to_ascii_lowercase())
+}
+
+fn mime_extension(mime: &str) -> Option<&'static str> {
+    let extension = match mime.to_ascii_lowercase().as_str() {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/x-m4a" => "m4a",
+        "audio/aac" | "audio/vnd.dlna.adts" => "aac",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/x-ms-wma" => "wma",
+        "audio/l16" => "pcm",
+        "video/mp4" => "mp4",
+        "video/x-matroska" => "mkv",
+        "video/mpeg" => "mpg",
+        "video/mp2t" | "video/vnd.dlna.mpeg-tts" => "ts",
+        "video/x-msvideo" | "video/avi" => "avi",
+        "video/quicktime" => "mov",
+        "video/webm" => "webm",
+        "video/x-ms-wmv" => "wmv",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/// The extension of the last segment of the path of `url`, ignoring its query.
+fn url_extension(url: &str) -> Option<&str> {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let path = url.split_once("://").map_or(Some(url), |(_, rest)| {
+        rest.find('/').map(|start| &rest[start..])
+    })?;
+    let (_, extension) = path.rsplit('/').next()?.rsplit_once('.')?;
+    Some(extension).filter(|extension| {
+        !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+/// A single page of `Browse` or `Search` results.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BrowseResult {
+    pub containers: Vec<Container>,
+    pub items: Vec<Item>,
+    pub number_returned: u32,
+    pub total_matches: u32,
+    pub update_id: Option<u32>,
+    /// The errors [`ParseMode::Lenient`](crate::parser::ParseMode) parsing got past.
+    pub warnings: Vec<String>,
+}
+
+impl From<&Item> for Metadata {
+    fn from(item: &Item) -> Self {
+        Self {
+            url: item.url.clone(),
+            title: item.title.clone(),
+            creator: item.creator.clone(),
+            artist: item.artist.clone(),
+            album: item.album.clone(),
+            album_art_uri: item.album_art_uri.clone(),
+            genre: item.genre.clone(),
+            protocol_info: item.protocol_info.clone(),
+            duration: item.duration_parsed(),
+        }
+    }
+}
+
+impl Item {
+    /// Whether the item is audio, per its `upnp:class` or else its MIME type.
+    #[must_use]
+    pub fn is_audio(&self) -> bool {
+        self.media_type().as_deref() == Some("audio")
+    }
+
+    /// Whether the item is a video, per its `upnp:class` or else its MIME type.
+    #[must_use]
+    pub fn is_video(&self) -> bool {
+        self.media_type().as_deref() == Some("video")
+    }
+
+    /// Whether the item is an image, per its `upnp:class` or else its MIME type.
+    #[must_use]
+    pub fn is_image(&self) -> bool {
+        self.media_type().as_deref() == Some("image")
+    }
+
+    /// `audio`, `video` or `image` as derived from the class of the item. Items of other
+    /// classes fall back to the MIME type of their `protocolInfo` or, as images have none,
+    /// of their first resource that isn't a thumbnail.
+    fn media_type(&self) -> Option<String> {
+        match self.object_class {
+            Some(ObjectClass::MusicTrack(_) | ObjectClass::AudioItem(_)) => {
+                Some("audio".to_string())
+            }
+            Some(ObjectClass::VideoItem(_)) => Some("video".to_string()),
+            Some(ObjectClass::Photo(_)) => Some("image".to_string()),
+            _ => protocol_media_type(&self.protocol_info).or_else(|| {
+                self.resources
+                    .iter()
+                    .filter(|resource| !resource.is_thumbnail())
+                    .find_map(|resource| protocol_media_type(&resource.protocol_info))
+            }),
+        }
+    }
+
+    /// The image to show for the item: its `upnp:albumArtURI`, or else a `JPEG_TN` or
+    /// `PNG_TN` thumbnail resource, or else its `upnp:icon`.
+    #[must_use]
+    pub fn thumbnail_uri(&self) -> Option<&str> {
+        self.album_art_uri
+            .as_deref()
+            .or_else(|| {
+                self.resources
+                    .iter()
+                    .find(|resource| resource.is_thumbnail())
+                    .map(|resource| resource.url.as_str())
+            })
+            .or(self.icon.as_deref())
+    }
+
+    /// The `res@duration` of the item, or `None` when it is unknown: missing,
+    /// `NOT_IMPLEMENTED`, malformed, or zero as advertised by live streams.
+    #[must_use]
+    pub fn duration_parsed(&self) -> Option<Duration> {
+        self.duration
+            .as_deref()
+            .and_then(parse_time)
+            .filter(|duration| !duration.is_zero())
+    }
+
+    /// The `dc:date` of the item as `YYYY-MM-DD`, whether the server sent a date alone or
+    /// a full datetime. See [`parse_date`] for the formats understood.
+    #[must_use]
+    pub fn date_parsed(&self) -> Option<String> {
+        self.date.as_deref().and_then(parse_date)
+    }
+}
+
+/// What a `ContentDirectory` `Browse` returns: the object itself or its children.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BrowseFlag {
+    #[default]
+    BrowseDirectChildren,
+    BrowseMetadata,
+}
+
+impl Display for BrowseFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BrowseDirectChildren => write!(f, "BrowseDirectChildren"),
+            Self::BrowseMetadata => write!(f, "BrowseMetadata"),
+        }
+    }
+}
+
+/// Where an `AVTransport` `Seek` goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SeekTarget {
+    /// A position in the current track, sent as `REL_TIME`.
+    RelTime(Duration),
+    /// A track of the current playlist, numbered from 1, sent as `TRACK_NR`.
+    TrackNr(u32),
+}
+
+/// The playback position reported by an `AVTransport` `GetPositionInfo`. Times are `None`
+/// when the renderer doesn't know them or reports `NOT_IMPLEMENTED`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PositionInfo {
+    pub track: u32,
+    /// `None` as well for the zero duration of live streams.
+    pub track_duration: Option<Duration>,
+    pub track_metadata: Option<Metadata>,
+    pub track_uri: String,
+    pub rel_time: Option<Duration>,
+    pub abs_time: Option<Duration>,
+}
+
+/// The media loaded on an `AVTransport`, as reported by `GetMediaInfo`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MediaInfo {
+    /// Number of tracks of the media, more than one for a playlist.
+    pub nr_tracks: u32,
+    /// `None` when unknown, including the zero duration of live streams.
+    pub media_duration: Option<Duration>,
+    pub current_uri: String,
+    pub current_uri_metadata: Option<Metadata>,
+    /// The current track, decoded from `CurrentURIMetaData`.
+    pub current_item: Option<Item>,
+    /// The track queued with `SetNextAVTransportURI`, empty when there is none.
+    pub next_uri: String,
+    /// The queued track, decoded from `NextURIMetaData`.
+    pub next_item: Option<Item>,
+}
+
+/// What an `AVTransport` can play from and record to, as reported by
+/// `GetDeviceCapabilities`. Values the renderer reports as `NOT_IMPLEMENTED` are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceCapabilities {
+    /// The storage media playback is possible from, such as `NETWORK`.
+    pub play_media: Vec<String>,
+    /// The storage media recording is possible to, empty for pure renderers.
+    pub rec_media: Vec<String>,
+    pub rec_quality_modes: Vec<String>,
+}
+
+/// The settings of an `AVTransport`, as reported by `GetTransportSettings`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TransportSettings {
+    /// Such as `NORMAL`, `SHUFFLE` or `REPEAT_ALL`.
+    pub play_mode: String,
+    /// `None` when the renderer doesn't record.
+    pub rec_quality_mode: Option<String>,
+}
+
+impl PositionInfo {
+    /// The position in the track: `rel_time`, or `abs_time` when the renderer doesn't
+    /// implement the former, as is common for live content.
+    #[must_use]
+    pub const fn effective_position(&self) -> Option<Duration> {
+        match self.rel_time {
+            Some(rel_time) => Some(rel_time),
+            None => self.abs_time,
+        }
+    }
+
+    /// The duration of the track: `track_duration`, or the `res@duration` of the track's
+    /// metadata when the renderer doesn't implement the former.
+    #[must_use]
+    pub fn effective_duration(&self) -> Option<Duration> {
+        self.track_duration.or_else(|| {
+            self.track_metadata
+                .as_ref()
+                .and_then(|metadata| metadata.duration)
+                .filter(|duration| !duration.is_zero())
+        })
+    }
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TransportInfo {
     pub current_transport_state: String,
     pub current_transport_status: String,
     pub current_speed: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    #[cfg(feature = "serde")]
+    use crate::parser::parse_description;
+    use crate::{
+        error::UpnpError,
+        parser::apply_scpd,
+        types::{Argument, Device, Item, ObjectClass, ProtocolInfoEntry, Resource, Service},
+    };
+
+    fn service(service_type: &str) -> Service {
+        Service {
+            service_type: service_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_item_media_predicates() {
+        let resource = |protocol_info: &str| Resource {
+            protocol_info: protocol_info.to_string(),
+            ..Default::default()
+        };
+        let track = Item {
+            object_class: Some(ObjectClass::from("object.item.audioItem.musicTrack")),
+            protocol_info: "http-get:*:audio/flac:*".to_string(),
+            ..Default::default()
+        };
+        assert!(track.is_audio());
+        assert!(!track.is_video());
+        assert!(!track.is_image());
+
+        let video = Item {
+            object_class: Some("object.item.videoItem.movie".into()),
+            protocol_info: "http-get:*:video/x-matroska:*".to_string(),
+            ..Default::default()
+        };
+        assert!(video.is_video());
+        assert!(!video.is_audio());
+
+        // Without a known class, the MIME type of the resources decides, thumbnails aside.
+        let photo = Item {
+            object_class: Some("object.item.vendorPicture".into()),
+            resources: vec![
+                resource("http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN"),
+                resource("http-get:*:IMAGE/JPEG:DLNA.ORG_PN=JPEG_LRG"),
+            ],
+            ..Default::default()
+        };
+        assert!(photo.is_image());
+        assert!(!photo.is_audio());
+        assert!(!photo.is_video());
+
+        assert!(!Item::default().is_audio());
+    }
+
+    #[test]
+    fn test_action_arguments() {
+        const SCPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0">
+            <actionList>
+                <action>
+                    <name>GetVolume</name>
+                    <argumentList>
+                        <argument>
+                            <name>InstanceID</name>
+                            <direction>in</direction>
+                            <relatedStateVariable>A_ARG_TYPE_InstanceID</relatedStateVariable>
+                        </argument>
+                        <argument>
+                            <name>CurrentVolume</name>
+                            <direction>out</direction>
+                            <relatedStateVariable>Volume</relatedStateVariable>
+                        </argument>
+                        <argument>
+                            <name>Channel</name>
+                            <direction>in</direction>
+                            <relatedStateVariable>A_ARG_TYPE_Channel</relatedStateVariable>
+                        </argument>
+                    </argumentList>
+                </action>
+                <action>
+                    <name>Stop</name>
+                </action>
+            </actionList>
+            <serviceStateTable></serviceStateTable>
+        </scpd>"#;
+
+        let mut service = Service::default();
+        apply_scpd(&mut service, SCPD).unwrap();
+        let names = |arguments: Vec<&Argument>| {
+            arguments
+                .into_iter()
+                .map(|argument| argument.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let get_volume = service.find_action("GetVolume").unwrap();
+        assert_eq!(
+            names(get_volume.in_arguments().collect()),
+            ["InstanceID", "Channel"]
+        );
+        assert_eq!(
+            names(get_volume.out_arguments().collect()),
+            ["CurrentVolume"]
+        );
+        let stop = service.find_action("Stop").unwrap();
+        assert_eq!(stop.in_arguments().count(), 0);
+        assert!(service.find_action("GetMute").is_none());
+    }
+
+    #[test]
+    fn test_find_service_ignores_version() {
+        let device = Device {
+            services: vec![service("urn:schemas-upnp-org:service:RenderingControl:1")],
+            devices: vec![Device {
+                services: vec![service("urn:schemas-upnp-org:service:AVTransport:2")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let av_transport = device
+            .find_service("urn:schemas-upnp-org:service:AVTransport:1")
+            .unwrap();
+        assert_eq!(
+            av_transport.service_type,
+            "urn:schemas-upnp-org:service:AVTransport:2"
+        );
+        assert!(device
+            .find_service("urn:schemas-upnp-org:service:renderingcontrol:3")
+            .is_some());
+        assert!(device.find_service("AVTransport").is_some());
+        assert!(device
+            .find_service("urn:schemas-sony-com:service:AVTransport:1")
+            .is_none());
+        assert!(device
+            .find_service("urn:schemas-upnp-org:service:ContentDirectory:1")
+            .is_none());
+    }
+
+    #[test]
+    fn test_service_is_type() {
+        let content_directory = service("urn:schemas-upnp-org:service:ContentDirectory:1");
+        assert!(content_directory.is_type("ContentDirectory"));
+        assert!(content_directory.is_type("contentdirectory"));
+        assert!(!content_directory.is_type("Directory"));
+        assert!(!content_directory.is_type("schemas-upnp-org"));
+        assert!(
+            service("urn:schemas-upnp-org:service:ContentDirectory:4").is_type("ContentDirectory")
+        );
+        assert!(service("urn:schemas-sony-com:service:IRCC:1").is_type("IRCC"));
+    }
+
+    #[test]
+    fn test_service_type_base_and_version() {
+        let av_transport = service("urn:schemas-upnp-org:service:AVTransport:2");
+        assert_eq!(
+            av_transport.service_type_base(),
+            "urn:schemas-upnp-org:service:AVTransport"
+        );
+        assert_eq!(av_transport.service_version(), Some(2));
+        assert_eq!(
+            service("urn:schemas-upnp-org:service:AVTransport:1").service_type_base(),
+            av_transport.service_type_base()
+        );
+
+        let vendor = service("urn:samsung.com:service:MainTVAgent2");
+        assert_eq!(
+            vendor.service_type_base(),
+            "urn:samsung.com:service:MainTVAgent2"
+        );
+        assert_eq!(vendor.service_version(), None);
+    }
+
+    #[test]
+    fn test_protocol_info_entry_from_str() {
+        let entry: ProtocolInfoEntry = "http-get:*:audio/flac:DLNA.ORG_OP=01".parse().unwrap();
+        assert_eq!(entry.protocol, "http-get");
+        assert_eq!(entry.content_format, "audio/flac");
+        assert_eq!(entry.additional_info, "DLNA.ORG_OP=01");
+        assert_eq!(entry.to_string(), "http-get:*:audio/flac:DLNA.ORG_OP=01");
+
+        let error = "http-get:*:audio/flac"
+            .parse::<ProtocolInfoEntry>()
+            .unwrap_err();
+        assert!(matches!(error, UpnpError::Other(_)));
+        assert_eq!(
+            error.to_string(),
+            "Invalid protocolInfo: http-get:*:audio/flac"
+        );
+    }
+
+    #[test]
+    fn test_object_class_from_str() {
+        let class = |value| ObjectClass::from(value);
+        assert!(matches!(
+            class("object.item.audioItem.musicTrack"),
+            ObjectClass::MusicTrack(_)
+        ));
+        assert!(matches!(
+            class("object.item.audioItem.audioBroadcast"),
+            ObjectClass::AudioItem(_)
+        ));
+        assert!(matches!(
+            class("object.item.videoItem.movie"),
+            ObjectClass::VideoItem(_)
+        ));
+        assert!(matches!(
+            class("object.item.imageItem.photo"),
+            ObjectClass::Photo(_)
+        ));
+        assert!(matches!(
+            class("object.container.storageFolder"),
+            ObjectClass::StorageFolder(_)
+        ));
+        assert!(matches!(
+            class("object.container.album.musicAlbum"),
+            ObjectClass::Album(_)
+        ));
+        assert!(matches!(
+            class("object.container.person.musicArtist"),
+            ObjectClass::Artist(_)
+        ));
+        assert_eq!(
+            class("object.item.audioItemExtension"),
+            ObjectClass::Unknown("object.item.audioItemExtension".to_string())
+        );
+
+        let unknown = class("object.item.epgItem.audioProgram");
+        assert_eq!(
+            unknown,
+            ObjectClass::Unknown("object.item.epgItem.audioProgram".to_string())
+        );
+        assert_eq!(unknown.value(), "object.item.epgItem.audioProgram");
+    }
+
+    #[test]
+    fn test_object_class_value_round_trips() {
+        for value in [
+            "object.item.audioItem.musicTrack",
+            "object.item.videoItem.movie",
+            "object.item.imageItem",
+            "object.item.imageItem.photo.vendorPhoto",
+            "object.container.album.musicAlbum",
+            "object.container.person.musicArtist",
+            "object.container.playlistContainer",
+        ] {
+            assert_eq!(ObjectClass::from(value).value(), value);
+        }
+    }
+
+    #[test]
+    fn test_device_identity_is_its_udn() {
+        let device = |udn: &str, location: &str| Device {
+            udn: udn.to_string(),
+            location: location.to_string(),
+            ..Default::default()
+        };
+        let wired = device(
+            "uuid:4d696e69-444c-164e",
+            "http://192.168.1.10:8200/rootDesc.xml",
+        );
+        let wireless = device(
+            "uuid:4d696e69-444c-164e",
+            "http://10.0.0.5:8200/rootDesc.xml",
+        );
+        let other = device(
+            "uuid:5d696e69-444c-164e",
+            "http://192.168.1.10:8200/rootDesc.xml",
+        );
+
+        assert_eq!(wired.id(), "4d696e69-444c-164e");
+        assert_eq!(wired, wireless);
+        assert_ne!(wired, other);
+        assert_eq!(HashSet::from([wired, wireless, other]).len(), 2);
+    }
+
+    #[test]
+    fn test_devices_without_udn_compare_by_location() {
+        let device = |udn: &str, location: &str| Device {
+            udn: udn.to_string(),
+            location: location.to_string(),
+            ..Default::default()
+        };
+        let first = device("", "http://192.168.1.10:49152/description.xml");
+        let second = device("", "http://192.168.1.11:49152/description.xml");
+        let again = device(" ", "http://192.168.1.10:49152/description.xml");
+        let named = device(
+            "uuid:4d696e69-444c-164e",
+            "http://192.168.1.10:49152/description.xml",
+        );
+
+        assert_ne!(first, second);
+        assert_eq!(first, again);
+        assert_ne!(first, named);
+        assert_eq!(HashSet::from([first, second, again, named]).len(), 3);
+    }
+
+    #[test]
+    fn test_device_host_and_origin() {
+        let device = |location: &str| Device {
+            location: location.to_string(),
+            ..Default::default()
+        };
+
+        let explicit_port = device("http://192.168.1.10:8200/rootDesc.xml");
+        assert_eq!(explicit_port.host().as_deref(), Some("192.168.1.10"));
+        assert_eq!(
+            explicit_port.origin().as_deref(),
+            Some("http://192.168.1.10:8200")
+        );
+
+        let default_port = device("http://nas.local:80/description.xml");
+        assert_eq!(default_port.host().as_deref(), Some("nas.local"));
+        assert_eq!(default_port.origin().as_deref(), Some("http://nas.local"));
+
+        let ipv6 = device("https://[fe80::1]/description.xml");
+        assert_eq!(ipv6.host().as_deref(), Some("[fe80::1]"));
+        assert_eq!(ipv6.origin().as_deref(), Some("https://[fe80::1]"));
+
+        assert_eq!(device("").host(), None);
+        assert_eq!(device("").origin(), None);
+    }
+
+    #[test]
+    fn test_device_has_device_type() {
+        let device = Device {
+            device_type: "urn:schemas-upnp-org:device:MediaServer:1".to_string(),
+            devices: vec![Device {
+                device_type: "urn:schemas-upnp-org:device:MediaRenderer:2".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(device.has_device_type("urn:schemas-upnp-org:device:MediaServer:1"));
+        assert!(device.has_device_type("urn:schemas-upnp-org:device:MediaRenderer:1"));
+        assert!(device.has_device_type("mediarenderer"));
+        assert!(!device.has_device_type("urn:schemas-upnp-org:device:InternetGatewayDevice:1"));
+    }
+
+    #[test]
+    fn test_device_has_service_type() {
+        let device = Device {
+            services: vec![service("urn:schemas-upnp-org:service:ConnectionManager:1")],
+            devices: vec![Device {
+                services: vec![service("urn:schemas-upnp-org:service:ContentDirectory:1")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(device.has_service_type("ConnectionManager"));
+        assert!(device.has_service_type("ContentDirectory"));
+        assert!(!device.has_service_type("AVTransport"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_device_serde_round_trip() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <specVersion><major>1</major><minor>0</minor></specVersion>
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaServer:1</deviceType>
+                <friendlyName>NAS</friendlyName>
+                <manufacturer>ACME</manufacturer>
+                <modelName>NAS-2000</modelName>
+                <UDN>uuid:nas</UDN>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+                        <SCPDURL>/cds.xml</SCPDURL>
+                        <controlURL>/control/cds</controlURL>
+                        <eventSubURL>/event/cds</eventSubURL>
+                    </service>
+                </serviceList>
+            </device>
+        </root>"#;
+        const SCPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0">
+            <actionList>
+                <action>
+                    <name>GetSystemUpdateID</name>
+                    <argumentList>
+                        <argument>
+                            <name>Id</name>
+                            <direction>out</direction>
+                            <relatedStateVariable>SystemUpdateID</relatedStateVariable>
+                        </argument>
+                    </argumentList>
+                </action>
+            </actionList>
+            <serviceStateTable>
+                <stateVariable sendEvents="yes">
+                    <name>SystemUpdateID</name>
+                    <dataType>ui4</dataType>
+                </stateVariable>
+            </serviceStateTable>
+        </scpd>"#;
+        let mut device =
+            parse_description("http://192.168.1.20:8080/description.xml", XML_ROOT).unwrap();
+        apply_scpd(&mut device.services[0], SCPD).unwrap();
+
+        let json = serde_json::to_string(&device).unwrap();
+        let decoded: Device = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.friendly_name, "NAS");
+        assert_eq!(decoded.udn, "uuid:nas");
+        let service = &decoded.services[0];
+        assert_eq!(service.control_url, "http://192.168.1.20:8080/control/cds");
+        assert_eq!(service.actions[0].name, "GetSystemUpdateID");
+        assert_eq!(service.state_variables[0].data_type, "ui4");
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    }
+}